@@ -0,0 +1,118 @@
+/*!
+异步的复制握手, 与同步版本的`Connect`一一对应
+*/
+
+use anyhow::{anyhow, Result};
+use redis::ToRedisArgs;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::aio::resp::AsyncRespDecode;
+use crate::connect::{parse_eof_mark, parse_rdb_length, psync_reply, NextStep, PsyncResp};
+use crate::resp::{Resp, Type};
+
+/// 只需写入的命令, 接收命令流时可以在独立的写端上发送
+pub(crate) trait AsyncCommand: AsyncWrite + Unpin + Send {
+    async fn send(&mut self, args: Vec<Vec<u8>>) -> Result<()> {
+        let result = redis::pack_command(&args);
+        self.write_all(&result).await?;
+        self.flush().await?;
+        Ok(())
+    }
+
+    async fn replconf_ack(&mut self, repl_offset: String) -> Result<()> {
+        let mut args = vec![];
+        args.extend("REPLCONF".to_redis_args());
+        args.extend("ACK".to_redis_args());
+        args.extend(repl_offset.to_redis_args());
+        self.send(args).await
+    }
+}
+
+pub(crate) trait AsyncConnect: AsyncRead + AsyncCommand {
+
+    async fn auth(&mut self, password: Option<String>, username: Option<String>) -> Result<()> {
+        let mut args = vec![];
+        args.extend("AUTH".to_redis_args());
+        if username.is_some() {
+            args.extend(username.to_redis_args());
+        }
+        args.extend(password.to_redis_args());
+        self.send(args).await?;
+        match self.decode_resp().await? {
+            Resp::String(_) => Ok(()),
+            Resp::Error(e) => Err(anyhow!("auth fail: {}", e)),
+            _ => Err(anyhow!("auth fail invalid err")),
+        }
+    }
+
+    async fn reply(&mut self) -> Result<()> {
+        match self.decode_resp().await? {
+            Resp::String(_) => {}
+            Resp::Error(err) => {
+                if (err.contains("NOAUTH") || err.contains("NOPERM"))
+                    && !err.contains("no password")
+                    && !err.contains("Unrecognized REPLCONF option")
+                {
+                    return Err(anyhow!("reply  err: {:?}", err));
+                }
+            }
+            _ => return Err(anyhow!("Unexpected response type")),
+        }
+        Ok(())
+    }
+
+    async fn replconf(&mut self, ip: String, port: u16) -> Result<()> {
+        self.send("PING".to_redis_args()).await?;
+        self.reply().await?;
+
+        let mut args = vec![];
+        args.extend("REPLCONF".to_redis_args());
+        args.extend("listening-port".to_redis_args());
+        args.extend(port.to_redis_args());
+        self.send(args).await?;
+        self.reply().await?;
+
+        if ip != "127.0.0.1" {
+            let mut args = vec![];
+            args.extend("REPLCONF".to_redis_args());
+            args.extend("ip-address".to_redis_args());
+            args.extend(ip.to_redis_args());
+            self.send(args).await?;
+            self.reply().await?;
+        }
+
+        let mut args = vec![];
+        args.extend("REPLCONF".to_redis_args());
+        args.extend("capa".to_redis_args());
+        args.extend("eof".to_redis_args());
+        args.extend("capa".to_redis_args());
+        args.extend("psync2".to_redis_args());
+        self.send(args).await?;
+        self.reply().await
+    }
+
+    async fn psync(&mut self, repl_id: String, repl_offset: String) -> Result<PsyncResp> {
+        let mut args = vec![];
+        args.extend("PSYNC".to_redis_args());
+        args.extend(repl_id.to_redis_args());
+        args.extend(repl_offset.to_redis_args());
+        self.send(args).await?;
+
+        let response = self.decode_resp().await.map_err(|err| err.context("decode_resp err"))?;
+        let mut psync_resp = psync_reply(response)?;
+        if psync_resp.next_step == NextStep::FullSync {
+            if let Type::BulkString = self.decode_type().await? {
+                let reply = self.decode_string().await?;
+                psync_resp.length = parse_rdb_length(&reply)?;
+                psync_resp.eof_mark = parse_eof_mark(&reply)?;
+            } else {
+                return Err(anyhow!("Expect BulkString response"));
+            }
+        }
+        Ok(psync_resp)
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send + ?Sized> AsyncCommand for W {}
+
+impl<R: AsyncRead + AsyncWrite + Unpin + Send + ?Sized> AsyncConnect for R {}
@@ -0,0 +1,57 @@
+use std::io::{Error, Result};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// 统计读取字节数的异步Reader, 用法与同步版本的`CountReader`一致
+pub(crate) struct AsyncCountReader<R> {
+    input: R,
+    len: i64,
+    marked: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncCountReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.input).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            if self.marked {
+                self.len += (buf.filled().len() - before) as i64;
+            }
+        }
+        poll
+    }
+}
+
+impl<R> AsyncCountReader<R> {
+    pub(crate) fn new(input: R) -> AsyncCountReader<R> {
+        AsyncCountReader {
+            input,
+            len: 0,
+            marked: false,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        &mut self.input
+    }
+
+    pub(crate) fn mark(&mut self) {
+        self.marked = true;
+    }
+
+    pub(crate) fn reset(&mut self) -> Result<i64> {
+        if self.marked {
+            let len = self.len;
+            self.len = 0;
+            self.marked = false;
+            return Ok(len);
+        }
+        Err(Error::other("not marked"))
+    }
+}
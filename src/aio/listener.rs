@@ -0,0 +1,518 @@
+/*!
+基于tokio的复制监听器
+
+与[Listener]的流程一致: 握手、PSYNC、解析RDB、接收命令, 断线后自动重连并尝试部分同步。
+[AsyncListener::start]返回的Future实现了`Send`, 可以直接交给`tokio::spawn`。
+`Config`中开启`is_tls_enabled`时使用TLS连接, 客户端证书与证书校验的配置与[Listener]相同。
+
+[Listener]: ../../listener/struct.Listener.html
+[AsyncListener::start]: struct.AsyncListener.html#method.start
+*/
+
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{info, warn};
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader, ReadHalf};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::aio::connect::{AsyncCommand, AsyncConnect};
+use crate::aio::io::AsyncCountReader;
+use crate::aio::rdb::AsyncRDBParser;
+use crate::aio::stream::AsyncStream;
+use crate::aio::resp::AsyncRespDecode;
+use crate::checkpoint::Checkpoint;
+use crate::cmd;
+use crate::config::Config;
+use crate::rdb::{check_eof_mark, ModuleRegistry, RDB_EOF_MARK_LEN};
+use crate::connect::NextStep;
+use crate::io::ProgressReader;
+use crate::listener::{
+    check_payload, command_args, is_getack, ProgressHandler, Replication, SyncEvent, HEARTBEAT_INTERVAL,
+};
+use crate::{Event, EventHandler, ModuleParser};
+
+/// 断线后重连的间隔
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 连接master并完成握手
+///
+/// 不借用监听器本身, 否则跨越await的`&AsyncListener`会要求handler实现`Sync`
+async fn connect(config: &Config) -> Result<BufReader<AsyncStream>> {
+    let stream = AsyncStream::connect(config).await?;
+    info!("connected to {}:{}", config.host, config.port);
+    let local_addr = stream.local_addr()?;
+    let mut stream = BufReader::new(stream);
+    if config.password.is_some() {
+        stream
+            .auth(config.password.clone(), config.username.clone())
+            .await?;
+    }
+    stream.replconf(local_addr.ip().to_string(), local_addr.port()).await?;
+    Ok(stream)
+}
+
+/// 按`interval`向master发送`REPLCONF ACK <offset>`, 从`getack`收到偏移量时立即应答, 只在写失败时返回
+async fn heartbeat<W: AsyncCommand>(
+    mut writer: W,
+    interval: Duration,
+    offset: &AtomicI64,
+    getack: &mut UnboundedReceiver<i64>,
+) -> anyhow::Error {
+    let mut ack = offset.load(Ordering::Relaxed);
+    loop {
+        if let Err(err) = writer.replconf_ack(ack.to_string()).await {
+            return err;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => ack = offset.load(Ordering::Relaxed),
+            Some(getack) = getack.recv() => ack = getack,
+        }
+    }
+}
+
+/// 从`input`中解析全量同步的RDB, 最多读取`length`字节, 并向handler报告传输进度
+async fn parse_rdb<R: AsyncRead + Unpin + Send>(
+    input: &mut R,
+    length: Option<u64>,
+    handler: &mut (dyn EventHandler + Send),
+    modules: &mut ModuleRegistry,
+    running: Arc<AtomicBool>,
+) -> Result<()> {
+    let read = Arc::new(AtomicU64::new(0));
+    let mut handler = ProgressHandler::new(handler, Arc::clone(&read), length);
+    let mut payload = ProgressReader::new(input.take(length.unwrap_or(u64::MAX)), read);
+    let result = payload.parse_with_modules(&mut handler, modules, Arc::clone(&running)).await;
+    let left = payload.get_ref().limit();
+    check_payload(result, length, left, running.load(Ordering::Relaxed))?;
+    handler.finish();
+    Ok(())
+}
+
+/// 在阻塞线程池中保存复制进度, 避免文件IO阻塞tokio的工作线程
+async fn save_checkpoint(replication: &mut Replication, force: bool) -> Result<()> {
+    if let Some((mut checkpoint, position)) = replication.checkpoint_due(force) {
+        let (checkpoint, result) = tokio::task::spawn_blocking(move || {
+            let result = checkpoint.save(&position);
+            (checkpoint, result)
+        })
+        .await?;
+        replication.checkpoint_saved(checkpoint);
+        result?;
+    }
+    Ok(())
+}
+
+/// 单机Redis的异步复制监听器
+pub struct AsyncListener {
+    pub config: Config,
+    replication: Replication,
+    event_handler: Box<dyn EventHandler + Send>,
+    modules: ModuleRegistry,
+    running: Arc<AtomicBool>,
+}
+
+impl AsyncListener {
+    /// 创建监听器
+    ///
+    /// 方法参数:
+    ///
+    /// * `config`: master的连接配置, `read_timeout`作用于接收每一条命令, 按`heartbeat_interval`发送ACK
+    /// * `event_handler`: 处理RDB数据与命令的handler
+    /// * `running`: 置为`false`后, 监听器在处理完当前数据后退出
+    pub fn new(
+        config: Config,
+        event_handler: Box<dyn EventHandler + Send>,
+        running: Arc<AtomicBool>,
+    ) -> AsyncListener {
+        AsyncListener {
+            config,
+            replication: Replication::new(),
+            event_handler,
+            modules: ModuleRegistry::new(),
+            running,
+        }
+    }
+
+    /// 设置复制进度的存储, `start`时会从中恢复复制进度
+    pub fn set_checkpoint(&mut self, checkpoint: Box<dyn Checkpoint + Send>) {
+        self.replication.set_checkpoint(checkpoint);
+    }
+
+    /// 注册`module_name`的解析器, 未注册的module以[RawModule]的形式交给handler
+    ///
+    /// [RawModule]: ../../rdb/struct.RawModule.html
+    pub fn register_module_parser(&mut self, module_name: &str, parser: Box<dyn ModuleParser + Send>) {
+        self.modules.register(module_name, parser);
+    }
+
+    /// master的复制ID
+    pub fn repl_id(&self) -> &str {
+        &self.replication.repl_id
+    }
+
+    /// 已处理完的复制偏移量
+    pub fn repl_offset(&self) -> i64 {
+        self.replication.repl_offset
+    }
+
+    /// 发送PSYNC, 如master要求全量同步, 则解析随后的RDB
+    async fn sync(&mut self, stream: &mut BufReader<AsyncStream>) -> Result<()> {
+        while self.running.load(Ordering::Relaxed) {
+            let offset = self.replication.psync_offset();
+            let resp = stream
+                .psync(self.replication.repl_id.clone(), offset.to_string())
+                .await?;
+            match resp.next_step {
+                NextStep::FullSync => {
+                    if resp.length != -1 {
+                        info!("full sync, size: {}bytes", resp.length);
+                    } else {
+                        info!("full sync, disk-less replication");
+                    }
+                    self.replication.invalidate();
+                    save_checkpoint(&mut self.replication, true).await?;
+
+                    self.event_handler.handle(Event::Sync(SyncEvent::FullResync {
+                        repl_id: &resp.repl_id,
+                        repl_offset: resp.repl_offset,
+                    }));
+                    let length = u64::try_from(resp.length).ok();
+                    parse_rdb(
+                        stream,
+                        length,
+                        &mut *self.event_handler,
+                        &mut self.modules,
+                        Arc::clone(&self.running),
+                    )
+                    .await?;
+                    if !self.running.load(Ordering::Relaxed) {
+                        // RDB未解析完整
+                        return Ok(());
+                    }
+                    if let Some(expected) = &resp.eof_mark {
+                        let mut mark = [0; RDB_EOF_MARK_LEN];
+                        stream.read_exact(&mut mark).await?;
+                        check_eof_mark(expected, &mark)?;
+                    }
+                    self.replication.full_synced(resp.repl_id, resp.repl_offset);
+                    save_checkpoint(&mut self.replication, true).await?;
+                    return Ok(());
+                }
+                NextStep::PartialResync => {
+                    if !resp.repl_id.is_empty() {
+                        self.replication.repl_id = resp.repl_id;
+                    }
+                    info!("partial resync from offset {}", offset);
+                    self.event_handler.handle(Event::Sync(SyncEvent::PartialResync {
+                        repl_id: &self.replication.repl_id,
+                        repl_offset: self.replication.repl_offset,
+                    }));
+                    return Ok(());
+                }
+                NextStep::Wait => tokio::time::sleep(Duration::from_secs(1)).await,
+            }
+        }
+        Ok(())
+    }
+
+    /// 持续接收master传播过来的命令, 同时定期向master发送ACK
+    async fn receive_aof(&mut self, stream: BufReader<AsyncStream>) -> Result<()> {
+        let (reader, writer) = tokio::io::split(stream);
+        let offset = AtomicI64::new(self.replication.repl_offset);
+        let (getack, mut getack_rx) = unbounded_channel();
+        let interval = self.config.heartbeat_interval.unwrap_or(HEARTBEAT_INTERVAL);
+        tokio::select! {
+            result = self.receive_commands(reader, &offset, getack) => result,
+            // 心跳只在写失败时结束, 此时连接已不可用
+            err = heartbeat(writer, interval, &offset, &mut getack_rx) => Err(err),
+        }
+    }
+
+    async fn receive_commands(
+        &mut self,
+        reader: ReadHalf<BufReader<AsyncStream>>,
+        offset: &AtomicI64,
+        getack: UnboundedSender<i64>,
+    ) -> Result<()> {
+        let mut reader = AsyncCountReader::new(reader);
+        while self.running.load(Ordering::Relaxed) {
+            reader.mark();
+            let resp = match self.config.read_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, reader.decode_resp())
+                    .await
+                    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "read timeout"))??,
+                None => reader.decode_resp().await?,
+            };
+            let size = reader.reset()?;
+            let args = command_args(resp)?;
+            if is_getack(&args) {
+                // 应答的偏移量不包括GETACK命令本身
+                let _ = getack.send(self.replication.repl_offset);
+            } else {
+                cmd::parse(args, &mut *self.event_handler, self.config.command_error_policy)?;
+            }
+            self.replication.repl_offset += size;
+            offset.store(self.replication.repl_offset, Ordering::Relaxed);
+            save_checkpoint(&mut self.replication, false).await?;
+        }
+        Ok(())
+    }
+
+    async fn run(&mut self) -> Result<()> {
+        let mut stream = connect(&self.config).await?;
+        self.sync(&mut stream).await?;
+        self.receive_aof(stream).await
+    }
+
+    /// 开始复制, 直到`running`被置为`false`或出现无法恢复的错误
+    pub async fn start(&mut self) -> io::Result<()> {
+        self.replication.load_checkpoint().map_err(io::Error::other)?;
+        loop {
+            let result = self.run().await;
+            // 已处理完的数据都已计入偏移量, 无论是否出错都保存一次
+            if let Err(err) = save_checkpoint(&mut self.replication, true).await {
+                warn!("save checkpoint err: {}", err);
+            }
+            let err = match result {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+            // 网络层的错误视为连接断开, 有复制进度时重连并尝试部分同步, 全量同步中断时重连并重新全量同步
+            match err.downcast::<io::Error>() {
+                Ok(err) if self.replication.reconnectable() && self.running.load(Ordering::Relaxed) => {
+                    warn!("connection lost: {}, reconnect in {:?}", err, RECONNECT_INTERVAL);
+                    tokio::time::sleep(RECONNECT_INTERVAL).await;
+                }
+                Ok(err) => return Err(err),
+                Err(err) => return Err(io::Error::other(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+    use std::net::{Shutdown, TcpListener};
+    use std::time::Duration;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use super::AsyncListener;
+    use crate::checkpoint::{Checkpoint, FileCheckpoint, Position};
+    use crate::listener::test::{
+        config, diskless_resync, fake_master, full_resync, handshake, pack, read_command, record, tls, EOF_MARK,
+        REPL_ID,
+    };
+    use crate::resp::RespDecode;
+
+    fn listener(tcp: &TcpListener, stop_at: &'static str) -> (AsyncListener, Arc<Mutex<Vec<String>>>) {
+        let (running, handler, events) = record(stop_at);
+        (AsyncListener::new(config(tcp), handler, running), events)
+    }
+
+    #[tokio::test]
+    async fn test_full_sync() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let set = pack(&["SET", "hello", "world"]);
+        let offset = 100 + set.len() as i64;
+        let (mut listener, events) = listener(&tcp, "SET(");
+        let master = fake_master(tcp, vec![set]);
+
+        let listener = tokio::spawn(async move {
+            listener.start().await.expect("listener err");
+            listener
+        })
+        .await
+        .unwrap();
+        assert_eq!(listener.repl_id(), REPL_ID);
+        assert_eq!(listener.repl_offset(), offset);
+        drop(listener);
+        master.join().unwrap();
+
+        let events = events.lock().unwrap();
+        assert!(events[0].contains("FullResync"));
+        assert!(events[1].contains("BOR"));
+        assert!(events.iter().any(|e| e.contains("String") && e.contains("[102, 111, 111]")));
+        assert!(events.last().unwrap().contains("SET"));
+    }
+
+    #[tokio::test]
+    async fn test_tls_full_sync() {
+        let dir = std::env::temp_dir().join(format!("redis-sync-aio-tls-{}", std::process::id()));
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (mut listener, events) = listener(&tcp, "SET(");
+        let acceptor = tls(&dir, &mut listener.config);
+        let master = thread::spawn(move || {
+            let (stream, _) = tcp.accept().unwrap();
+            let mut stream = acceptor.accept(stream).unwrap();
+            handshake(&mut stream);
+            full_resync(&mut stream);
+            stream.write_all(&pack(&["SET", "hello", "world"])).unwrap();
+            while stream.decode_resp().is_ok() {}
+        });
+
+        let listener = tokio::spawn(async move {
+            listener.start().await.expect("listener err");
+            listener
+        })
+        .await
+        .unwrap();
+        assert_eq!(listener.repl_id(), REPL_ID);
+        drop(listener);
+        master.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let events = events.lock().unwrap();
+        assert!(events.iter().any(|e| e.contains("String") && e.contains("[102, 111, 111]")));
+        assert!(events.last().unwrap().contains("SET"));
+    }
+
+    #[tokio::test]
+    async fn test_partial_resync() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (mut listener, events) = listener(&tcp, "DEL(");
+        let set = pack(&["SET", "hello", "world"]);
+        let next_offset = 100 + set.len() as i64 + 1;
+        let master = thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            handshake(&mut stream);
+            full_resync(&mut stream);
+            stream.write_all(&set).unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            while stream.decode_resp().is_ok() {}
+
+            let (mut stream, _) = tcp.accept().unwrap();
+            let psync = handshake(&mut stream);
+            stream.write_all(format!("+CONTINUE {}\r\n", REPL_ID).as_bytes()).unwrap();
+            stream.write_all(&pack(&["DEL", "hello"])).unwrap();
+            while stream.decode_resp().is_ok() {}
+            psync
+        });
+
+        listener.start().await.expect("listener err");
+        drop(listener);
+        let psync = master.join().unwrap();
+        assert_eq!(psync, vec!["PSYNC".to_string(), REPL_ID.to_string(), next_offset.to_string()]);
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.iter().filter(|e| e.contains("FullResync")).count(), 1);
+        assert!(events.iter().any(|e| e.contains("PartialResync")));
+        assert!(events.last().unwrap().contains("DEL"));
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_checkpoint() {
+        let path = std::env::temp_dir().join(format!("redis-sync-aio-listener-{}", std::process::id()));
+        let mut checkpoint = FileCheckpoint::new(&path);
+        checkpoint
+            .save(&Position {
+                repl_id: REPL_ID.to_string(),
+                repl_offset: 500,
+            })
+            .unwrap();
+
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (mut listener, _) = listener(&tcp, "DEL(");
+        let del = pack(&["DEL", "hello"]);
+        let offset = 500 + del.len() as i64;
+        let master = thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            let psync = handshake(&mut stream);
+            stream.write_all(format!("+CONTINUE {}\r\n", REPL_ID).as_bytes()).unwrap();
+            stream.write_all(&del).unwrap();
+            while stream.decode_resp().is_ok() {}
+            psync
+        });
+
+        listener.set_checkpoint(Box::new(FileCheckpoint::new(&path)));
+        listener.start().await.expect("listener err");
+        drop(listener);
+        let psync = master.join().unwrap();
+        assert_eq!(psync, vec!["PSYNC".to_string(), REPL_ID.to_string(), "501".to_string()]);
+        assert_eq!(checkpoint.load().unwrap().unwrap().repl_offset, offset);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (mut listener, _) = listener(&tcp, "DEL(");
+        listener.config.heartbeat_interval = Some(Duration::from_millis(50));
+        listener.config.read_timeout = Some(Duration::from_millis(300));
+        let master = thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            handshake(&mut stream);
+            full_resync(&mut stream);
+            let ack = read_command(&mut stream);
+            let mut acks = 1;
+            while stream.decode_resp().is_ok() {
+                acks += 1;
+            }
+
+            let (mut stream, _) = tcp.accept().unwrap();
+            let psync = handshake(&mut stream);
+            stream.write_all(format!("+CONTINUE {}\r\n", REPL_ID).as_bytes()).unwrap();
+            stream.write_all(&pack(&["DEL", "hello"])).unwrap();
+            while stream.decode_resp().is_ok() {}
+            (ack, acks, psync)
+        });
+
+        listener.start().await.expect("listener err");
+        drop(listener);
+        let (ack, acks, psync) = master.join().unwrap();
+        assert_eq!(ack, vec!["REPLCONF", "ACK", "100"]);
+        assert!(acks > 1);
+        assert_eq!(psync, vec!["PSYNC".to_string(), REPL_ID.to_string(), "101".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_getack() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (mut listener, events) = listener(&tcp, "DEL(");
+        listener.config.heartbeat_interval = Some(Duration::from_secs(60));
+        let set = pack(&["SET", "hello", "world"]);
+        let getack = pack(&["REPLCONF", "GETACK", "*"]);
+        let offset = 100 + set.len() as i64;
+        let master = thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            handshake(&mut stream);
+            full_resync(&mut stream);
+            assert_eq!(read_command(&mut stream), vec!["REPLCONF", "ACK", "100"]);
+            stream.write_all(&set).unwrap();
+            stream.write_all(&getack).unwrap();
+            let ack = read_command(&mut stream);
+            stream.write_all(&pack(&["DEL", "hello"])).unwrap();
+            while stream.decode_resp().is_ok() {}
+            ack
+        });
+
+        listener.start().await.expect("listener err");
+        drop(listener);
+        let ack = master.join().unwrap();
+        assert_eq!(ack, vec!["REPLCONF".to_string(), "ACK".to_string(), offset.to_string()]);
+        assert!(!events.lock().unwrap().iter().any(|e| e.contains("REPLCONF")));
+    }
+
+    #[tokio::test]
+    async fn test_diskless_sync() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (mut listener, events) = listener(&tcp, "SET(");
+        let master = thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            handshake(&mut stream);
+            diskless_resync(&mut stream, EOF_MARK);
+            stream.write_all(&pack(&["SET", "hello", "world"])).unwrap();
+            while stream.decode_resp().is_ok() {}
+        });
+
+        listener.start().await.expect("listener err");
+        drop(listener);
+        master.join().unwrap();
+        assert!(events.lock().unwrap().last().unwrap().contains("SET"));
+    }
+}
@@ -0,0 +1,22 @@
+/*!
+基于tokio的异步复制
+
+与同步版本的[Listener]功能一致, 但网络读写都基于`tokio::io::AsyncRead`/`AsyncWrite`,
+一个tokio运行时即可同时驱动大量master的复制, 无需为每个master占用一个线程。
+
+此模块包括:
+- 异步的RESP解析与复制握手, 支持明文TCP与TLS连接
+- 异步的RDB解析, 见[AsyncRDBParser]
+- 异步的复制监听器, 见[AsyncListener]
+
+[Listener]: ../listener/struct.Listener.html
+[AsyncRDBParser]: rdb/trait.AsyncRDBParser.html
+[AsyncListener]: listener/struct.AsyncListener.html
+*/
+
+mod connect;
+mod io;
+pub mod listener;
+pub mod rdb;
+mod resp;
+mod stream;
@@ -0,0 +1,993 @@
+/*!
+异步的RDB解析
+
+RDB中的每条记录先按照其格式从网络中异步读取完整, 再交给同步的[RDBParser]解码,
+解码规则只维护一份, 异步解析仅负责确定每条记录的边界。
+因此单条记录(比如一个很大的key)会完整地缓存在内存中。
+
+[RDBParser]: ../../rdb/trait.RDBParser.html
+*/
+
+use std::future::Future;
+use std::io::{Cursor, Result};
+use std::result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::crc64::crc64;
+use crate::error::RedisSyncError;
+use crate::rdb::*;
+use crate::{Event, EventHandler};
+
+pub trait AsyncRDBParser: AsyncRead + Unpin + Send {
+    /// 解析RDB, 与[RDBParser::parse]产生相同的事件
+    ///
+    /// [RDBParser::parse]: ../../rdb/trait.RDBParser.html#method.parse
+    fn parse<'a>(
+        &'a mut self,
+        event_handler: &'a mut (dyn EventHandler + Send),
+        running: Arc<AtomicBool>,
+    ) -> impl Future<Output = result::Result<(), RedisSyncError>> + Send + 'a {
+        async move {
+            let mut modules = ModuleRegistry::new();
+            self.parse_with_modules(event_handler, &mut modules, running).await
+        }
+    }
+
+    /// 同[parse], module类型的值交由`modules`中注册的解析器解析
+    ///
+    /// 记录的边界按module的opcode确定, 因此不支持没有opcode的`RDB_TYPE_MODULE`
+    ///
+    /// [parse]: #method.parse
+    fn parse_with_modules<'a>(
+        &'a mut self,
+        event_handler: &'a mut (dyn EventHandler + Send),
+        modules: &'a mut ModuleRegistry,
+        running: Arc<AtomicBool>,
+    ) -> impl Future<Output = result::Result<(), RedisSyncError>> + Send + 'a {
+        async move {
+            event_handler.handle(Event::RDB(Object::BOR));
+            // 开头5个字节: REDIS, 随后4个字节: rdb版本
+            let mut bytes = [0; 9];
+            self.read_exact(&mut bytes).await?;
+            let rdb_version = parse_rdb_version(&bytes[5..])?;
+            let mut db = 0;
+            let mut offset = bytes.len() as u64;
+            let mut crc = crc64(0, &bytes);
+
+            while running.load(Ordering::Relaxed) {
+                let data_type = self.read_u8().await?;
+                let mut capture = Capture {
+                    input: &mut *self,
+                    buf: Vec::new(),
+                };
+                if let Err(err) = capture.entry(data_type).await {
+                    return Err(rdb_error(err, Some(data_type), offset));
+                }
+                let len = capture.buf.len() as u64;
+                crc = crc64(crc64(crc, &[data_type]), &capture.buf);
+                let mut cursor = Cursor::new(capture.buf);
+                match cursor.parse_entry(data_type, rdb_version, &mut db, &mut *event_handler, modules) {
+                    Ok(true) => offset += 1 + len,
+                    Ok(false) => {
+                        if rdb_version >= 5 {
+                            let expected = self.read_u64_le().await?;
+                            verify_checksum(expected, crc)?;
+                        }
+                        break;
+                    }
+                    Err(err) => return Err(rdb_error(err, Some(data_type), offset)),
+                }
+            }
+            event_handler.handle(Event::RDB(Object::EOR));
+            Ok(())
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin + Send + ?Sized> AsyncRDBParser for R {}
+
+/// 按RDB的格式读取一条记录, 并将读到的字节原样保存下来
+struct Capture<'a, R: ?Sized> {
+    input: &'a mut R,
+    buf: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin + Send + ?Sized> Capture<'_, R> {
+    async fn read_u8(&mut self) -> Result<u8> {
+        let byte = self.input.read_u8().await?;
+        self.buf.push(byte);
+        Ok(byte)
+    }
+
+    /// `length`来自输入, 不可信, 因此分段读取, 缓存只随实际读到的数据增长
+    async fn skip(&mut self, mut length: usize) -> Result<()> {
+        while length > 0 {
+            let chunk = length.min(READ_CHUNK_SIZE);
+            let start = self.buf.len();
+            self.buf.resize(start + chunk, 0);
+            self.input.read_exact(&mut self.buf[start..]).await?;
+            length -= chunk;
+        }
+        Ok(())
+    }
+
+    async fn read_length(&mut self) -> Result<(isize, bool)> {
+        let byte = self.read_u8().await?;
+        let _type = (byte & 0xC0) >> 6;
+        if _type == RDB_ENCVAL {
+            Ok(((byte & 0x3F) as isize, true))
+        } else if _type == RDB_6BITLEN {
+            Ok(((byte & 0x3F) as isize, false))
+        } else if _type == RDB_14BITLEN {
+            let next_byte = self.read_u8().await?;
+            Ok(((((byte as u16 & 0x3F) << 8) | next_byte as u16) as isize, false))
+        } else if byte == RDB_32BITLEN || byte == RDB_64BITLEN {
+            let size = if byte == RDB_32BITLEN { 4 } else { 8 };
+            let mut length = 0isize;
+            for _ in 0..size {
+                length = (length << 8) | self.read_u8().await? as isize;
+            }
+            Ok((length, false))
+        } else {
+            Err(malformed(format!("invalid length encoding: {:#04x}", byte)))
+        }
+    }
+
+    /// 读取一个长度或元素个数, 与`RDBDecode::read_len`对应
+    async fn read_len(&mut self) -> Result<usize> {
+        match self.read_length().await? {
+            (length, false) if length >= 0 => Ok(length as usize),
+            (length, _) => Err(malformed(format!("invalid length: {}", length))),
+        }
+    }
+
+    async fn read_string(&mut self) -> Result<()> {
+        let (length, is_encoded) = self.read_length().await?;
+        if is_encoded {
+            match length {
+                RDB_ENC_INT8 => self.skip(1).await,
+                RDB_ENC_INT16 => self.skip(2).await,
+                RDB_ENC_INT32 => self.skip(4).await,
+                RDB_ENC_LZF => {
+                    let compressed_len = self.read_len().await?;
+                    self.read_len().await?;
+                    self.skip(compressed_len).await
+                }
+                _ => Err(malformed(format!("Invalid string length: {}", length))),
+            }
+        } else if length < 0 {
+            Err(malformed(format!("Invalid string length: {}", length)))
+        } else {
+            self.skip(length as usize).await
+        }
+    }
+
+    async fn read_double(&mut self) -> Result<()> {
+        let len = self.read_u8().await?;
+        match len {
+            253..=255 => Ok(()),
+            _ => self.skip(len as usize).await,
+        }
+    }
+
+    async fn read_strings(&mut self, count: usize) -> Result<()> {
+        for _ in 0..count {
+            self.read_string().await?;
+        }
+        Ok(())
+    }
+
+    /// 读取`data_type`之后的一条记录, 与`RDBParser::parse_entry`对应
+    async fn entry(&mut self, data_type: u8) -> Result<()> {
+        match data_type {
+            RDB_OPCODE_AUX => self.read_strings(2).await,
+            RDB_OPCODE_SELECTDB => self.read_length().await.map(|_| ()),
+            RDB_OPCODE_RESIZEDB => {
+                self.read_length().await?;
+                self.read_length().await.map(|_| ())
+            }
+            RDB_OPCODE_EXPIRETIME | RDB_OPCODE_EXPIRETIME_MS => {
+                self.skip(if data_type == RDB_OPCODE_EXPIRETIME_MS { 8 } else { 4 }).await?;
+                let value_type = self.read_u8().await?;
+                self.evict(value_type).await
+            }
+            RDB_OPCODE_FREQ | RDB_OPCODE_IDLE => self.evict(data_type).await,
+            RDB_OPCODE_MODULE_AUX => {
+                self.read_length().await?;
+                self.module_value().await
+            }
+            RDB_OPCODE_FUNCTION2 => self.read_string().await,
+            RDB_OPCODE_FUNCTION_PRE_GA => {
+                self.read_strings(2).await?;
+                let (has_desc, _) = self.read_length().await?;
+                self.read_strings(if has_desc != 0 { 2 } else { 1 }).await
+            }
+            RDB_OPCODE_SLOT_INFO => {
+                self.read_length().await?;
+                self.read_length().await?;
+                self.read_length().await.map(|_| ())
+            }
+            RDB_OPCODE_EOF => Ok(()),
+            _ if is_value_type(data_type) => self.object(data_type).await,
+            // 未知的opcode由RDBParser::parse_entry报错
+            _ => Ok(()),
+        }
+    }
+
+    async fn evict(&mut self, value_type: u8) -> Result<()> {
+        match value_type {
+            RDB_OPCODE_FREQ => self.skip(1).await?,
+            RDB_OPCODE_IDLE => {
+                self.read_length().await?;
+            }
+            _ => return self.object(value_type).await,
+        }
+        let value_type = self.read_u8().await?;
+        self.object(value_type).await
+    }
+
+    async fn object(&mut self, value_type: u8) -> Result<()> {
+        // key
+        self.read_string().await?;
+        match value_type {
+            RDB_TYPE_STRING => self.read_string().await,
+            RDB_TYPE_LIST | RDB_TYPE_SET | RDB_TYPE_LIST_QUICKLIST => {
+                let count = self.read_len().await?;
+                self.read_strings(count).await
+            }
+            RDB_TYPE_HASH => {
+                let count = self.read_len().await?;
+                for _ in 0..count {
+                    self.read_strings(2).await?;
+                }
+                Ok(())
+            }
+            RDB_TYPE_ZSET | RDB_TYPE_ZSET_2 => {
+                let count = self.read_len().await?;
+                for _ in 0..count {
+                    self.read_string().await?;
+                    if value_type == RDB_TYPE_ZSET_2 {
+                        self.skip(8).await?;
+                    } else {
+                        self.read_double().await?;
+                    }
+                }
+                Ok(())
+            }
+            RDB_TYPE_LIST_QUICKLIST_2 => {
+                let count = self.read_len().await?;
+                for _ in 0..count {
+                    // container
+                    self.read_length().await?;
+                    self.read_string().await?;
+                }
+                Ok(())
+            }
+            RDB_TYPE_HASH_ZIPMAP
+            | RDB_TYPE_LIST_ZIPLIST
+            | RDB_TYPE_SET_INTSET
+            | RDB_TYPE_ZSET_ZIPLIST
+            | RDB_TYPE_HASH_ZIPLIST
+            | RDB_TYPE_HASH_LISTPACK
+            | RDB_TYPE_ZSET_LISTPACK
+            | RDB_TYPE_SET_LISTPACK => self.read_string().await,
+            RDB_TYPE_HASH_METADATA | RDB_TYPE_HASH_METADATA_PRE_GA => {
+                if value_type == RDB_TYPE_HASH_METADATA {
+                    self.skip(8).await?;
+                }
+                let count = self.read_len().await?;
+                for _ in 0..count {
+                    // 过期时间, 字段名, 字段值
+                    self.read_length().await?;
+                    self.read_strings(2).await?;
+                }
+                Ok(())
+            }
+            RDB_TYPE_HASH_LISTPACK_EX | RDB_TYPE_HASH_LISTPACK_EX_PRE_GA => {
+                if value_type == RDB_TYPE_HASH_LISTPACK_EX {
+                    self.skip(8).await?;
+                }
+                self.read_string().await
+            }
+            RDB_TYPE_STREAM_LISTPACKS | RDB_TYPE_STREAM_LISTPACKS_2 | RDB_TYPE_STREAM_LISTPACKS_3 => {
+                self.stream(value_type).await
+            }
+            RDB_TYPE_MODULE_2 => {
+                self.read_length().await?;
+                self.module_value().await
+            }
+            _ => Err(malformed(format!("unknown value type: {}", value_type))),
+        }
+    }
+
+    async fn stream(&mut self, value_type: u8) -> Result<()> {
+        let length = self.read_len().await?;
+        // 每个listpack节点: 起始ID与listpack
+        for _ in 0..length {
+            self.read_strings(2).await?;
+        }
+        // 长度与last_id
+        for _ in 0..3 {
+            self.read_length().await?;
+        }
+        if value_type >= RDB_TYPE_STREAM_LISTPACKS_2 {
+            // first_id、max_deleted_id与added_entries_count
+            for _ in 0..5 {
+                self.read_length().await?;
+            }
+        }
+        let groups = self.read_len().await?;
+        for _ in 0..groups {
+            self.read_string().await?;
+            self.read_length().await?;
+            self.read_length().await?;
+            if value_type >= RDB_TYPE_STREAM_LISTPACKS_2 {
+                self.read_length().await?;
+            }
+            let pel = self.read_len().await?;
+            for _ in 0..pel {
+                self.skip(16 + 8).await?;
+                self.read_length().await?;
+            }
+            let consumers = self.read_len().await?;
+            for _ in 0..consumers {
+                self.read_string().await?;
+                self.skip(8).await?;
+                if value_type >= RDB_TYPE_STREAM_LISTPACKS_3 {
+                    self.skip(8).await?;
+                }
+                let pel = self.read_len().await?;
+                let length = pel.checked_mul(16).ok_or_else(|| malformed(format!("invalid length: {}", pel)))?;
+                self.skip(length).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn module_value(&mut self) -> Result<()> {
+        loop {
+            let (op_code, _) = self.read_length().await?;
+            match op_code {
+                RDB_MODULE_OPCODE_EOF => return Ok(()),
+                RDB_MODULE_OPCODE_SINT | RDB_MODULE_OPCODE_UINT => {
+                    self.read_length().await?;
+                }
+                RDB_MODULE_OPCODE_STRING => self.read_string().await?,
+                RDB_MODULE_OPCODE_FLOAT => self.skip(4).await?,
+                RDB_MODULE_OPCODE_DOUBLE => self.skip(8).await?,
+                _ => return Err(malformed(format!("unknown module opcode: {}", op_code))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::any::Any;
+    use std::io::{Cursor, Read};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use super::AsyncRDBParser;
+    use crate::crc64::crc64;
+    use crate::error::RedisSyncError;
+    use crate::rdb::{Module, ModuleRegistry, Object, RDBDecode, RDBParser, RawModule, MODULE_SET};
+    use crate::{Event, EventHandler, ModuleParser};
+
+    #[derive(Default)]
+    struct Record {
+        events: Vec<String>,
+    }
+
+    impl EventHandler for Record {
+        fn handle(&mut self, event: Event) {
+            self.events.push(format!("{:?}", event));
+        }
+    }
+
+    fn string(buf: &mut Vec<u8>, s: &str) {
+        buf.push(s.len() as u8);
+        buf.extend_from_slice(s.as_bytes());
+    }
+
+    fn rdb() -> Vec<u8> {
+        let mut rdb = b"REDIS0009".to_vec();
+        // aux
+        rdb.push(0xFA);
+        string(&mut rdb, "redis-ver");
+        string(&mut rdb, "7.0.0");
+        rdb.extend_from_slice(&[0xFE, 0x00, 0xFB, 4, 1]);
+        // string, 值为int8编码
+        rdb.push(0x00);
+        string(&mut rdb, "num");
+        rdb.extend_from_slice(&[0xC0, 42]);
+        // 带过期时间的list
+        rdb.push(0xFC);
+        rdb.extend_from_slice(&1_700_000_000_000i64.to_le_bytes());
+        rdb.push(0x01);
+        string(&mut rdb, "list");
+        rdb.push(2);
+        string(&mut rdb, "a");
+        string(&mut rdb, "b");
+        // hash
+        rdb.push(0x04);
+        string(&mut rdb, "hash");
+        rdb.push(1);
+        string(&mut rdb, "field");
+        string(&mut rdb, "value");
+        // zset_2
+        rdb.push(0x05);
+        string(&mut rdb, "zset");
+        rdb.push(1);
+        string(&mut rdb, "member");
+        rdb.extend_from_slice(&1.5f64.to_le_bytes());
+        rdb.push(0xFF);
+        rdb.extend_from_slice(&[0; 8]);
+        rdb
+    }
+
+    #[tokio::test]
+    async fn test_parse_same_as_sync() {
+        let rdb = rdb();
+        let mut expected = Record::default();
+        RDBParser::parse(&mut Cursor::new(&rdb), &mut expected, Arc::new(AtomicBool::new(true))).unwrap();
+
+        let mut actual = Record::default();
+        AsyncRDBParser::parse(&mut Cursor::new(&rdb), &mut actual, Arc::new(AtomicBool::new(true)))
+            .await
+            .unwrap();
+        assert_eq!(actual.events, expected.events);
+        // BOR, AUX, SELECT, RESIZEDB, 4条数据, EOR
+        assert_eq!(actual.events.len(), 9);
+    }
+
+    #[derive(Default)]
+    struct Hints {
+        aux: Vec<(String, String)>,
+        repl_offset: Option<i64>,
+        resize: Vec<(isize, usize, usize)>,
+    }
+
+    impl EventHandler for Hints {
+        fn handle(&mut self, event: Event) {
+            match event {
+                Event::RDB(Object::Aux(aux)) => {
+                    self.repl_offset = self.repl_offset.or(aux.repl_offset());
+                    let field = String::from_utf8_lossy(aux.field).to_string();
+                    self.aux.push((field, String::from_utf8_lossy(aux.value).to_string()));
+                }
+                Event::RDB(Object::ResizeDB(resize)) => self.resize.push((resize.db, resize.total, resize.expires)),
+                _ => {}
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_aux_and_resize_db() {
+        let mut rdb = b"REDIS0009".to_vec();
+        for (field, value) in [("redis-ver", "7.2.4"), ("repl-id", "8f4d2a"), ("repl-offset", "1024")] {
+            rdb.push(0xFA);
+            string(&mut rdb, field);
+            string(&mut rdb, value);
+        }
+        // ctime, 值为int32编码
+        rdb.push(0xFA);
+        string(&mut rdb, "ctime");
+        rdb.push(0xC2);
+        rdb.extend_from_slice(&1_700_000_000i32.to_le_bytes());
+        rdb.extend_from_slice(&[0xFE, 0x02, 0xFB, 3, 1]);
+        rdb.push(0xFF);
+        rdb.extend_from_slice(&[0; 8]);
+
+        let mut expected = Hints::default();
+        RDBParser::parse(&mut Cursor::new(&rdb), &mut expected, Arc::new(AtomicBool::new(true))).unwrap();
+        let mut actual = Hints::default();
+        AsyncRDBParser::parse(&mut Cursor::new(&rdb), &mut actual, Arc::new(AtomicBool::new(true)))
+            .await
+            .unwrap();
+        for hints in [&expected, &actual] {
+            assert_eq!(hints.aux[0], ("redis-ver".to_string(), "7.2.4".to_string()));
+            assert_eq!(hints.aux[3], ("ctime".to_string(), "1700000000".to_string()));
+            assert_eq!(hints.aux.len(), 4);
+            assert_eq!(hints.repl_offset, Some(1024));
+            assert_eq!(hints.resize, vec![(2, 3, 1)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_checksum() {
+        let mut rdb = rdb();
+        let len = rdb.len();
+        let crc = crc64(0, &rdb[..len - 8]);
+        rdb[len - 8..].copy_from_slice(&crc.to_le_bytes());
+        let mut handler = Record::default();
+        RDBParser::parse(&mut Cursor::new(&rdb), &mut handler, Arc::new(AtomicBool::new(true))).unwrap();
+        AsyncRDBParser::parse(&mut Cursor::new(&rdb), &mut handler, Arc::new(AtomicBool::new(true)))
+            .await
+            .unwrap();
+
+        // 翻转list中的一个字节, 格式仍然合法, 只能通过checksum发现
+        let index = rdb.windows(2).position(|w| w == [1, b'a']).unwrap() + 1;
+        rdb[index] = b'c';
+        let err = RDBParser::parse(&mut Cursor::new(&rdb), &mut handler, Arc::new(AtomicBool::new(true))).unwrap_err();
+        assert!(matches!(err, RedisSyncError::Checksum { expected, .. } if expected == crc));
+        let err = AsyncRDBParser::parse(&mut Cursor::new(&rdb), &mut handler, Arc::new(AtomicBool::new(true)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RedisSyncError::Checksum { expected, .. } if expected == crc));
+    }
+
+    #[tokio::test]
+    async fn test_parse_truncated() {
+        let rdb = rdb();
+        let mut handler = Record::default();
+        let mut input = Cursor::new(&rdb[..rdb.len() - 20]);
+        assert!(AsyncRDBParser::parse(&mut input, &mut handler, Arc::new(AtomicBool::new(true)))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_parse_set_listpack() {
+        let mut list_pack = vec![0; 4];
+        list_pack.extend_from_slice(&71u16.to_le_bytes());
+        // 7位无符号整数
+        list_pack.extend_from_slice(&[0x05, 0x01]);
+        for i in 0..70 {
+            let member = format!("m{:02}", i);
+            list_pack.push(0x80 | member.len() as u8);
+            list_pack.extend_from_slice(member.as_bytes());
+            list_pack.push(1 + member.len() as u8);
+        }
+        list_pack.push(0xFF);
+        let total = list_pack.len() as u32;
+        list_pack[..4].copy_from_slice(&total.to_le_bytes());
+
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.extend_from_slice(&[0xFE, 0x00, 20]);
+        string(&mut rdb, "set");
+        rdb.push(0x40 | (list_pack.len() >> 8) as u8);
+        rdb.push(list_pack.len() as u8);
+        rdb.extend_from_slice(&list_pack);
+        rdb.push(0xFF);
+        rdb.extend_from_slice(&[0; 8]);
+
+        let mut expected = Record::default();
+        RDBParser::parse(&mut Cursor::new(&rdb), &mut expected, Arc::new(AtomicBool::new(true))).unwrap();
+        let mut actual = Record::default();
+        AsyncRDBParser::parse(&mut Cursor::new(&rdb), &mut actual, Arc::new(AtomicBool::new(true)))
+            .await
+            .unwrap();
+        assert_eq!(actual.events, expected.events);
+        // BOR, SELECT, 64个元素, 7个元素, EOR
+        assert_eq!(expected.events.len(), 5);
+        assert!(expected.events[2].contains("members: [[53], [109, 48, 48]"));
+        assert!(expected.events[3].contains("[109, 54, 57]]"));
+    }
+
+    fn stream_id(buf: &mut Vec<u8>, ms: u64, seq: u64) {
+        buf.extend_from_slice(&ms.to_be_bytes());
+        buf.extend_from_slice(&seq.to_be_bytes());
+    }
+
+    /// 只有一条消息和一个消费组的stream, 三个参数分别为listpack编码的field个数、长度编码的全局PEL个数及消费者个数
+    fn stream_rdb(num_fields: &[u8], global_pel: &[u8], consumers: &[u8]) -> Vec<u8> {
+        // master entry: count, deleted, 1个field, 结束符; 随后一条SAMEFIELDS的消息
+        let mut list_pack = vec![0; 6];
+        list_pack.extend_from_slice(&[0x01, 0x01, 0x00, 0x01]);
+        list_pack.extend_from_slice(num_fields);
+        list_pack.extend_from_slice(&[0x81, b'f', 0x02, 0x00, 0x01]);
+        list_pack.extend_from_slice(&[0x02, 0x01, 0x00, 0x01, 0x00, 0x01, 0x81, b'v', 0x02, 0x03, 0x01]);
+        list_pack.push(0xFF);
+
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.extend_from_slice(&[0xFE, 0x00, 21]);
+        string(&mut rdb, "stream");
+        rdb.push(1);
+        rdb.push(16);
+        stream_id(&mut rdb, 1, 0);
+        rdb.push(list_pack.len() as u8);
+        rdb.extend_from_slice(&list_pack);
+        // length, last_id, first_id, max_deleted_id, entries_added
+        rdb.extend_from_slice(&[1, 1, 0, 1, 0, 0, 0, 1]);
+        // 1个消费组: name, last_id, entries_read
+        rdb.push(1);
+        string(&mut rdb, "group");
+        rdb.extend_from_slice(&[1, 0, 1]);
+        // 全局PEL
+        rdb.extend_from_slice(global_pel);
+        stream_id(&mut rdb, 1, 0);
+        rdb.extend_from_slice(&1_700_000_000_000i64.to_le_bytes());
+        rdb.push(2);
+        // 消费者: name, seen_time, active_time, PEL
+        rdb.extend_from_slice(consumers);
+        string(&mut rdb, "consumer");
+        rdb.extend_from_slice(&1_700_000_000_500i64.to_le_bytes());
+        rdb.extend_from_slice(&1_700_000_000_100i64.to_le_bytes());
+        rdb.push(1);
+        stream_id(&mut rdb, 1, 0);
+        rdb.push(0xFF);
+        rdb.extend_from_slice(&[0; 8]);
+        rdb
+    }
+
+    #[tokio::test]
+    async fn test_parse_stream_listpacks_3() {
+        let rdb = stream_rdb(&[0x01, 0x01], &[1], &[1]);
+        let mut expected = Record::default();
+        RDBParser::parse(&mut Cursor::new(&rdb), &mut expected, Arc::new(AtomicBool::new(true))).unwrap();
+        let mut actual = Record::default();
+        AsyncRDBParser::parse(&mut Cursor::new(&rdb), &mut actual, Arc::new(AtomicBool::new(true)))
+            .await
+            .unwrap();
+        assert_eq!(actual.events, expected.events);
+        let stream = &expected.events[2];
+        assert!(stream.contains(
+            "pending: [PendingEntry { id: ID { ms: 1, seq: 0 }, delivery_time: 1700000000000, delivery_count: 2 }]"
+        ));
+        assert!(stream.contains(
+            "seen_time: 1700000000500, active_time: Some(1700000000100), pending: [ID { ms: 1, seq: 0 }]"
+        ));
+    }
+
+    #[test]
+    fn test_parse_stream_invalid_count() {
+        let parse = |rdb: &[u8]| {
+            RDBParser::parse(&mut Cursor::new(rdb), &mut Record::default(), Arc::new(AtomicBool::new(true)))
+        };
+        let reason = |rdb: &[u8]| match parse(rdb) {
+            Err(RedisSyncError::MalformedRDB { value_type, reason, .. }) => {
+                assert_eq!(value_type, Some(21));
+                reason
+            }
+            other => panic!("unexpected result: {:?}", other),
+        };
+        let mut negative = vec![0x81];
+        negative.extend_from_slice(&u64::MAX.to_be_bytes());
+        let mut huge = vec![0x81];
+        huge.extend_from_slice(&(1u64 << 60).to_be_bytes());
+
+        // master entry中的field个数为13位整数-1
+        assert_eq!(reason(&stream_rdb(&[0xDF, 0xFF, 0x02], &[1], &[1])), "invalid integer in listpack: -1");
+        assert_eq!(reason(&stream_rdb(&[0x01, 0x01], &negative, &[1])), "invalid length: -1");
+        assert_eq!(reason(&stream_rdb(&[0x01, 0x01], &[1], &negative)), "invalid length: -1");
+        // 个数远超实际的数据时, 在数据读完或格式错误处返回错误, 不会按声明的个数分配内存
+        for (global_pel, consumers) in [(&huge[..], &[1][..]), (&[1], &huge)] {
+            assert!(parse(&stream_rdb(&[0x01, 0x01], global_pel, consumers)).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_function() {
+        let code = "#!lua name=lib\nreturn 1";
+        let mut rdb = b"REDIS0010".to_vec();
+        rdb.push(245);
+        string(&mut rdb, code);
+        // rc版本的格式: 库名, 引擎名, 描述, 代码
+        rdb.push(246);
+        string(&mut rdb, "lib");
+        string(&mut rdb, "LUA");
+        rdb.push(1);
+        string(&mut rdb, "desc");
+        string(&mut rdb, code);
+        rdb.push(0xFF);
+        rdb.extend_from_slice(&[0; 8]);
+
+        let mut expected = Record::default();
+        RDBParser::parse(&mut Cursor::new(&rdb), &mut expected, Arc::new(AtomicBool::new(true))).unwrap();
+        let mut actual = Record::default();
+        AsyncRDBParser::parse(&mut Cursor::new(&rdb), &mut actual, Arc::new(AtomicBool::new(true)))
+            .await
+            .unwrap();
+        assert_eq!(actual.events, expected.events);
+        let function = format!("RDB(Function(Function {{ code: {:?} }}))", code.as_bytes());
+        assert_eq!(expected.events[1], function);
+        assert_eq!(expected.events[2], function);
+    }
+
+    #[tokio::test]
+    async fn test_parse_hash_field_expire() {
+        let min_expire = 1_700_000_000_000i64;
+        let mut rdb = b"REDIS0012".to_vec();
+        // hash with metadata: 最小过期时间, 字段个数, 依次为相对过期时间, 字段名, 字段值
+        rdb.push(24);
+        string(&mut rdb, "meta");
+        rdb.extend_from_slice(&min_expire.to_le_bytes());
+        rdb.extend_from_slice(&[2, 11]);
+        string(&mut rdb, "a");
+        string(&mut rdb, "1");
+        rdb.push(0);
+        string(&mut rdb, "b");
+        string(&mut rdb, "2");
+        // pre-GA版本存储的是绝对过期时间
+        rdb.push(22);
+        string(&mut rdb, "pre");
+        rdb.extend_from_slice(&[1, 0x80, 0x00, 0x0F, 0x42, 0x40]);
+        string(&mut rdb, "c");
+        string(&mut rdb, "3");
+        // listpack ex: 字段名, 字段值, 过期时间
+        let mut list_pack = vec![0; 4];
+        list_pack.extend_from_slice(&3u16.to_le_bytes());
+        list_pack.extend_from_slice(&[0x81, b'f', 0x02, 0x81, b'v', 0x02, 0xF4]);
+        list_pack.extend_from_slice(&min_expire.to_le_bytes());
+        list_pack.extend_from_slice(&[0x09, 0xFF]);
+        rdb.push(25);
+        string(&mut rdb, "lp");
+        rdb.extend_from_slice(&min_expire.to_le_bytes());
+        rdb.push(list_pack.len() as u8);
+        rdb.extend_from_slice(&list_pack);
+        rdb.push(0xFF);
+        rdb.extend_from_slice(&[0; 8]);
+
+        let mut expected = Record::default();
+        RDBParser::parse(&mut Cursor::new(&rdb), &mut expected, Arc::new(AtomicBool::new(true))).unwrap();
+        let mut actual = Record::default();
+        AsyncRDBParser::parse(&mut Cursor::new(&rdb), &mut actual, Arc::new(AtomicBool::new(true)))
+            .await
+            .unwrap();
+        assert_eq!(actual.events, expected.events);
+        assert!(expected.events[1].contains("value: [49], expire: Some(1700000000010) }"));
+        assert!(expected.events[1].contains("value: [50], expire: None }"));
+        assert!(expected.events[2].contains("value: [51], expire: Some(1000000) }"));
+        assert!(expected.events[3].contains("value: [118], expire: Some(1700000000000) }"));
+    }
+
+    struct Counter {
+        value: isize,
+        text: Vec<u8>,
+    }
+
+    impl Module for Counter {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    struct CounterParser;
+
+    impl ModuleParser for CounterParser {
+        fn parse(&mut self, input: &mut dyn Read, module_name: &str, module_version: usize) -> std::io::Result<Box<dyn Module>> {
+            assert_eq!(module_name, "counter-1");
+            assert_eq!(module_version, 3);
+            input.read_length().unwrap();
+            let (value, _) = input.read_length().unwrap();
+            input.read_length().unwrap();
+            let text = input.read_string().unwrap();
+            Ok(Box::new(Counter { value, text }))
+        }
+    }
+
+    #[derive(Default)]
+    struct Modules {
+        modules: Vec<String>,
+    }
+
+    impl EventHandler for Modules {
+        fn handle(&mut self, event: Event) {
+            if let Event::RDB(Object::Module(key, module, _)) = event {
+                let key = String::from_utf8(key).unwrap();
+                if let Some(counter) = module.as_any().downcast_ref::<Counter>() {
+                    let text = String::from_utf8_lossy(&counter.text);
+                    self.modules.push(format!("{}: {} {}", key, counter.value, text));
+                } else if let Some(raw) = module.as_any().downcast_ref::<RawModule>() {
+                    self.modules.push(format!("{}: {}/{} {:?}", key, raw.name, raw.version, raw.data));
+                }
+            }
+        }
+    }
+
+    fn module_rdb() -> Vec<u8> {
+        let name = "counter-1";
+        let mut id = 3u64;
+        for (i, chr) in name.chars().enumerate() {
+            let index = MODULE_SET.iter().position(|c| *c == chr).unwrap() as u64;
+            id |= index << (10 + (8 - i) * 6);
+        }
+        let mut rdb = b"REDIS0009".to_vec();
+        rdb.push(7);
+        string(&mut rdb, "module");
+        rdb.push(0x81);
+        rdb.extend_from_slice(&id.to_be_bytes());
+        // UINT 42, STRING "abc", EOF
+        rdb.extend_from_slice(&[2, 42, 5, 3, b'a', b'b', b'c', 0]);
+        rdb.push(0x00);
+        string(&mut rdb, "after");
+        string(&mut rdb, "value");
+        rdb.push(0xFF);
+        rdb.extend_from_slice(&[0; 8]);
+        rdb
+    }
+
+    #[tokio::test]
+    async fn test_parse_module() {
+        let rdb = module_rdb();
+        // 未注册解析器时保留原始数据
+        let mut expected = Modules::default();
+        RDBParser::parse(&mut Cursor::new(&rdb), &mut expected, Arc::new(AtomicBool::new(true))).unwrap();
+        assert_eq!(expected.modules, vec!["module: counter-1/3 [2, 42, 5, 3, 97, 98, 99, 0]"]);
+        let mut actual = Modules::default();
+        AsyncRDBParser::parse(&mut Cursor::new(&rdb), &mut actual, Arc::new(AtomicBool::new(true)))
+            .await
+            .unwrap();
+        assert_eq!(actual.modules, expected.modules);
+
+        let mut modules = ModuleRegistry::new();
+        modules.register("counter-1", Box::new(CounterParser));
+        let mut handler = Modules::default();
+        RDBParser::parse_with_modules(&mut Cursor::new(&rdb), &mut handler, &mut modules, Arc::new(AtomicBool::new(true)))
+            .unwrap();
+        assert_eq!(handler.modules, vec!["module: 42 abc"]);
+        let mut handler = Modules::default();
+        AsyncRDBParser::parse_with_modules(&mut Cursor::new(&rdb), &mut handler, &mut modules, Arc::new(AtomicBool::new(true)))
+            .await
+            .unwrap();
+        assert_eq!(handler.modules, vec!["module: 42 abc"]);
+
+        // 后续的key不受影响
+        let mut events = Record::default();
+        RDBParser::parse(&mut Cursor::new(&rdb), &mut events, Arc::new(AtomicBool::new(true))).unwrap();
+        assert!(events.events[2].starts_with("RDB(String(KeyValue { key: [97, 102, 116, 101, 114]"));
+    }
+
+    #[tokio::test]
+    async fn test_parse_malformed() {
+        // zset中的score无法解析为double
+        let mut rdb = b"REDIS0009".to_vec();
+        rdb.extend_from_slice(&[0xFE, 0x00]);
+        rdb.push(0x03);
+        string(&mut rdb, "zset");
+        rdb.push(1);
+        string(&mut rdb, "member");
+        string(&mut rdb, "abc");
+        rdb.push(0xFF);
+
+        let sync = RDBParser::parse(&mut Cursor::new(&rdb), &mut Record::default(), Arc::new(AtomicBool::new(true)));
+        let aio = AsyncRDBParser::parse(&mut Cursor::new(&rdb), &mut Record::default(), Arc::new(AtomicBool::new(true)))
+            .await;
+        for result in [sync, aio] {
+            match result {
+                Err(RedisSyncError::MalformedRDB {
+                    value_type,
+                    key,
+                    offset,
+                    reason,
+                }) => {
+                    assert_eq!(value_type, Some(0x03));
+                    assert_eq!(key.as_deref(), Some("zset"));
+                    assert_eq!(offset, 11);
+                    assert_eq!(reason, "invalid double: abc");
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        // 未知的value type
+        let mut rdb = b"REDIS0009".to_vec();
+        rdb.extend_from_slice(&[0xFE, 0x00, 30]);
+        string(&mut rdb, "key");
+        rdb.push(0xFF);
+        let result = RDBParser::parse(&mut Cursor::new(&rdb), &mut Record::default(), Arc::new(AtomicBool::new(true)));
+        assert!(matches!(
+            result,
+            Err(RedisSyncError::MalformedRDB {
+                value_type: Some(30),
+                offset: 11,
+                ..
+            })
+        ));
+    }
+
+    /// 以`value`作为key为"key"的string的值, 构造只有一条记录的RDB
+    fn string_rdb(value: &[u8]) -> Vec<u8> {
+        let mut rdb = b"REDIS0009".to_vec();
+        rdb.extend_from_slice(&[0xFE, 0x00, 0x00]);
+        string(&mut rdb, "key");
+        rdb.extend_from_slice(value);
+        rdb.push(0xFF);
+        rdb
+    }
+
+    #[test]
+    fn test_parse_invalid_length() {
+        let parse = |rdb: &[u8]| {
+            RDBParser::parse(&mut Cursor::new(rdb), &mut Record::default(), Arc::new(AtomicBool::new(true)))
+        };
+        let reason = |rdb: &[u8]| match parse(rdb) {
+            Err(RedisSyncError::MalformedRDB { value_type, offset, reason, .. }) => {
+                assert_eq!((value_type, offset), (Some(0), 11));
+                reason
+            }
+            other => panic!("unexpected result: {:?}", other),
+        };
+        // 未定义的长度编码
+        assert_eq!(reason(&string_rdb(&[0x82, b'v'])), "invalid length encoding: 0x82");
+        // 64位的长度超出isize
+        let mut negative = vec![0x81];
+        negative.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(reason(&string_rdb(&negative)), "Invalid string length: -1");
+        // lzf的原始长度远超压缩数据可能解压出的长度
+        let mut lzf = vec![0xC3, 0x02, 0x81];
+        lzf.extend_from_slice(&(1u64 << 40).to_be_bytes());
+        lzf.extend_from_slice(&[0x00, b'a']);
+        assert!(reason(&string_rdb(&lzf)).starts_with("lzf output length"));
+        // 长度超出剩余的数据时按数据不足处理, 不会按声明的长度分配内存
+        let mut huge = vec![0x81];
+        huge.extend_from_slice(&(1u64 << 60).to_be_bytes());
+        assert!(matches!(parse(&string_rdb(&huge)), Err(RedisSyncError::Disconnect(_))));
+    }
+
+    #[tokio::test]
+    async fn test_parse_invalid_length_same_as_sync() {
+        let mut negative = vec![0x81];
+        negative.extend_from_slice(&u64::MAX.to_be_bytes());
+        let mut huge = vec![0x81];
+        huge.extend_from_slice(&(1u64 << 60).to_be_bytes());
+        let mut huge_lzf = vec![0xC3];
+        huge_lzf.extend_from_slice(&huge);
+        huge_lzf.push(0x01);
+        let rdbs = [
+            string_rdb(&[0x82, b'v']),
+            string_rdb(&negative),
+            string_rdb(&huge),
+            string_rdb(&huge_lzf),
+            stream_rdb(&[0x01, 0x01], &negative, &[1]),
+            stream_rdb(&[0x01, 0x01], &huge, &[1]),
+            stream_rdb(&[0x01, 0x01], &[1], &huge),
+        ];
+        for rdb in rdbs {
+            let running = || Arc::new(AtomicBool::new(true));
+            let sync = RDBParser::parse(&mut Cursor::new(&rdb), &mut Record::default(), running());
+            let aio = AsyncRDBParser::parse(&mut Cursor::new(&rdb), &mut Record::default(), running()).await;
+            match (sync, aio) {
+                (
+                    Err(RedisSyncError::MalformedRDB { reason: expected, .. }),
+                    Err(RedisSyncError::MalformedRDB { reason, .. }),
+                ) => assert_eq!(reason, expected),
+                // 异步解析先按记录的边界读取数据, 长度远超实际的数据时在读完之前就会失败
+                (Err(_), Err(_)) => {}
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_slot_info() {
+        let mut rdb = b"REDIS0012".to_vec();
+        rdb.extend_from_slice(&[0xFE, 0x00, 0xF4, 0x40, 0x64, 2, 1]);
+        rdb.push(0x00);
+        string(&mut rdb, "key");
+        string(&mut rdb, "value");
+        rdb.push(0xFF);
+        rdb.extend_from_slice(&[0; 8]);
+
+        let mut expected = Record::default();
+        RDBParser::parse(&mut Cursor::new(&rdb), &mut expected, Arc::new(AtomicBool::new(true))).unwrap();
+        let mut actual = Record::default();
+        AsyncRDBParser::parse(&mut Cursor::new(&rdb), &mut actual, Arc::new(AtomicBool::new(true)))
+            .await
+            .unwrap();
+        assert_eq!(actual.events, expected.events);
+        assert!(expected.events[2].contains("SlotInfo { slot: 100, size: 2, expires: 1 }"));
+        assert!(expected.events[3].contains("key: [107, 101, 121]"));
+
+        // 未知的opcode
+        let mut rdb = b"REDIS0013".to_vec();
+        rdb.extend_from_slice(&[0xFE, 0x00, 0xF3, 1, 2, 3]);
+        let sync = RDBParser::parse(&mut Cursor::new(&rdb), &mut Record::default(), Arc::new(AtomicBool::new(true)));
+        let aio = AsyncRDBParser::parse(&mut Cursor::new(&rdb), &mut Record::default(), Arc::new(AtomicBool::new(true)))
+            .await;
+        for result in [sync, aio] {
+            match result {
+                Err(RedisSyncError::MalformedRDB { value_type, offset, reason, .. }) => {
+                    assert_eq!(value_type, Some(0xF3));
+                    assert_eq!(offset, 11);
+                    assert_eq!(reason, "unsupported RDB version 13, unknown opcode: 243");
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+/*!
+异步的Redis Serialization Protocol解析, 与同步版本的`RespDecode`一一对应
+*/
+
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::resp::{Resp, Type, COLON, CR, DOLLAR, LF, MINUS, PLUS, STAR};
+use crate::to_string;
+
+pub(crate) trait AsyncRespDecode: AsyncRead + Unpin + Send {
+    async fn decode_resp(&mut self) -> Result<Resp> {
+        match self.decode_type().await? {
+            Type::String => Ok(Resp::String(self.decode_string().await?)),
+            Type::Int => self.decode_int().await,
+            Type::Error => Ok(Resp::Error(self.decode_string().await?)),
+            Type::BulkString => self.decode_bulk_string().await,
+            Type::Array => self.decode_array().await,
+        }
+    }
+
+    async fn decode_type(&mut self) -> Result<Type> {
+        loop {
+            let b = self.read_u8().await?;
+            if b == LF {
+                continue;
+            } else {
+                match b {
+                    PLUS => return Ok(Type::String),
+                    MINUS => return Ok(Type::Error),
+                    COLON => return Ok(Type::Int),
+                    DOLLAR => return Ok(Type::BulkString),
+                    STAR => return Ok(Type::Array),
+                    _ => return Err(anyhow!("decode_type err: {}", b)),
+                }
+            }
+        }
+    }
+
+    async fn decode_string(&mut self) -> Result<String> {
+        let mut buf = vec![];
+        loop {
+            let byte = self.read_u8().await?;
+            if byte != CR {
+                buf.push(byte);
+            } else {
+                break;
+            }
+        }
+        if self.read_u8().await? == LF {
+            Ok(to_string(buf))
+        } else {
+            Err(anyhow!("Expect LF after CR"))
+        }
+    }
+
+    async fn decode_int(&mut self) -> Result<Resp> {
+        let s = self.decode_string().await?;
+        let i = s.parse::<i64>().map_err(|_| anyhow!("Expected Int, but got {}", s))?;
+        Ok(Resp::Int(i))
+    }
+
+    async fn decode_bulk_string(&mut self) -> Result<Resp> {
+        let r = self.decode_int().await?;
+        if let Resp::Int(i) = r {
+            if i > 0 {
+                let mut buf = vec![0; i as usize];
+                self.read_exact(&mut buf).await?;
+                let mut end = [0; 2];
+                self.read_exact(&mut end).await?;
+                if !end.eq(&[CR, LF]) {
+                    Err(anyhow!("Expected CRLF"))
+                } else {
+                    Ok(Resp::BulkBytes(buf))
+                }
+            } else {
+                self.read_exact(&mut [0; 2]).await?;
+                Ok(Resp::BulkBytes(vec![0; 0]))
+            }
+        } else {
+            Err(anyhow!("Expected Int Response"))
+        }
+    }
+
+    async fn decode_array(&mut self) -> Result<Resp> {
+        let r = self.decode_int().await?;
+        if let Resp::Int(i) = r {
+            let mut arr = Vec::with_capacity(i as usize);
+            for _ in 0..i {
+                // 递归的async调用需要装箱
+                let resp = Box::pin(self.decode_resp()).await?;
+                arr.push(resp);
+            }
+            Ok(Resp::Array(arr))
+        } else {
+            Err(anyhow!("Expected Int Response"))
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin + Send + ?Sized> AsyncRespDecode for R {}
@@ -0,0 +1,80 @@
+/*!
+与master之间的异步连接, 与同步版本的`Stream`对应
+
+开启TLS时复用同步版本的[tls_connector], PKCS#12客户端证书与`is_tls_insecure`的处理方式完全一致。
+
+[tls_connector]: ../../stream/fn.tls_connector.html
+*/
+
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_native_tls::{TlsConnector, TlsStream};
+
+use crate::config::Config;
+use crate::stream::tls_connector;
+
+pub(crate) enum AsyncStream {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl AsyncStream {
+    /// 连接master, 开启TLS时在TCP连接建立后完成TLS握手
+    pub(crate) async fn connect(config: &Config) -> Result<AsyncStream> {
+        let tcp = TcpStream::connect((config.host.as_str(), config.port)).await?;
+        if !config.is_tls_enabled {
+            return Ok(AsyncStream::Tcp(tcp));
+        }
+        let connector = TlsConnector::from(tls_connector(config)?);
+        let tls = connector
+            .connect(&config.host, tcp)
+            .await
+            .map_err(|err| anyhow!("tls handshake err: {}", err))?;
+        Ok(AsyncStream::Tls(Box::new(tls)))
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            AsyncStream::Tcp(tcp) => tcp.local_addr(),
+            AsyncStream::Tls(tls) => tls.get_ref().get_ref().get_ref().local_addr(),
+        }
+    }
+}
+
+impl AsyncRead for AsyncStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(tcp) => Pin::new(tcp).poll_read(cx, buf),
+            AsyncStream::Tls(tls) => Pin::new(tls.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for AsyncStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(tcp) => Pin::new(tcp).poll_write(cx, buf),
+            AsyncStream::Tls(tls) => Pin::new(tls.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(tcp) => Pin::new(tcp).poll_flush(cx),
+            AsyncStream::Tls(tls) => Pin::new(tls.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(tcp) => Pin::new(tcp).poll_shutdown(cx),
+            AsyncStream::Tls(tls) => Pin::new(tls.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
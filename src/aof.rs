@@ -0,0 +1,799 @@
+/*!
+离线读写AOF文件
+
+支持两种形式:
+
+* 单个AOF文件, 如`appendonly.aof`, 开启`aof-use-rdb-preamble`时以RDB开头, 之后是RESP格式的命令
+* Redis 7的`appendonlydir/`目录或其中的`.manifest`文件, 依次读取manifest中的base文件及各个incr文件,
+  history文件已被新的base文件取代, 会被忽略
+
+RDB部分的数据以`Event::RDB`, 命令以`Event::AOF`的形式交给[EventHandler], 与[Listener]产生的事件相同,
+可用于离线回放备份或审计历史命令。`aof-timestamp-enabled`写入的`#TS:`注释会被跳过。
+
+[AOFWriter]则反过来将事件写成AOF文件, 可以把从master同步到的数据持久化为增量备份。
+
+[EventHandler]: ../trait.EventHandler.html
+[Listener]: ../listener/struct.Listener.html
+*/
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::result;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use byteorder::ReadBytesExt;
+use log::warn;
+
+use crate::cmd::{self, Command, ParseErrorPolicy};
+use crate::dump::{self, OwnedObject};
+use crate::error::RedisSyncError;
+use crate::io::ProgressReader;
+use crate::listener::command_args;
+use crate::rdb::{ExpireType, Meta, ModuleRegistry, Object, RDBParser, Stream, ID};
+use crate::resp::{RespDecode, STAR};
+use crate::writer::WriteOptions;
+use crate::{Event, EventHandler};
+
+type Result<T> = result::Result<T, RedisSyncError>;
+
+/// AOF文件的读取器
+pub struct AOFReader {
+    path: PathBuf,
+    /// module类型的值的解析器
+    pub modules: ModuleRegistry,
+    /// 命令解析失败时的处理方式
+    pub command_error_policy: ParseErrorPolicy,
+    /// 最后一个文件末尾的命令不完整时忽略该命令, 与Redis的`aof-load-truncated yes`相同
+    pub load_truncated: bool,
+}
+
+impl AOFReader {
+    /// `path`可以是单个AOF文件, `appendonlydir/`目录或其中的manifest文件
+    pub fn new<P: Into<PathBuf>>(path: P) -> AOFReader {
+        AOFReader {
+            path: path.into(),
+            modules: ModuleRegistry::new(),
+            command_error_policy: ParseErrorPolicy::default(),
+            load_truncated: true,
+        }
+    }
+
+    /// 按顺序读取全部文件, 所有数据交给`handler`处理
+    ///
+    /// 文件内容不符合格式时返回[RedisSyncError::MalformedAOF], RDB部分的格式错误时返回[RedisSyncError::MalformedRDB]
+    ///
+    /// [RedisSyncError::MalformedAOF]: ../error/enum.RedisSyncError.html#variant.MalformedAOF
+    /// [RedisSyncError::MalformedRDB]: ../error/enum.RedisSyncError.html#variant.MalformedRDB
+    pub fn read(&mut self, handler: &mut dyn EventHandler) -> Result<()> {
+        let files = aof_files(&self.path)?;
+        for (i, file) in files.iter().enumerate() {
+            self.read_file(file, handler, i + 1 == files.len())?;
+        }
+        Ok(())
+    }
+
+    fn read_file(&mut self, path: &Path, handler: &mut dyn EventHandler, is_last: bool) -> Result<()> {
+        let mut input = BufReader::new(File::open(path)?);
+        let is_rdb = input.fill_buf()?.starts_with(b"REDIS");
+        let read = Arc::new(AtomicU64::new(0));
+        let mut input = ProgressReader::new(input, Arc::clone(&read));
+        if is_rdb {
+            input.parse_with_modules(handler, &mut self.modules, Arc::new(AtomicBool::new(true)))?;
+        }
+
+        loop {
+            let offset = read.load(Ordering::Relaxed);
+            let error = |reason: String| RedisSyncError::MalformedAOF {
+                file: path.display().to_string(),
+                offset,
+                reason,
+            };
+            let first = match input.read_u8() {
+                Ok(first) => first,
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err.into()),
+            };
+            let result = match first {
+                b'#' => input.decode_string().map(|_| None),
+                STAR => input.decode_array().and_then(command_args).map(Some),
+                _ => return Err(error(format!("unexpected byte {:#04x}, expected a command", first))),
+            };
+            match result {
+                Ok(Some(args)) => cmd::parse(args, handler, self.command_error_policy)?,
+                Ok(None) => {}
+                Err(err) if is_eof(&err) && is_last && self.load_truncated => {
+                    warn!("ignore truncated command at the end of {}, offset: {}", path.display(), offset);
+                    return Ok(());
+                }
+                Err(err) if is_eof(&err) => return Err(error("unexpected end of file".to_string())),
+                Err(err) => return Err(error(err.to_string())),
+            }
+        }
+    }
+}
+
+fn is_eof(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<std::io::Error>(), Some(err) if err.kind() == ErrorKind::UnexpectedEof)
+}
+
+/// 需要按顺序读取的AOF文件
+fn aof_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if path.is_dir() {
+        let mut manifests = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?.path();
+            if entry.extension().is_some_and(|ext| ext == "manifest") {
+                manifests.push(entry);
+            }
+        }
+        match manifests.as_slice() {
+            [manifest] => read_manifest(manifest),
+            _ => Err(RedisSyncError::MalformedAOF {
+                file: path.display().to_string(),
+                offset: 0,
+                reason: format!("expected one manifest, found {}", manifests.len()),
+            }),
+        }
+    } else if path.extension().is_some_and(|ext| ext == "manifest") {
+        read_manifest(path)
+    } else {
+        Ok(vec![path.to_path_buf()])
+    }
+}
+
+/// 解析manifest, 每行为`file <name> seq <seq> type <b|h|i>`, 返回base文件及按顺序排列的incr文件
+fn read_manifest(path: &Path) -> Result<Vec<PathBuf>> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let content = fs::read_to_string(path)?;
+    let mut base = None;
+    let mut incrs = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |reason: String| RedisSyncError::MalformedAOF {
+            file: path.display().to_string(),
+            offset: i as u64 + 1,
+            reason,
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if !tokens.len().is_multiple_of(2) {
+            return Err(error(format!("invalid manifest line: {}", line)));
+        }
+        let mut name = None;
+        let mut file_type = None;
+        for pair in tokens.chunks(2) {
+            match pair[0] {
+                "file" => name = Some(pair[1]),
+                "type" => file_type = Some(pair[1]),
+                _ => {}
+            }
+        }
+        let name = name.ok_or_else(|| error("missing file name".to_string()))?;
+        match file_type {
+            Some("b") => {
+                if base.replace(dir.join(name)).is_some() {
+                    return Err(error("found more than one base file".to_string()));
+                }
+            }
+            Some("i") => incrs.push(dir.join(name)),
+            Some("h") => {}
+            file_type => return Err(error(format!("unknown file type: {:?}", file_type))),
+        }
+    }
+    Ok(base.into_iter().chain(incrs).collect())
+}
+
+/// [AOFWriter]的选项
+pub struct AOFWriteOptions {
+    /// 文件名前缀, 与Redis的`appendfilename`相同, 生成`<file_name>.<seq>.incr.aof`及`<file_name>.manifest`
+    pub file_name: String,
+    /// 当前文件超过此大小后切换到新的文件, 单位为字节
+    pub max_segment_size: u64,
+    /// `RESTORE`的payload使用的RDB版本, 需要不高于目标Redis支持的版本, 如Redis 6.x为9, 7.0为10, 7.2为11
+    pub restore_version: u8,
+}
+
+impl Default for AOFWriteOptions {
+    fn default() -> Self {
+        AOFWriteOptions {
+            file_name: "appendonly.aof".to_string(),
+            max_segment_size: 64 * 1024 * 1024,
+            restore_version: WriteOptions::default().version,
+        }
+    }
+}
+
+/// 将事件以RESP格式的命令写入AOF文件
+///
+/// 输出目录与Redis 7的`appendonlydir/`相同, 每个文件都记录在manifest中, 可被[AOFReader]或`redis-server`加载,
+/// 也可将各个文件依次交给`redis-cli --pipe`回放。目录中已有manifest时, 新的文件接在已有文件之后。
+///
+/// RDB数据被转换为等价的命令:
+///
+/// * String: `SET`
+/// * List, Set, SortedSet, Hash: `RPUSH`, `SADD`, `ZADD`, `HSET`, 字段的过期时间使用`HPEXPIREAT`
+/// * Stream: `XADD`, `XSETID`, `XGROUP CREATE`, `XGROUP CREATECONSUMER`及`XCLAIM`, 与Redis重写AOF时相同
+/// * Module: `RESTORE ... REPLACE`, 只支持[RawModule], payload的RDB版本由`restore_version`指定
+/// * Function: `FUNCTION LOAD REPLACE`
+///
+/// key的过期时间以`PEXPIREAT`写入。写入出错后不再处理之后的事件, 错误由[AOFWriter::flush]返回。
+///
+/// [RawModule]: ../rdb/struct.RawModule.html
+pub struct AOFWriter {
+    dir: PathBuf,
+    options: AOFWriteOptions,
+    /// manifest的全部内容
+    manifest: Vec<String>,
+    seq: u64,
+    output: BufWriter<File>,
+    /// 当前文件已写入的字节数
+    size: u64,
+    db: Option<isize>,
+    /// 处于MULTI与EXEC之间时不切换文件, 否则Redis加载时会丢弃不完整的事务
+    in_multi: bool,
+    error: Option<io::Error>,
+}
+
+impl AOFWriter {
+    /// 在`dir`中创建新的AOF文件, 目录不存在时会被创建
+    pub fn new<P: Into<PathBuf>>(dir: P, options: AOFWriteOptions) -> io::Result<AOFWriter> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let mut manifest = match fs::read_to_string(dir.join(format!("{}.manifest", options.file_name))) {
+            Ok(content) => content.lines().filter(|line| !line.trim().is_empty()).map(String::from).collect(),
+            Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        let seq = manifest.iter().filter_map(|line| manifest_seq(line)).max().unwrap_or(0) + 1;
+        let output = create_segment(&dir, &options.file_name, &mut manifest, seq)?;
+        Ok(AOFWriter { dir, options, manifest, seq, output, size: 0, db: None, in_multi: false, error: None })
+    }
+
+    /// 以RESP数组的格式写入一条命令, 第一个参数为命令名
+    pub fn write_command<A: AsRef<[u8]>>(&mut self, args: &[A]) -> io::Result<()> {
+        if self.size >= self.options.max_segment_size && !self.in_multi {
+            self.rotate()?;
+        }
+        let mut buf = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            let arg = arg.as_ref();
+            buf.extend(format!("${}\r\n", arg.len()).into_bytes());
+            buf.extend_from_slice(arg);
+            buf.extend_from_slice(b"\r\n");
+        }
+        self.output.write_all(&buf)?;
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+
+    /// 返回之前处理事件时出现的错误, 没有错误时将缓冲的数据写入文件
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.output.flush()
+    }
+
+    /// 切换到下一个文件, 新文件以`SELECT`开头, 可以独立回放
+    fn rotate(&mut self) -> io::Result<()> {
+        self.output.flush()?;
+        self.seq += 1;
+        self.output = create_segment(&self.dir, &self.options.file_name, &mut self.manifest, self.seq)?;
+        self.size = 0;
+        if let Some(db) = self.db {
+            self.write_command(&[b"SELECT" as &[u8], db.to_string().as_bytes()])?;
+        }
+        Ok(())
+    }
+
+    fn write_aof(&mut self, cmd: &Command) -> io::Result<()> {
+        self.write_command(&cmd.to_args())?;
+        match cmd {
+            Command::SELECT(select) => self.db = Some(select.db as isize),
+            Command::MULTI => self.in_multi = true,
+            Command::EXEC => self.in_multi = false,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn select(&mut self, db: isize) -> io::Result<()> {
+        if self.db != Some(db) {
+            self.write_command(&[b"SELECT" as &[u8], db.to_string().as_bytes()])?;
+            self.db = Some(db);
+        }
+        Ok(())
+    }
+
+    fn expire(&mut self, key: &[u8], meta: &Meta) -> io::Result<()> {
+        match &meta.expire {
+            Some((ExpireType::Second, expire)) => self.pexpireat(key, expire * 1000),
+            Some((ExpireType::Millisecond, expire)) => self.pexpireat(key, *expire),
+            None => Ok(()),
+        }
+    }
+
+    fn pexpireat(&mut self, key: &[u8], millis: i64) -> io::Result<()> {
+        self.write_command(&[b"PEXPIREAT" as &[u8], key, millis.to_string().as_bytes()])
+    }
+
+    /// 集合类数据分批到达, 每批写入一条命令, 过期时间随每批重复写入
+    fn write_object(&mut self, object: Object) -> io::Result<()> {
+        match object {
+            Object::String(kv) => {
+                self.select(kv.meta.db)?;
+                self.write_command(&[b"SET" as &[u8], kv.key, kv.value])?;
+                self.expire(kv.key, kv.meta)
+            }
+            Object::List(list) => {
+                self.select(list.meta.db)?;
+                let mut args: Vec<&[u8]> = vec![b"RPUSH", list.key];
+                args.extend(list.values.iter().map(Vec::as_slice));
+                self.write_command(&args)?;
+                self.expire(list.key, list.meta)
+            }
+            Object::Set(set) => {
+                self.select(set.meta.db)?;
+                let mut args: Vec<&[u8]> = vec![b"SADD", set.key];
+                args.extend(set.members.iter().map(Vec::as_slice));
+                self.write_command(&args)?;
+                self.expire(set.key, set.meta)
+            }
+            Object::SortedSet(zset) => {
+                self.select(zset.meta.db)?;
+                let mut args = vec![b"ZADD".to_vec(), zset.key.to_vec()];
+                for item in zset.items {
+                    args.push(item.score.to_string().into_bytes());
+                    args.push(item.member.clone());
+                }
+                self.write_command(&args)?;
+                self.expire(zset.key, zset.meta)
+            }
+            Object::Hash(hash) => {
+                self.select(hash.meta.db)?;
+                let mut args: Vec<&[u8]> = vec![b"HSET", hash.key];
+                for field in hash.fields {
+                    args.push(&field.name);
+                    args.push(&field.value);
+                }
+                self.write_command(&args)?;
+                for field in hash.fields {
+                    if let Some(expire) = field.expire {
+                        let expire = expire.to_string();
+                        let args: [&[u8]; 6] = [b"HPEXPIREAT", hash.key, expire.as_bytes(), b"FIELDS", b"1", &field.name];
+                        self.write_command(&args)?;
+                    }
+                }
+                self.expire(hash.key, hash.meta)
+            }
+            Object::Stream(key, stream) => {
+                self.select(stream.meta.db)?;
+                self.write_stream(&key, &stream)?;
+                self.expire(&key, stream.meta)
+            }
+            Object::Module(key, module, meta) => {
+                let object = OwnedObject::Module(key, module);
+                let options = WriteOptions { version: self.options.restore_version, ..Default::default() };
+                let payload = dump::encode(&object, options)?;
+                self.select(meta.db)?;
+                self.write_command(&[b"RESTORE" as &[u8], object.key(), b"0", &payload, b"REPLACE"])?;
+                self.expire(object.key(), meta)
+            }
+            Object::Function(function) => {
+                self.write_command(&[b"FUNCTION" as &[u8], b"LOAD", b"REPLACE", function.code])
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn write_stream(&mut self, key: &[u8], stream: &Stream) -> io::Result<()> {
+        let mut added = false;
+        for entry in stream.entries.values().filter(|entry| !entry.deleted) {
+            let id = entry.id.to_string();
+            let mut args: Vec<&[u8]> = vec![b"XADD", key, id.as_bytes()];
+            for (field, value) in &entry.fields {
+                args.push(field);
+                args.push(value);
+            }
+            self.write_command(&args)?;
+            added = true;
+        }
+        if !added {
+            // 没有消息的stream无法直接创建, 先写入一条再截断
+            self.write_command(&[b"XADD" as &[u8], key, b"MAXLEN", b"0", b"0-1", b"x", b"y"])?;
+        }
+        // 与Redis重写AOF时相同, 最后以XSETID恢复被删除的消息影响的last id,
+        // RDB 10及以上的版本还记录了添加过的消息数及被删除的最大id
+        let last_id = stream
+            .last_id
+            .or_else(|| stream.entries.keys().next_back().copied())
+            .unwrap_or(ID { ms: 0, seq: 0 })
+            .to_string();
+        let mut args = vec![b"XSETID".to_vec(), key.to_vec(), last_id.into_bytes()];
+        if let Some(entries_added) = stream.added_entries_count {
+            let max_deleted_id = stream.max_deleted_id.unwrap_or(ID { ms: 0, seq: 0 });
+            args.push(b"ENTRIESADDED".to_vec());
+            args.push(entries_added.to_string().into_bytes());
+            args.push(b"MAXDELETEDID".to_vec());
+            args.push(max_deleted_id.to_string().into_bytes());
+        }
+        self.write_command(&args)?;
+
+        for group in &stream.groups {
+            let group_id = group.last_id.to_string();
+            let mut args = vec![b"XGROUP".to_vec(), b"CREATE".to_vec(), key.to_vec(), group.name.clone(), group_id.into_bytes()];
+            if let Some(entries_read) = group.entries_read {
+                args.push(b"ENTRIESREAD".to_vec());
+                args.push(entries_read.to_string().into_bytes());
+            }
+            self.write_command(&args)?;
+            for consumer in &group.consumers {
+                self.write_command(&[b"XGROUP" as &[u8], b"CREATECONSUMER", key, &group.name, &consumer.name])?;
+                for id in &consumer.pending {
+                    let Some(pending) = group.pending.iter().find(|pending| pending.id == *id) else {
+                        continue;
+                    };
+                    let id = id.to_string();
+                    let time = pending.delivery_time.to_string();
+                    let count = pending.delivery_count.to_string();
+                    let args: [&[u8]; 12] = [
+                        b"XCLAIM",
+                        key,
+                        &group.name,
+                        &consumer.name,
+                        b"0",
+                        id.as_bytes(),
+                        b"TIME",
+                        time.as_bytes(),
+                        b"RETRYCOUNT",
+                        count.as_bytes(),
+                        b"JUSTID",
+                        b"FORCE",
+                    ];
+                    self.write_command(&args)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EventHandler for AOFWriter {
+    fn handle(&mut self, event: Event) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = match event {
+            Event::RDB(object) => self.write_object(object),
+            Event::AOF(cmd) => self.write_aof(&cmd),
+            Event::Sync(_) => Ok(()),
+        };
+        if let Err(err) = result {
+            self.error = Some(err);
+        }
+    }
+}
+
+fn manifest_seq(line: &str) -> Option<u64> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    tokens.chunks(2).find(|pair| pair.len() == 2 && pair[0] == "seq").and_then(|pair| pair[1].parse().ok())
+}
+
+/// 创建第`seq`个incr文件并将其加入manifest, manifest先写入临时文件再替换, 避免出现不完整的manifest
+fn create_segment(dir: &Path, file_name: &str, manifest: &mut Vec<String>, seq: u64) -> io::Result<BufWriter<File>> {
+    let name = format!("{}.{}.incr.aof", file_name, seq);
+    let output = BufWriter::new(File::create(dir.join(&name))?);
+    manifest.push(format!("file {} seq {} type i", name, seq));
+    let path = dir.join(format!("{}.manifest", file_name));
+    let tmp = dir.join(format!("{}.manifest.tmp", file_name));
+    fs::write(&tmp, manifest.join("\n") + "\n")?;
+    fs::rename(&tmp, &path)?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use std::collections::BTreeMap;
+    use std::io::{Cursor, ErrorKind};
+
+    use byteorder::ReadBytesExt;
+
+    use crate::aof::{AOFReader, AOFWriteOptions, AOFWriter};
+    use crate::cmd::{self, Command, ParseErrorPolicy};
+    use crate::dump::{self, OwnedObject};
+    use crate::error::RedisSyncError;
+    use crate::listener::command_args;
+    use crate::rdb::{
+        Consumer, Entry, ExpireType, Field, Group, Hash, Item, KeyValue, List, Meta, ModuleRegistry, Object,
+        PendingEntry, RawModule, SortedSet, Stream, ID,
+    };
+    use crate::resp::{RespDecode, STAR};
+    use crate::writer::{RDBWriter, WriteOptions};
+    use crate::{to_string, Event, EventHandler};
+
+    #[derive(Default)]
+    struct Record(Vec<String>);
+
+    impl EventHandler for Record {
+        fn handle(&mut self, event: Event) {
+            match event {
+                Event::RDB(Object::String(kv)) => {
+                    self.0.push(format!("rdb {} {}", to_string(kv.key.to_vec()), to_string(kv.value.to_vec())))
+                }
+                Event::AOF(Command::SET(set)) => {
+                    self.0.push(format!("set {} {}", to_string(set.key.to_vec()), to_string(set.value.to_vec())))
+                }
+                Event::AOF(Command::SELECT(select)) => self.0.push(format!("select {}", select.db)),
+                Event::AOF(Command::MULTI) => self.0.push("multi".to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("redis-sync-aof-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn commands(commands: &[&[&str]]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for args in commands {
+            buf.extend(format!("*{}\r\n", args.len()).into_bytes());
+            for arg in *args {
+                buf.extend(format!("${}\r\n{}\r\n", arg.len(), arg).into_bytes());
+            }
+        }
+        buf
+    }
+
+    fn rdb(key: &str, value: &str) -> Vec<u8> {
+        let meta = Meta { db: 0, expire: None, evict: None };
+        let mut writer = RDBWriter::new(Vec::new(), WriteOptions::default()).unwrap();
+        writer
+            .write(&Object::String(KeyValue { key: key.as_bytes(), value: value.as_bytes(), meta: &meta }))
+            .unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn read(reader: &mut AOFReader) -> Vec<String> {
+        let mut record = Record::default();
+        reader.read(&mut record).unwrap();
+        record.0
+    }
+
+    #[test]
+    fn test_read_single_file() {
+        let dir = temp_dir("single");
+        let path = dir.join("appendonly.aof");
+        let mut aof = commands(&[&["SELECT", "0"], &["SET", "a", "1"]]);
+        aof.extend(b"#TS:1700000000\r\n");
+        aof.extend(commands(&[&["set", "b", "2"], &["MULTI"]]));
+        fs::write(&path, &aof).unwrap();
+        assert_eq!(read(&mut AOFReader::new(&path)), vec!["select 0", "set a 1", "set b 2", "multi"]);
+
+        // 以RDB开头的AOF文件
+        let mut aof = rdb("base", "0");
+        aof.extend(commands(&[&["SET", "a", "1"]]));
+        fs::write(&path, &aof).unwrap();
+        assert_eq!(read(&mut AOFReader::new(&path)), vec!["select 0", "rdb base 0", "set a 1"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_manifest() {
+        let dir = temp_dir("manifest");
+        fs::write(dir.join("appendonly.aof.1.base.rdb"), rdb("old", "0")).unwrap();
+        fs::write(dir.join("appendonly.aof.2.base.rdb"), rdb("base", "0")).unwrap();
+        fs::write(dir.join("appendonly.aof.2.incr.aof"), commands(&[&["SET", "a", "1"]])).unwrap();
+        fs::write(dir.join("appendonly.aof.3.incr.aof"), commands(&[&["SET", "b", "2"]])).unwrap();
+        fs::write(
+            dir.join("appendonly.aof.manifest"),
+            "file appendonly.aof.1.base.rdb seq 1 type h\n\
+             file appendonly.aof.2.base.rdb seq 2 type b\n\
+             file appendonly.aof.2.incr.aof seq 2 type i\n\
+             file appendonly.aof.3.incr.aof seq 3 type i\n",
+        )
+        .unwrap();
+        let expected = vec!["select 0", "rdb base 0", "set a 1", "set b 2"];
+        assert_eq!(read(&mut AOFReader::new(&dir)), expected);
+        assert_eq!(read(&mut AOFReader::new(dir.join("appendonly.aof.manifest"))), expected);
+
+        // 纯RESP格式的base文件
+        fs::write(dir.join("appendonly.aof.2.base.aof"), commands(&[&["SET", "base", "0"]])).unwrap();
+        fs::write(
+            dir.join("appendonly.aof.manifest"),
+            "file appendonly.aof.2.base.aof seq 2 type b\nfile appendonly.aof.2.incr.aof seq 2 type i\n",
+        )
+        .unwrap();
+        assert_eq!(read(&mut AOFReader::new(&dir)), vec!["set base 0", "set a 1"]);
+
+        fs::write(dir.join("appendonly.aof.manifest"), "file appendonly.aof.2.incr.aof seq 2 type x\n").unwrap();
+        match AOFReader::new(&dir).read(&mut Record::default()) {
+            Err(RedisSyncError::MalformedAOF { offset, reason, .. }) => {
+                assert_eq!((offset, reason.as_str()), (1, "unknown file type: Some(\"x\")"))
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let dir = temp_dir("truncated");
+        let path = dir.join("appendonly.aof");
+        let mut aof = commands(&[&["SET", "a", "1"], &["SET", "b", "2"]]);
+        aof.truncate(aof.len() - 3);
+        fs::write(&path, &aof).unwrap();
+        assert_eq!(read(&mut AOFReader::new(&path)), vec!["set a 1"]);
+
+        let mut reader = AOFReader::new(&path);
+        reader.load_truncated = false;
+        match reader.read(&mut Record::default()) {
+            Err(RedisSyncError::MalformedAOF { offset, reason, .. }) => {
+                assert_eq!((offset, reason.as_str()), (27, "unexpected end of file"))
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        fs::write(&path, b"SET a 1\r\n").unwrap();
+        assert!(matches!(AOFReader::new(&path).read(&mut Record::default()), Err(RedisSyncError::MalformedAOF { .. })));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 读出文件中的全部命令, 不经过命令解析, 参数以空格连接
+    fn resp_commands(path: &Path) -> Vec<Vec<Vec<u8>>> {
+        let mut input = Cursor::new(fs::read(path).unwrap());
+        let mut commands = Vec::new();
+        loop {
+            match input.read_u8() {
+                Ok(STAR) => commands.push(command_args(input.decode_array().unwrap()).unwrap()),
+                Ok(byte) => panic!("unexpected byte {:#04x}", byte),
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return commands,
+                Err(err) => panic!("{}", err),
+            }
+        }
+    }
+
+    fn joined(commands: &[Vec<Vec<u8>>]) -> Vec<String> {
+        commands.iter().map(|args| args.iter().map(|arg| to_string(arg.clone())).collect::<Vec<_>>().join(" ")).collect()
+    }
+
+    fn send(writer: &mut AOFWriter, commands: &[&[&str]]) {
+        for args in commands {
+            let args = args.iter().map(|arg| arg.as_bytes().to_vec()).collect();
+            cmd::parse(args, writer, ParseErrorPolicy::default()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_write_objects() {
+        let dir = temp_dir("write");
+        let mut writer = AOFWriter::new(&dir, AOFWriteOptions { restore_version: 9, ..Default::default() }).unwrap();
+        let db0 = Meta { db: 0, expire: Some((ExpireType::Second, 1700000000)), evict: None };
+        let db1 = Meta { db: 1, expire: Some((ExpireType::Millisecond, 1700000000123)), evict: None };
+        let db2 = Meta { db: 2, expire: None, evict: None };
+
+        writer.handle(Event::RDB(Object::BOR));
+        writer.handle(Event::RDB(Object::String(KeyValue { key: b"str", value: b"v", meta: &db0 })));
+        let values = [b"a".to_vec(), b"b".to_vec()];
+        writer.handle(Event::RDB(Object::List(List { key: b"list", values: &values, meta: &db1 })));
+        writer.handle(Event::RDB(Object::List(List { key: b"list", values: &values[1..], meta: &db1 })));
+        let items = [Item { member: b"m".to_vec(), score: 1.5 }, Item { member: b"n".to_vec(), score: f64::INFINITY }];
+        writer.handle(Event::RDB(Object::SortedSet(SortedSet { key: b"zset", items: &items, meta: &db2 })));
+        let fields = [
+            Field { name: b"f".to_vec(), value: b"1".to_vec(), expire: None },
+            Field { name: b"g".to_vec(), value: b"2".to_vec(), expire: Some(1700000000999) },
+        ];
+        writer.handle(Event::RDB(Object::Hash(Hash { key: b"hash", fields: &fields, meta: &db2 })));
+
+        let id = |ms, seq| ID { ms, seq };
+        let mut entries = BTreeMap::new();
+        for (entry_id, deleted) in [(id(1, 0), false), (id(2, 0), true)] {
+            let fields = BTreeMap::from([(b"k".to_vec(), b"v".to_vec())]);
+            entries.insert(entry_id, Entry { id: entry_id, deleted, fields });
+        }
+        let group = Group {
+            name: b"g".to_vec(),
+            last_id: id(1, 0),
+            entries_read: Some(1),
+            pending: vec![PendingEntry { id: id(1, 0), delivery_time: 1700000000000, delivery_count: 2 }],
+            consumers: vec![Consumer { name: b"c".to_vec(), seen_time: 0, active_time: None, pending: vec![id(1, 0)] }],
+        };
+        let stream = |entries, groups| Stream {
+            entries,
+            groups,
+            last_id: Some(id(2, 0)),
+            first_id: None,
+            max_deleted_id: None,
+            added_entries_count: None,
+            meta: &db2,
+        };
+        let mut full = stream(entries, vec![group]);
+        full.added_entries_count = Some(2);
+        full.max_deleted_id = Some(id(2, 0));
+        writer.handle(Event::RDB(Object::Stream(b"stream".to_vec(), full)));
+        writer.handle(Event::RDB(Object::Stream(b"empty".to_vec(), stream(BTreeMap::new(), Vec::new()))));
+        let module = RawModule { name: "counter-1".to_string(), version: 3, data: vec![2, 42, 0] };
+        writer.handle(Event::RDB(Object::Module(b"module".to_vec(), Box::new(module), &db2)));
+        writer.handle(Event::RDB(Object::EOR));
+        send(&mut writer, &[&["SET", "a", "1"]]);
+        writer.flush().unwrap();
+
+        let mut commands = resp_commands(&dir.join("appendonly.aof.1.incr.aof"));
+        let restore = commands.remove(commands.len() - 2);
+        assert_eq!(
+            joined(&commands),
+            vec![
+                "SELECT 0",
+                "SET str v",
+                "PEXPIREAT str 1700000000000",
+                "SELECT 1",
+                "RPUSH list a b",
+                "PEXPIREAT list 1700000000123",
+                "RPUSH list b",
+                "PEXPIREAT list 1700000000123",
+                "SELECT 2",
+                "ZADD zset 1.5 m inf n",
+                "HSET hash f 1 g 2",
+                "HPEXPIREAT hash 1700000000999 FIELDS 1 g",
+                "XADD stream 1-0 k v",
+                "XSETID stream 2-0 ENTRIESADDED 2 MAXDELETEDID 2-0",
+                "XGROUP CREATE stream g 1-0 ENTRIESREAD 1",
+                "XGROUP CREATECONSUMER stream g c",
+                "XCLAIM stream g c 0 1-0 TIME 1700000000000 RETRYCOUNT 2 JUSTID FORCE",
+                "XADD empty MAXLEN 0 0-1 x y",
+                "XSETID empty 2-0",
+                "SET a 1",
+            ]
+        );
+        assert_eq!((&restore[..3], &restore[4]), (&[b"RESTORE".to_vec(), b"module".to_vec(), b"0".to_vec()][..], &b"REPLACE".to_vec()));
+        // payload末尾依次为2个字节的RDB版本及8个字节的CRC64
+        assert_eq!(restore[3][restore[3].len() - 10..restore[3].len() - 8], [9, 0]);
+        match dump::decode(b"module", &restore[3], &mut ModuleRegistry::new()).unwrap() {
+            OwnedObject::Module(_, module) => assert_eq!(module.as_any().downcast_ref::<RawModule>().unwrap().data, vec![2, 42, 0]),
+            object => panic!("unexpected object: {:?}", object.key()),
+        }
+        assert_eq!(read(&mut AOFReader::new(&dir)), vec!["select 0", "set str v", "select 1", "select 2", "set a 1"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_rotate() {
+        let dir = temp_dir("rotate");
+        let options = || AOFWriteOptions { max_segment_size: 1, ..Default::default() };
+        let mut writer = AOFWriter::new(&dir, options()).unwrap();
+        send(&mut writer, &[&["SELECT", "1"], &["SET", "a", "1"], &["MULTI"], &["SET", "b", "2"], &["EXEC"], &["SET", "c", "3"]]);
+        writer.flush().unwrap();
+
+        let segment = |seq| joined(&resp_commands(&dir.join(format!("appendonly.aof.{}.incr.aof", seq))));
+        assert_eq!(segment(1), vec!["SELECT 1"]);
+        assert_eq!(segment(2), vec!["SELECT 1", "SET a 1"]);
+        // 事务不会被拆分到两个文件中
+        assert_eq!(segment(3), vec!["SELECT 1", "MULTI", "SET b 2", "EXEC"]);
+        assert_eq!(segment(4), vec!["SELECT 1", "SET c 3"]);
+        assert_eq!(
+            read(&mut AOFReader::new(&dir)),
+            vec!["select 1", "select 1", "set a 1", "select 1", "multi", "set b 2", "select 1", "set c 3"]
+        );
+
+        // 已有manifest时接着写入新的文件
+        drop(writer);
+        let mut writer = AOFWriter::new(&dir, options()).unwrap();
+        send(&mut writer, &[&["SET", "d", "4"]]);
+        writer.flush().unwrap();
+        assert_eq!(segment(5), vec!["SET d 4"]);
+        let manifest = fs::read_to_string(dir.join("appendonly.aof.manifest")).unwrap();
+        assert_eq!(manifest.lines().count(), 5);
+        assert!(manifest.ends_with("file appendonly.aof.5.incr.aof seq 5 type i\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+/*!
+复制进度的持久化
+
+[Listener]在handler处理完数据之后, 通过[Checkpoint]保存master的复制ID与已处理的偏移量,
+进程重启后读取保存的进度发起部分同步, 避免重新下载整个数据集。
+
+[Listener]: ../listener/struct.Listener.html
+[Checkpoint]: trait.Checkpoint.html
+*/
+
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+
+/// 复制进度
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    /// master的复制ID
+    pub repl_id: String,
+    /// 已处理完的复制偏移量
+    pub repl_offset: i64,
+}
+
+/// 复制进度的存储
+pub trait Checkpoint {
+    /// 读取上次保存的复制进度, 从未保存过时返回`None`
+    fn load(&mut self) -> Result<Option<Position>>;
+
+    /// 保存复制进度
+    fn save(&mut self, position: &Position) -> Result<()>;
+}
+
+/// 以文件保存复制进度
+///
+/// 文件内容为一行`<repl_id> <repl_offset>`, 先写入临时文件再rename覆盖, 保证进度文件不会出现写了一半的情况,
+/// rename之后同步所在目录, 保证掉电后rename的结果不会丢失
+pub struct FileCheckpoint {
+    path: PathBuf,
+}
+
+impl FileCheckpoint {
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileCheckpoint {
+        FileCheckpoint { path: path.into() }
+    }
+}
+
+impl Checkpoint for FileCheckpoint {
+    fn load(&mut self) -> Result<Option<Position>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let mut iter = content.split_whitespace();
+        if let (Some(repl_id), Some(repl_offset)) = (iter.next(), iter.next()) {
+            if let Ok(repl_offset) = repl_offset.parse::<i64>() {
+                return Ok(Some(Position {
+                    repl_id: repl_id.to_string(),
+                    repl_offset,
+                }));
+            }
+        }
+        Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid checkpoint file: {}", self.path.display()),
+        ))
+    }
+
+    fn save(&mut self, position: &Position) -> Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = File::create(&tmp)?;
+        writeln!(file, "{} {}", position.repl_id, position.repl_offset)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        sync_dir(&self.path)
+    }
+}
+
+/// 同步`path`所在的目录, 使其中的rename落盘
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+/// 非unix平台无法打开目录, rename的持久性由文件系统保证
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{Checkpoint, FileCheckpoint, Position};
+
+    #[test]
+    fn test_file_checkpoint() {
+        let path = std::env::temp_dir().join(format!("redis-sync-checkpoint-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut checkpoint = FileCheckpoint::new(&path);
+        assert_eq!(checkpoint.load().unwrap(), None);
+
+        let position = Position {
+            repl_id: "8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb".to_string(),
+            repl_offset: 1024,
+        };
+        checkpoint.save(&position).unwrap();
+        assert_eq!(checkpoint.load().unwrap(), Some(position));
+
+        fs::write(&path, "garbage").unwrap();
+        assert!(checkpoint.load().is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
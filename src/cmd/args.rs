@@ -0,0 +1,382 @@
+/*!
+将解析后的命令还原为参数列表
+
+命令名和选项统一还原为大写, 可选参数按固定的顺序写出, 因此不保证与原命令逐字节一致。
+解析时被忽略的参数无法还原, 如`SET`的`GET`选项(只影响返回值), 以及`BITFIELD`中无法识别的`OVERFLOW`类型。
+`XTRIM`只解析了`MAXLEN`策略, 其他策略会被还原为`MAXLEN`。
+*/
+
+use crate::cmd::hashes::{ExpireCondition, FieldExistType};
+use crate::cmd::keys::ORDER;
+use crate::cmd::lists::POSITION;
+use crate::cmd::sorted_sets::AGGREGATE;
+use crate::cmd::strings::{ExistType, ExpireType, Op, Operation, Overflow};
+use crate::cmd::Command;
+
+/// 参数列表的构造器
+struct Argv(Vec<Vec<u8>>);
+
+impl Argv {
+    fn new(name: &str) -> Argv {
+        Argv(vec![name.as_bytes().to_vec()])
+    }
+
+    fn arg<A: AsRef<[u8]>>(mut self, arg: A) -> Argv {
+        self.0.push(arg.as_ref().to_vec());
+        self
+    }
+
+    fn args<A: AsRef<[u8]>, I: IntoIterator<Item = A>>(mut self, args: I) -> Argv {
+        self.0.extend(args.into_iter().map(|arg| arg.as_ref().to_vec()));
+        self
+    }
+
+    /// `Some(true)`时写入`flag`
+    fn flag(self, flag: &str, on: Option<bool>) -> Argv {
+        if on == Some(true) {
+            self.arg(flag)
+        } else {
+            self
+        }
+    }
+
+    /// 有值时写入`name value`
+    fn option<A: AsRef<[u8]>>(self, name: &str, value: Option<A>) -> Argv {
+        match value {
+            Some(value) => self.arg(name).arg(value),
+            None => self,
+        }
+    }
+}
+
+fn expire_type(expire_type: &ExpireType) -> &'static str {
+    match expire_type {
+        ExpireType::EX => "EX",
+        ExpireType::PX => "PX",
+        ExpireType::EXAT => "EXAT",
+        ExpireType::PXAT => "PXAT",
+    }
+}
+
+fn exist_type(exist_type: &Option<ExistType>) -> Option<bool> {
+    exist_type.as_ref().map(|_| true)
+}
+
+fn condition(argv: Argv, condition: &Option<ExpireCondition>) -> Argv {
+    match condition {
+        Some(ExpireCondition::NX) => argv.arg("NX"),
+        Some(ExpireCondition::XX) => argv.arg("XX"),
+        Some(ExpireCondition::GT) => argv.arg("GT"),
+        Some(ExpireCondition::LT) => argv.arg("LT"),
+        None => argv,
+    }
+}
+
+fn fields(argv: Argv, fields: &[&[u8]]) -> Argv {
+    argv.arg("FIELDS").arg(fields.len().to_string()).args(fields)
+}
+
+fn store(argv: Argv, keys: &[&[u8]], weights: &Option<Vec<&[u8]>>, aggregate: &Option<AGGREGATE>) -> Argv {
+    let argv = argv.arg(keys.len().to_string()).args(keys);
+    let argv = match weights {
+        Some(weights) => argv.arg("WEIGHTS").args(weights),
+        None => argv,
+    };
+    match aggregate {
+        Some(AGGREGATE::SUM) => argv.arg("AGGREGATE").arg("SUM"),
+        Some(AGGREGATE::MIN) => argv.arg("AGGREGATE").arg("MIN"),
+        Some(AGGREGATE::MAX) => argv.arg("AGGREGATE").arg("MAX"),
+        None => argv,
+    }
+}
+
+impl Command<'_> {
+    /// 还原为Redis命令的参数列表, 第一个元素为命令名, 可以按RESP数组的格式写入AOF或发送给Redis
+    pub fn to_args(&self) -> Vec<Vec<u8>> {
+        let argv = match self {
+            Command::APPEND(cmd) => Argv::new("APPEND").arg(cmd.key).arg(cmd.value),
+            Command::BITFIELD(cmd) => {
+                let mut argv = Argv::new("BITFIELD").arg(cmd.key);
+                for statement in cmd.statements.iter().flatten() {
+                    argv = match statement {
+                        Operation::GET(get) => argv.arg("GET").arg(get._type).arg(get.offset),
+                        Operation::INCRBY(incr) => argv.arg("INCRBY").arg(incr._type).arg(incr.offset).arg(incr.increment),
+                        Operation::SET(set) => argv.arg("SET").arg(set._type).arg(set.offset).arg(set.value),
+                        Operation::OVERFLOW(overflow) => argv.arg("OVERFLOW").arg(match overflow {
+                            Overflow::WRAP => "WRAP",
+                            Overflow::SAT => "SAT",
+                            Overflow::FAIL => "FAIL",
+                        }),
+                    };
+                }
+                argv
+            }
+            Command::BITOP(cmd) => {
+                let op = match cmd.operation {
+                    Op::AND => "AND",
+                    Op::OR => "OR",
+                    Op::XOR => "XOR",
+                    Op::NOT => "NOT",
+                };
+                Argv::new("BITOP").arg(op).arg(cmd.dest_key).args(&cmd.keys)
+            }
+            Command::BRPOPLPUSH(cmd) => Argv::new("BRPOPLPUSH").arg(cmd.source).arg(cmd.destination).arg(cmd.timeout),
+            Command::DECR(cmd) => Argv::new("DECR").arg(cmd.key),
+            Command::DECRBY(cmd) => Argv::new("DECRBY").arg(cmd.key).arg(cmd.decrement),
+            Command::DEL(cmd) => Argv::new("DEL").args(&cmd.keys),
+            Command::EVAL(cmd) => Argv::new("EVAL")
+                .arg(cmd.script)
+                .arg(cmd.num_keys.to_string())
+                .args(&cmd.keys)
+                .args(&cmd.args),
+            Command::EVALSHA(cmd) => Argv::new("EVALSHA")
+                .arg(cmd.sha1)
+                .arg(cmd.num_keys.to_string())
+                .args(&cmd.keys)
+                .args(&cmd.args),
+            Command::EXPIRE(cmd) => Argv::new("EXPIRE").arg(cmd.key).arg(cmd.seconds),
+            Command::EXPIREAT(cmd) => Argv::new("EXPIREAT").arg(cmd.key).arg(cmd.timestamp),
+            Command::EXEC => Argv::new("EXEC"),
+            Command::FLUSHALL(cmd) => Argv::new("FLUSHALL").flag("ASYNC", cmd._async),
+            Command::FLUSHDB(cmd) => Argv::new("FLUSHDB").flag("ASYNC", cmd._async),
+            Command::GETSET(cmd) => Argv::new("GETSET").arg(cmd.key).arg(cmd.value),
+            Command::HDEL(cmd) => Argv::new("HDEL").arg(cmd.key).args(&cmd.fields),
+            Command::HEXPIRE(cmd) => {
+                let argv = condition(Argv::new("HEXPIRE").arg(cmd.key).arg(cmd.seconds), &cmd.condition);
+                fields(argv, &cmd.fields)
+            }
+            Command::HEXPIREAT(cmd) => {
+                let argv = condition(Argv::new("HEXPIREAT").arg(cmd.key).arg(cmd.timestamp), &cmd.condition);
+                fields(argv, &cmd.fields)
+            }
+            Command::HGETDEL(cmd) => fields(Argv::new("HGETDEL").arg(cmd.key), &cmd.fields),
+            Command::HINCRBY(cmd) => Argv::new("HINCRBY").arg(cmd.key).arg(cmd.field).arg(cmd.increment),
+            Command::HMSET(cmd) => Argv::new("HMSET")
+                .arg(cmd.key)
+                .args(cmd.fields.iter().flat_map(|field| [field.name, field.value])),
+            Command::HPERSIST(cmd) => fields(Argv::new("HPERSIST").arg(cmd.key), &cmd.fields),
+            Command::HPEXPIRE(cmd) => {
+                let argv = condition(Argv::new("HPEXPIRE").arg(cmd.key).arg(cmd.milliseconds), &cmd.condition);
+                fields(argv, &cmd.fields)
+            }
+            Command::HPEXPIREAT(cmd) => {
+                let argv = condition(Argv::new("HPEXPIREAT").arg(cmd.key).arg(cmd.mill_timestamp), &cmd.condition);
+                fields(argv, &cmd.fields)
+            }
+            Command::HSET(cmd) => Argv::new("HSET")
+                .arg(cmd.key)
+                .args(cmd.fields.iter().flat_map(|field| [field.name, field.value])),
+            Command::HSETEX(cmd) => {
+                let mut argv = Argv::new("HSETEX").arg(cmd.key);
+                argv = match cmd.exist_type {
+                    Some(FieldExistType::FNX) => argv.arg("FNX"),
+                    Some(FieldExistType::FXX) => argv.arg("FXX"),
+                    None => argv,
+                };
+                if let Some((expire, time)) = &cmd.expire {
+                    argv = argv.arg(expire_type(expire)).arg(time);
+                }
+                argv.flag("KEEPTTL", cmd.keep_ttl)
+                    .arg("FIELDS")
+                    .arg(cmd.fields.len().to_string())
+                    .args(cmd.fields.iter().flat_map(|field| [field.name, field.value]))
+            }
+            Command::HSETNX(cmd) => Argv::new("HSETNX").arg(cmd.key).arg(cmd.field).arg(cmd.value),
+            Command::INCR(cmd) => Argv::new("INCR").arg(cmd.key),
+            Command::INCRBY(cmd) => Argv::new("INCRBY").arg(cmd.key).arg(cmd.increment),
+            Command::LINSERT(cmd) => {
+                let position = match cmd.position {
+                    POSITION::BEFORE => "BEFORE",
+                    POSITION::AFTER => "AFTER",
+                };
+                Argv::new("LINSERT").arg(cmd.key).arg(position).arg(cmd.pivot).arg(cmd.element)
+            }
+            Command::LPOP(cmd) => Argv::new("LPOP").arg(cmd.key).args(cmd.count),
+            Command::LPUSH(cmd) => Argv::new("LPUSH").arg(cmd.key).args(&cmd.elements),
+            Command::LPUSHX(cmd) => Argv::new("LPUSHX").arg(cmd.key).args(&cmd.elements),
+            Command::LREM(cmd) => Argv::new("LREM").arg(cmd.key).arg(cmd.count).arg(cmd.element),
+            Command::LSET(cmd) => Argv::new("LSET").arg(cmd.key).arg(cmd.index).arg(cmd.element),
+            Command::LTRIM(cmd) => Argv::new("LTRIM").arg(cmd.key).arg(cmd.start).arg(cmd.stop),
+            Command::MOVE(cmd) => Argv::new("MOVE").arg(cmd.key).arg(cmd.db),
+            Command::MSET(cmd) => Argv::new("MSET").args(cmd.key_values.iter().flat_map(|kv| [kv.key, kv.value])),
+            Command::MSETNX(cmd) => Argv::new("MSETNX").args(cmd.key_values.iter().flat_map(|kv| [kv.key, kv.value])),
+            Command::MULTI => Argv::new("MULTI"),
+            Command::PERSIST(cmd) => Argv::new("PERSIST").arg(cmd.key),
+            Command::PEXPIRE(cmd) => Argv::new("PEXPIRE").arg(cmd.key).arg(cmd.milliseconds),
+            Command::PEXPIREAT(cmd) => Argv::new("PEXPIREAT").arg(cmd.key).arg(cmd.mill_timestamp),
+            Command::PFADD(cmd) => Argv::new("PFADD").arg(cmd.key).args(&cmd.elements),
+            Command::PFCOUNT(cmd) => Argv::new("PFCOUNT").args(&cmd.keys),
+            Command::PFMERGE(cmd) => Argv::new("PFMERGE").arg(cmd.dest_key).args(&cmd.source_keys),
+            Command::PSETEX(cmd) => Argv::new("PSETEX").arg(cmd.key).arg(cmd.milliseconds).arg(cmd.value),
+            Command::PUBLISH(cmd) => Argv::new("PUBLISH").arg(cmd.channel).arg(cmd.message),
+            Command::RENAME(cmd) => Argv::new("RENAME").arg(cmd.key).arg(cmd.new_key),
+            Command::RENAMENX(cmd) => Argv::new("RENAMENX").arg(cmd.key).arg(cmd.new_key),
+            Command::RESTORE(cmd) => Argv::new("RESTORE")
+                .arg(cmd.key)
+                .arg(cmd.ttl)
+                .arg(cmd.value)
+                .flag("REPLACE", cmd.replace)
+                .flag("ABSTTL", cmd.abs_ttl)
+                .option("IDLETIME", cmd.idle_time)
+                .option("FREQ", cmd.freq),
+            Command::RPOP(cmd) => Argv::new("RPOP").arg(cmd.key).args(cmd.count),
+            Command::RPOPLPUSH(cmd) => Argv::new("RPOPLPUSH").arg(cmd.source).arg(cmd.destination),
+            Command::RPUSH(cmd) => Argv::new("RPUSH").arg(cmd.key).args(&cmd.elements),
+            Command::RPUSHX(cmd) => Argv::new("RPUSHX").arg(cmd.key).args(&cmd.elements),
+            Command::SADD(cmd) => Argv::new("SADD").arg(cmd.key).args(&cmd.members),
+            Command::SCRIPTFLUSH => Argv::new("SCRIPT").arg("FLUSH"),
+            Command::SCRIPTLOAD(cmd) => Argv::new("SCRIPT").arg("LOAD").arg(cmd.script),
+            Command::SDIFFSTORE(cmd) => Argv::new("SDIFFSTORE").arg(cmd.destination).args(&cmd.keys),
+            Command::SET(cmd) => {
+                let mut argv = Argv::new("SET").arg(cmd.key).arg(cmd.value);
+                if let Some((expire, time)) = &cmd.expire {
+                    argv = argv.arg(expire_type(expire)).arg(time);
+                }
+                argv = match cmd.exist_type {
+                    Some(ExistType::NX) => argv.arg("NX"),
+                    Some(ExistType::XX) => argv.arg("XX"),
+                    None => argv,
+                };
+                argv.flag("KEEPTTL", cmd.keep_ttl)
+            }
+            Command::SETBIT(cmd) => Argv::new("SETBIT").arg(cmd.key).arg(cmd.offset).arg(cmd.value),
+            Command::SETEX(cmd) => Argv::new("SETEX").arg(cmd.key).arg(cmd.seconds).arg(cmd.value),
+            Command::SETNX(cmd) => Argv::new("SETNX").arg(cmd.key).arg(cmd.value),
+            Command::SELECT(cmd) => Argv::new("SELECT").arg(cmd.db.to_string()),
+            Command::SETRANGE(cmd) => Argv::new("SETRANGE").arg(cmd.key).arg(cmd.offset).arg(cmd.value),
+            Command::SINTERSTORE(cmd) => Argv::new("SINTERSTORE").arg(cmd.destination).args(&cmd.keys),
+            Command::SMOVE(cmd) => Argv::new("SMOVE").arg(cmd.source).arg(cmd.destination).arg(cmd.member),
+            Command::SORT(cmd) => {
+                let mut argv = Argv::new("SORT").arg(cmd.key).option("BY", cmd.by_pattern);
+                if let Some(limit) = &cmd.limit {
+                    argv = argv.arg("LIMIT").arg(limit.offset).arg(limit.count);
+                }
+                for pattern in cmd.get_patterns.iter().flatten() {
+                    argv = argv.arg("GET").arg(pattern);
+                }
+                argv = match cmd.order {
+                    Some(ORDER::ASC) => argv.arg("ASC"),
+                    Some(ORDER::DESC) => argv.arg("DESC"),
+                    None => argv,
+                };
+                argv.flag("ALPHA", cmd.alpha).option("STORE", cmd.destination)
+            }
+            Command::SREM(cmd) => Argv::new("SREM").arg(cmd.key).args(&cmd.members),
+            Command::SUNIONSTORE(cmd) => Argv::new("SUNIONSTORE").arg(cmd.destination).args(&cmd.keys),
+            Command::SWAPDB(cmd) => Argv::new("SWAPDB").arg(cmd.index1).arg(cmd.index2),
+            Command::UNLINK(cmd) => Argv::new("UNLINK").args(&cmd.keys),
+            Command::ZADD(cmd) => {
+                let mut argv = Argv::new("ZADD").arg(cmd.key);
+                argv = match cmd.exist_type {
+                    Some(ExistType::NX) => argv.arg("NX"),
+                    Some(ExistType::XX) => argv.arg("XX"),
+                    None => argv,
+                };
+                argv.flag("CH", cmd.ch)
+                    .flag("INCR", cmd.incr)
+                    .args(cmd.items.iter().flat_map(|item| [item.score, item.member]))
+            }
+            Command::ZINCRBY(cmd) => Argv::new("ZINCRBY").arg(cmd.key).arg(cmd.increment).arg(cmd.member),
+            Command::ZINTERSTORE(cmd) => store(Argv::new("ZINTERSTORE").arg(cmd.destination), &cmd.keys, &cmd.weights, &cmd.aggregate),
+            Command::ZPOPMAX(cmd) => Argv::new("ZPOPMAX").arg(cmd.key).args(cmd.count),
+            Command::ZPOPMIN(cmd) => Argv::new("ZPOPMIN").arg(cmd.key).args(cmd.count),
+            Command::ZREM(cmd) => Argv::new("ZREM").arg(cmd.key).args(&cmd.members),
+            Command::ZREMRANGEBYLEX(cmd) => Argv::new("ZREMRANGEBYLEX").arg(cmd.key).arg(cmd.min).arg(cmd.max),
+            Command::ZREMRANGEBYRANK(cmd) => Argv::new("ZREMRANGEBYRANK").arg(cmd.key).arg(cmd.start).arg(cmd.stop),
+            Command::ZREMRANGEBYSCORE(cmd) => Argv::new("ZREMRANGEBYSCORE").arg(cmd.key).arg(cmd.min).arg(cmd.max),
+            Command::ZUNIONSTORE(cmd) => store(Argv::new("ZUNIONSTORE").arg(cmd.destination), &cmd.keys, &cmd.weights, &cmd.aggregate),
+            Command::XACK(cmd) => Argv::new("XACK").arg(cmd.key).arg(cmd.group).args(&cmd.ids),
+            Command::XADD(cmd) => Argv::new("XADD")
+                .arg(cmd.key)
+                .arg(cmd.id)
+                .args(cmd.fields.iter().flat_map(|field| [field.name, field.value])),
+            Command::XCLAIM(cmd) => Argv::new("XCLAIM")
+                .arg(cmd.key)
+                .arg(cmd.group)
+                .arg(cmd.consumer)
+                .arg(cmd.min_idle_time)
+                .args(&cmd.ids)
+                .option("IDLE", cmd.idle)
+                .option("TIME", cmd.time)
+                .option("RETRYCOUNT", cmd.retry_count)
+                .flag("FORCE", cmd.force)
+                .flag("JUSTID", cmd.just_id),
+            Command::XDEL(cmd) => Argv::new("XDEL").arg(cmd.key).args(&cmd.ids),
+            Command::XGROUP(cmd) => {
+                // 一条XGROUP命令只包含一个子命令
+                let argv = Argv::new("XGROUP");
+                if let Some(create) = &cmd.create {
+                    argv.arg("CREATE").arg(create.key).arg(create.group_name).arg(create.id)
+                } else if let Some(set_id) = &cmd.set_id {
+                    argv.arg("SETID").arg(set_id.key).arg(set_id.group_name).arg(set_id.id)
+                } else if let Some(destroy) = &cmd.destroy {
+                    argv.arg("DESTROY").arg(destroy.key).arg(destroy.group_name)
+                } else if let Some(del) = &cmd.del_consumer {
+                    argv.arg("DELCONSUMER").arg(del.key).arg(del.group_name).arg(del.consumer_name)
+                } else {
+                    argv
+                }
+            }
+            Command::XTRIM(cmd) => {
+                let argv = Argv::new("XTRIM").arg(cmd.key).arg("MAXLEN");
+                let argv = if cmd.approximation { argv.arg("~") } else { argv };
+                argv.arg(cmd.count.to_string())
+            }
+            Command::Other(cmd) => Argv::new(&cmd.name).args(&cmd.args),
+        };
+        argv.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cmd::{parse, Command, ParseErrorPolicy};
+    use crate::{Event, EventHandler};
+
+    struct ToArgs(Vec<Vec<u8>>);
+
+    impl EventHandler for ToArgs {
+        fn handle(&mut self, event: Event) {
+            if let Event::AOF(cmd) = event {
+                self.0 = cmd.to_args();
+            }
+        }
+    }
+
+    fn round_trip(cmd: &str) -> String {
+        let args = cmd.split(' ').map(|arg| arg.as_bytes().to_vec()).collect();
+        let mut handler = ToArgs(Vec::new());
+        parse(args, &mut handler, ParseErrorPolicy::Fail).unwrap();
+        handler.0.iter().map(|arg| String::from_utf8_lossy(arg)).collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn test_to_args() {
+        for cmd in [
+            "SET key value PXAT 1700000000000 NX",
+            "SET key value KEEPTTL",
+            "HSET h f1 v1 f2 v2",
+            "HSETEX h FNX EX 10 FIELDS 2 f1 v1 f2 v2",
+            "HPEXPIREAT h 1700000000000 GT FIELDS 1 f1",
+            "ZADD z XX CH 1 a 2.5 b",
+            "ZUNIONSTORE dest 2 z1 z2 WEIGHTS 1 2 AGGREGATE MAX",
+            "LPOP list",
+            "LPOP list 3",
+            "RPOP list 2",
+            "BITFIELD b SET u8 0 255 OVERFLOW SAT INCRBY u8 0 10 OVERFLOW FAIL INCRBY u8 8 1 GET u8 0",
+            "XADD s 1-1 f v",
+            "XTRIM s MAXLEN ~ 100",
+            "XGROUP CREATE s g $",
+            "RESTORE key 0 payload REPLACE IDLETIME 10",
+            "EVAL script 1 k a1 a2",
+            "SORT list BY w_* LIMIT 0 10 GET o_* DESC ALPHA STORE dest",
+            "SCRIPT FLUSH",
+            "SELECT 3",
+            "CLIENT SETNAME x",
+        ] {
+            assert_eq!(round_trip(cmd), cmd);
+        }
+        assert_eq!(round_trip("set key value nx px 100"), "SET key value PX 100 NX");
+        assert_eq!(Command::MULTI.to_args(), vec![b"MULTI".to_vec()]);
+    }
+}
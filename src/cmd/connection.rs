@@ -6,17 +6,16 @@ Connection相关的命令定义、解析
 [Redis Command Reference]: https://redis.io/commands#connection
 */
 
-use std::slice::Iter;
+use crate::cmd::{Args, Result};
 
 #[derive(Debug)]
 pub struct SELECT {
     pub db: i32,
 }
 
-pub(crate) fn parse_select(mut iter: Iter<Vec<u8>>) -> SELECT {
-    let db = String::from_utf8_lossy(iter.next().unwrap());
-    let db = db.parse::<i32>().unwrap();
-    SELECT { db }
+pub(crate) fn parse_select(mut iter: Args) -> Result<SELECT> {
+    let db = iter.parse_arg::<i32>()?;
+    Ok(SELECT { db })
 }
 
 #[derive(Debug)]
@@ -25,8 +24,8 @@ pub struct SWAPDB<'a> {
     pub index2: &'a [u8],
 }
 
-pub(crate) fn parse_swapdb(mut iter: Iter<Vec<u8>>) -> SWAPDB {
-    let index1 = iter.next().unwrap();
-    let index2 = iter.next().unwrap();
-    SWAPDB { index1, index2 }
+pub(crate) fn parse_swapdb(mut iter: Args) -> Result<SWAPDB> {
+    let index1 = iter.next_arg()?;
+    let index2 = iter.next_arg()?;
+    Ok(SWAPDB { index1, index2 })
 }
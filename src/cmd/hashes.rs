@@ -6,7 +6,8 @@ Hashes相关的命令定义、解析
 [Redis Command Reference]: https://redis.io/commands#hash
 */
 
-use std::slice::Iter;
+use crate::cmd::strings::ExpireType;
+use crate::cmd::{Args, Result};
 
 #[derive(Debug)]
 pub struct HDEL<'a> {
@@ -14,13 +15,13 @@ pub struct HDEL<'a> {
     pub fields: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_hdel(mut iter: Iter<Vec<u8>>) -> HDEL {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_hdel(mut iter: Args) -> Result<HDEL> {
+    let key = iter.next_arg()?;
     let mut fields = Vec::new();
     for field in iter {
         fields.push(field.as_slice());
     }
-    HDEL { key, fields }
+    Ok(HDEL { key, fields })
 }
 
 #[derive(Debug)]
@@ -30,11 +31,11 @@ pub struct HINCRBY<'a> {
     pub increment: &'a [u8],
 }
 
-pub(crate) fn parse_hincrby(mut iter: Iter<Vec<u8>>) -> HINCRBY {
-    let key = iter.next().unwrap();
-    let field = iter.next().unwrap();
-    let increment = iter.next().unwrap();
-    HINCRBY { key, field, increment }
+pub(crate) fn parse_hincrby(mut iter: Args) -> Result<HINCRBY> {
+    let key = iter.next_arg()?;
+    let field = iter.next_arg()?;
+    let increment = iter.next_arg()?;
+    Ok(HINCRBY { key, field, increment })
 }
 
 #[derive(Debug)]
@@ -55,8 +56,8 @@ pub struct Field<'a> {
     pub value: &'a [u8],
 }
 
-pub(crate) fn parse_hmset(mut iter: Iter<Vec<u8>>) -> HMSET {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_hmset(mut iter: Args) -> Result<HMSET> {
+    let key = iter.next_arg()?;
     let mut fields = Vec::new();
     // loop {
     //     if let Some(field) = iter.next() {
@@ -71,18 +72,14 @@ pub(crate) fn parse_hmset(mut iter: Iter<Vec<u8>>) -> HMSET {
     //     }
     // }
     while let Some(field) = iter.next() {
-        if let Some(value) = iter.next() {
-            let field = Field { name: field, value };
-            fields.push(field);
-        } else {
-            panic!("HMSET缺失field value");
-        }
+        let value = iter.next_arg()?;
+        fields.push(Field { name: field, value });
     } 
-    HMSET { key, fields }
+    Ok(HMSET { key, fields })
 }
 
-pub(crate) fn parse_hset(mut iter: Iter<Vec<u8>>) -> HSET {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_hset(mut iter: Args) -> Result<HSET> {
+    let key = iter.next_arg()?;
     let mut fields = Vec::new();
     // loop {
     //     if let Some(field) = iter.next() {
@@ -97,14 +94,10 @@ pub(crate) fn parse_hset(mut iter: Iter<Vec<u8>>) -> HSET {
     //     }
     // }
     while let Some(field) = iter.next() {
-        if let Some(value) = iter.next() {
-            let field = Field { name: field, value };
-            fields.push(field);
-        } else {
-            panic!("HSET缺失field value");
-        }
+        let value = iter.next_arg()?;
+        fields.push(Field { name: field, value });
     }
-    HSET { key, fields }
+    Ok(HSET { key, fields })
 }
 
 #[derive(Debug)]
@@ -114,9 +107,198 @@ pub struct HSETNX<'a> {
     pub value: &'a [u8],
 }
 
-pub(crate) fn parse_hsetnx(mut iter: Iter<Vec<u8>>) -> HSETNX {
-    let key = iter.next().unwrap();
-    let field = iter.next().unwrap();
-    let value = iter.next().unwrap();
-    HSETNX { key, field, value }
+pub(crate) fn parse_hsetnx(mut iter: Args) -> Result<HSETNX> {
+    let key = iter.next_arg()?;
+    let field = iter.next_arg()?;
+    let value = iter.next_arg()?;
+    Ok(HSETNX { key, field, value })
+}
+
+/// 字段过期时间的设置条件
+#[derive(Debug)]
+pub enum ExpireCondition {
+    // Set expiration only when the field has no expiration.
+    NX,
+    // Set expiration only when the field has an existing expiration.
+    XX,
+    // Set expiration only when the new expiration is greater than current one.
+    GT,
+    // Set expiration only when the new expiration is less than current one.
+    LT,
+}
+
+#[derive(Debug)]
+pub struct HEXPIRE<'a> {
+    pub key: &'a [u8],
+    pub seconds: &'a [u8],
+    pub condition: Option<ExpireCondition>,
+    pub fields: Vec<&'a [u8]>,
+}
+
+pub(crate) fn parse_hexpire(mut iter: Args) -> Result<HEXPIRE> {
+    let key = iter.next_arg()?;
+    let seconds = iter.next_arg()?;
+    let (condition, fields) = parse_field_expire(&mut iter)?;
+    Ok(HEXPIRE { key, seconds, condition, fields })
+}
+
+#[derive(Debug)]
+pub struct HPEXPIRE<'a> {
+    pub key: &'a [u8],
+    pub milliseconds: &'a [u8],
+    pub condition: Option<ExpireCondition>,
+    pub fields: Vec<&'a [u8]>,
+}
+
+pub(crate) fn parse_hpexpire(mut iter: Args) -> Result<HPEXPIRE> {
+    let key = iter.next_arg()?;
+    let milliseconds = iter.next_arg()?;
+    let (condition, fields) = parse_field_expire(&mut iter)?;
+    Ok(HPEXPIRE { key, milliseconds, condition, fields })
+}
+
+#[derive(Debug)]
+pub struct HEXPIREAT<'a> {
+    pub key: &'a [u8],
+    pub timestamp: &'a [u8],
+    pub condition: Option<ExpireCondition>,
+    pub fields: Vec<&'a [u8]>,
+}
+
+pub(crate) fn parse_hexpireat(mut iter: Args) -> Result<HEXPIREAT> {
+    let key = iter.next_arg()?;
+    let timestamp = iter.next_arg()?;
+    let (condition, fields) = parse_field_expire(&mut iter)?;
+    Ok(HEXPIREAT { key, timestamp, condition, fields })
+}
+
+#[derive(Debug)]
+pub struct HPEXPIREAT<'a> {
+    pub key: &'a [u8],
+    pub mill_timestamp: &'a [u8],
+    pub condition: Option<ExpireCondition>,
+    pub fields: Vec<&'a [u8]>,
+}
+
+pub(crate) fn parse_hpexpireat(mut iter: Args) -> Result<HPEXPIREAT> {
+    let key = iter.next_arg()?;
+    let mill_timestamp = iter.next_arg()?;
+    let (condition, fields) = parse_field_expire(&mut iter)?;
+    Ok(HPEXPIREAT {
+        key,
+        mill_timestamp,
+        condition,
+        fields,
+    })
+}
+
+#[derive(Debug)]
+pub struct HPERSIST<'a> {
+    pub key: &'a [u8],
+    pub fields: Vec<&'a [u8]>,
+}
+
+pub(crate) fn parse_hpersist(mut iter: Args) -> Result<HPERSIST> {
+    let key = iter.next_arg()?;
+    let fields = parse_fields(&mut iter)?;
+    Ok(HPERSIST { key, fields })
+}
+
+#[derive(Debug)]
+pub struct HGETDEL<'a> {
+    pub key: &'a [u8],
+    pub fields: Vec<&'a [u8]>,
+}
+
+pub(crate) fn parse_hgetdel(mut iter: Args) -> Result<HGETDEL> {
+    let key = iter.next_arg()?;
+    let fields = parse_fields(&mut iter)?;
+    Ok(HGETDEL { key, fields })
+}
+
+#[derive(Debug)]
+pub struct HSETEX<'a> {
+    pub key: &'a [u8],
+    pub exist_type: Option<FieldExistType>,
+    pub expire: Option<(ExpireType, &'a [u8])>,
+    pub keep_ttl: Option<bool>,
+    pub fields: Vec<Field<'a>>,
+}
+
+#[derive(Debug)]
+pub enum FieldExistType {
+    // Only set the fields if none of them already exist.
+    FNX,
+    // Only set the fields if all of them already exist.
+    FXX,
+}
+
+pub(crate) fn parse_hsetex(mut iter: Args) -> Result<HSETEX> {
+    let key = iter.next_arg()?;
+    let mut exist_type = None;
+    let mut expire = None;
+    let mut keep_ttl = None;
+    loop {
+        let arg = String::from_utf8_lossy(iter.next_arg()?).to_uppercase();
+        match arg.as_str() {
+            "FNX" => exist_type = Some(FieldExistType::FNX),
+            "FXX" => exist_type = Some(FieldExistType::FXX),
+            "EX" => expire = Some((ExpireType::EX, iter.next_arg()?.as_slice())),
+            "PX" => expire = Some((ExpireType::PX, iter.next_arg()?.as_slice())),
+            "EXAT" => expire = Some((ExpireType::EXAT, iter.next_arg()?.as_slice())),
+            "PXAT" => expire = Some((ExpireType::PXAT, iter.next_arg()?.as_slice())),
+            "KEEPTTL" => keep_ttl = Some(true),
+            "FIELDS" => break,
+            _ => return Err(iter.invalid(format!("unknown option: {}", arg))),
+        }
+    }
+    let num_fields = iter.parse_arg::<usize>()?;
+    let mut fields = Vec::with_capacity(num_fields);
+    for _ in 0..num_fields {
+        let name = iter.next_arg()?;
+        let value = iter.next_arg()?;
+        fields.push(Field { name, value });
+    }
+    Ok(HSETEX {
+        key,
+        exist_type,
+        expire,
+        keep_ttl,
+        fields,
+    })
+}
+
+/// 解析`[NX | XX | GT | LT] FIELDS numfields field [field ...]`
+fn parse_field_expire<'a>(iter: &mut Args<'a>) -> Result<(Option<ExpireCondition>, Vec<&'a [u8]>)> {
+    let mut condition = None;
+    loop {
+        let arg = String::from_utf8_lossy(iter.next_arg()?).to_uppercase();
+        match arg.as_str() {
+            "NX" => condition = Some(ExpireCondition::NX),
+            "XX" => condition = Some(ExpireCondition::XX),
+            "GT" => condition = Some(ExpireCondition::GT),
+            "LT" => condition = Some(ExpireCondition::LT),
+            "FIELDS" => break,
+            _ => return Err(iter.invalid(format!("unknown option: {}", arg))),
+        }
+    }
+    Ok((condition, read_fields(iter)?))
+}
+
+/// 解析`FIELDS numfields field [field ...]`
+fn parse_fields<'a>(iter: &mut Args<'a>) -> Result<Vec<&'a [u8]>> {
+    let arg = iter.next_arg()?;
+    if !arg.eq_ignore_ascii_case(b"FIELDS") {
+        return Err(iter.invalid("expect FIELDS"));
+    }
+    read_fields(iter)
+}
+
+fn read_fields<'a>(iter: &mut Args<'a>) -> Result<Vec<&'a [u8]>> {
+    let num_fields = iter.parse_arg::<usize>()?;
+    let mut fields = Vec::with_capacity(num_fields);
+    for _ in 0..num_fields {
+        fields.push(iter.next_arg()?.as_slice());
+    }
+    Ok(fields)
 }
@@ -6,7 +6,7 @@ HyperLogLog相关的命令定义、解析
 [Redis Command Reference]: https://redis.io/commands#hyperloglog
 */
 
-use std::slice::Iter;
+use crate::cmd::{Args, Result};
 
 #[derive(Debug)]
 pub struct PFADD<'a> {
@@ -14,13 +14,13 @@ pub struct PFADD<'a> {
     pub elements: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_pfadd(mut iter: Iter<Vec<u8>>) -> PFADD {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_pfadd(mut iter: Args) -> Result<PFADD> {
+    let key = iter.next_arg()?;
     let mut elements = Vec::new();
     for element in iter {
         elements.push(element.as_slice());
     }
-    PFADD { key, elements }
+    Ok(PFADD { key, elements })
 }
 
 #[derive(Debug)]
@@ -28,12 +28,12 @@ pub struct PFCOUNT<'a> {
     pub keys: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_pfcount(iter: Iter<Vec<u8>>) -> PFCOUNT {
+pub(crate) fn parse_pfcount(iter: Args) -> Result<PFCOUNT> {
     let mut keys = Vec::new();
     for key in iter {
         keys.push(key.as_slice());
     }
-    PFCOUNT { keys }
+    Ok(PFCOUNT { keys })
 }
 
 #[derive(Debug)]
@@ -42,11 +42,11 @@ pub struct PFMERGE<'a> {
     pub source_keys: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_pfmerge(mut iter: Iter<Vec<u8>>) -> PFMERGE {
-    let dest_key = iter.next().unwrap();
+pub(crate) fn parse_pfmerge(mut iter: Args) -> Result<PFMERGE> {
+    let dest_key = iter.next_arg()?;
     let mut source_keys = Vec::new();
     for source in iter {
         source_keys.push(source.as_slice());
     }
-    PFMERGE { dest_key, source_keys }
+    Ok(PFMERGE { dest_key, source_keys })
 }
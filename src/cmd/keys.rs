@@ -6,7 +6,10 @@ Keys相关的命令定义、解析
 [Redis Command Reference]: https://redis.io/commands#generic
 */
 
-use std::slice::Iter;
+use crate::cmd::{Args, Result};
+use crate::dump::{self, OwnedObject};
+use crate::error::RedisSyncError;
+use crate::rdb::ModuleRegistry;
 
 use crate::cmd::keys::ORDER::{ASC, DESC};
 
@@ -15,12 +18,12 @@ pub struct DEL<'a> {
     pub keys: Vec<&'a Vec<u8>>,
 }
 
-pub(crate) fn parse_del(iter: Iter<Vec<u8>>) -> DEL {
+pub(crate) fn parse_del(iter: Args) -> Result<DEL> {
     let mut keys = Vec::new();
     for next_key in iter {
         keys.push(next_key);
     }
-    DEL { keys }
+    Ok(DEL { keys })
 }
 
 #[derive(Debug)]
@@ -28,9 +31,9 @@ pub struct PERSIST<'a> {
     pub key: &'a [u8],
 }
 
-pub(crate) fn parse_persist(mut iter: Iter<Vec<u8>>) -> PERSIST {
-    let key = iter.next().unwrap();
-    PERSIST { key }
+pub(crate) fn parse_persist(mut iter: Args) -> Result<PERSIST> {
+    let key = iter.next_arg()?;
+    Ok(PERSIST { key })
 }
 
 #[derive(Debug)]
@@ -39,10 +42,10 @@ pub struct EXPIRE<'a> {
     pub seconds: &'a [u8],
 }
 
-pub(crate) fn parse_expire(mut iter: Iter<Vec<u8>>) -> EXPIRE {
-    let key = iter.next().unwrap();
-    let seconds = iter.next().unwrap();
-    EXPIRE { key, seconds }
+pub(crate) fn parse_expire(mut iter: Args) -> Result<EXPIRE> {
+    let key = iter.next_arg()?;
+    let seconds = iter.next_arg()?;
+    Ok(EXPIRE { key, seconds })
 }
 
 #[derive(Debug)]
@@ -51,10 +54,10 @@ pub struct PEXPIRE<'a> {
     pub milliseconds: &'a [u8],
 }
 
-pub(crate) fn parse_pexpire(mut iter: Iter<Vec<u8>>) -> PEXPIRE {
-    let key = iter.next().unwrap();
-    let milliseconds = iter.next().unwrap();
-    PEXPIRE { key, milliseconds }
+pub(crate) fn parse_pexpire(mut iter: Args) -> Result<PEXPIRE> {
+    let key = iter.next_arg()?;
+    let milliseconds = iter.next_arg()?;
+    Ok(PEXPIRE { key, milliseconds })
 }
 
 #[derive(Debug)]
@@ -63,10 +66,10 @@ pub struct EXPIREAT<'a> {
     pub timestamp: &'a [u8],
 }
 
-pub(crate) fn parse_expireat(mut iter: Iter<Vec<u8>>) -> EXPIREAT {
-    let key = iter.next().unwrap();
-    let timestamp = iter.next().unwrap();
-    EXPIREAT { key, timestamp }
+pub(crate) fn parse_expireat(mut iter: Args) -> Result<EXPIREAT> {
+    let key = iter.next_arg()?;
+    let timestamp = iter.next_arg()?;
+    Ok(EXPIREAT { key, timestamp })
 }
 
 #[derive(Debug)]
@@ -75,10 +78,10 @@ pub struct PEXPIREAT<'a> {
     pub mill_timestamp: &'a [u8],
 }
 
-pub(crate) fn parse_pexpireat(mut iter: Iter<Vec<u8>>) -> PEXPIREAT {
-    let key = iter.next().unwrap();
-    let mill_timestamp = iter.next().unwrap();
-    PEXPIREAT { key, mill_timestamp }
+pub(crate) fn parse_pexpireat(mut iter: Args) -> Result<PEXPIREAT> {
+    let key = iter.next_arg()?;
+    let mill_timestamp = iter.next_arg()?;
+    Ok(PEXPIREAT { key, mill_timestamp })
 }
 
 #[derive(Debug)]
@@ -87,10 +90,10 @@ pub struct MOVE<'a> {
     pub db: &'a [u8],
 }
 
-pub(crate) fn parse_move(mut iter: Iter<Vec<u8>>) -> MOVE {
-    let key = iter.next().unwrap();
-    let db = iter.next().unwrap();
-    MOVE { key, db }
+pub(crate) fn parse_move(mut iter: Args) -> Result<MOVE> {
+    let key = iter.next_arg()?;
+    let db = iter.next_arg()?;
+    Ok(MOVE { key, db })
 }
 
 #[derive(Debug)]
@@ -99,10 +102,10 @@ pub struct RENAME<'a> {
     pub new_key: &'a [u8],
 }
 
-pub(crate) fn parse_rename(mut iter: Iter<Vec<u8>>) -> RENAME {
-    let key = iter.next().unwrap();
-    let new_key = iter.next().unwrap();
-    RENAME { key, new_key }
+pub(crate) fn parse_rename(mut iter: Args) -> Result<RENAME> {
+    let key = iter.next_arg()?;
+    let new_key = iter.next_arg()?;
+    Ok(RENAME { key, new_key })
 }
 
 #[derive(Debug)]
@@ -111,10 +114,10 @@ pub struct RENAMENX<'a> {
     pub new_key: &'a [u8],
 }
 
-pub(crate) fn parse_renamenx(mut iter: Iter<Vec<u8>>) -> RENAMENX {
-    let key = iter.next().unwrap();
-    let new_key = iter.next().unwrap();
-    RENAMENX { key, new_key }
+pub(crate) fn parse_renamenx(mut iter: Args) -> Result<RENAMENX> {
+    let key = iter.next_arg()?;
+    let new_key = iter.next_arg()?;
+    Ok(RENAMENX { key, new_key })
 }
 
 #[derive(Debug)]
@@ -128,10 +131,19 @@ pub struct RESTORE<'a> {
     pub freq: Option<&'a [u8]>,
 }
 
-pub(crate) fn parse_restore(mut iter: Iter<Vec<u8>>) -> RESTORE {
-    let key = iter.next().unwrap();
-    let ttl = iter.next().unwrap();
-    let value = iter.next().unwrap();
+impl RESTORE<'_> {
+    /// 解析`value`中的DUMP payload, 见[dump::decode]
+    ///
+    /// [dump::decode]: ../../dump/fn.decode.html
+    pub fn decode(&self, modules: &mut ModuleRegistry) -> std::result::Result<OwnedObject, RedisSyncError> {
+        dump::decode(self.key, self.value, modules)
+    }
+}
+
+pub(crate) fn parse_restore(mut iter: Args) -> Result<RESTORE> {
+    let key = iter.next_arg()?;
+    let ttl = iter.next_arg()?;
+    let value = iter.next_arg()?;
     let mut replace = None;
     let mut abs_ttl = None;
     let mut idle_time = None;
@@ -143,12 +155,12 @@ pub(crate) fn parse_restore(mut iter: Iter<Vec<u8>>) -> RESTORE {
         } else if &arg == "ABSTTL" {
             abs_ttl = Some(true);
         } else if &arg == "IDLETIME" {
-            idle_time = Some(iter.next().unwrap().as_slice());
+            idle_time = Some(iter.next_arg()?.as_slice());
         } else if &arg == "FREQ" {
-            freq = Some(iter.next().unwrap().as_slice());
+            freq = Some(iter.next_arg()?.as_slice());
         }
     }
-    RESTORE {
+    Ok(RESTORE {
         key,
         ttl,
         value,
@@ -156,7 +168,7 @@ pub(crate) fn parse_restore(mut iter: Iter<Vec<u8>>) -> RESTORE {
         abs_ttl,
         idle_time,
         freq,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -182,8 +194,8 @@ pub enum ORDER {
     DESC,
 }
 
-pub(crate) fn parse_sort(mut iter: Iter<Vec<u8>>) -> SORT {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_sort(mut iter: Args) -> Result<SORT> {
+    let key = iter.next_arg()?;
     let mut order = None;
     let mut alpha = None;
     let mut limit = None;
@@ -201,24 +213,24 @@ pub(crate) fn parse_sort(mut iter: Iter<Vec<u8>>) -> SORT {
         } else if &arg_upper == "ALPHA" {
             alpha = Some(true);
         } else if &arg_upper == "LIMIT" {
-            let offset = iter.next().unwrap();
-            let count = iter.next().unwrap();
+            let offset = iter.next_arg()?;
+            let count = iter.next_arg()?;
             limit = Some(LIMIT { offset, count });
         } else if &arg_upper == "STORE" {
-            let store = iter.next().unwrap();
+            let store = iter.next_arg()?;
             destination = Some(store.as_slice());
         } else if &arg_upper == "BY" {
-            let pattern = iter.next().unwrap();
+            let pattern = iter.next_arg()?;
             by_pattern = Some(pattern.as_slice());
         } else if &arg_upper == "GET" {
-            let next_pattern = iter.next().unwrap();
+            let next_pattern = iter.next_arg()?;
             patterns.push(next_pattern.as_slice());
         }
     }
     if !patterns.is_empty() {
         get_patterns = Some(patterns);
     }
-    SORT {
+    Ok(SORT {
         key,
         by_pattern,
         limit,
@@ -226,7 +238,7 @@ pub(crate) fn parse_sort(mut iter: Iter<Vec<u8>>) -> SORT {
         order,
         alpha,
         destination,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -234,10 +246,10 @@ pub struct UNLINK<'a> {
     pub keys: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_unlink(iter: Iter<Vec<u8>>) -> UNLINK {
+pub(crate) fn parse_unlink(iter: Args) -> Result<UNLINK> {
     let mut keys = Vec::new();
     for next_key in iter {
         keys.push(next_key.as_slice());
     }
-    UNLINK { keys }
+    Ok(UNLINK { keys })
 }
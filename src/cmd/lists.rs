@@ -7,7 +7,7 @@ Lists相关的命令定义、解析
 [Redis Command Reference]: https://redis.io/commands#list
 */
 
-use std::slice::Iter;
+use crate::cmd::{Args, Result};
 
 use crate::cmd::lists::POSITION::{AFTER, BEFORE};
 
@@ -18,15 +18,15 @@ pub struct BRPOPLPUSH<'a> {
     pub timeout: &'a [u8],
 }
 
-pub(crate) fn parse_brpoplpush(mut iter: Iter<Vec<u8>>) -> BRPOPLPUSH {
-    let source = iter.next().unwrap();
-    let destination = iter.next().unwrap();
-    let timeout = iter.next().unwrap();
-    BRPOPLPUSH {
+pub(crate) fn parse_brpoplpush(mut iter: Args) -> Result<BRPOPLPUSH> {
+    let source = iter.next_arg()?;
+    let destination = iter.next_arg()?;
+    let timeout = iter.next_arg()?;
+    Ok(BRPOPLPUSH {
         source,
         destination,
         timeout,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -43,9 +43,9 @@ pub enum POSITION {
     AFTER,
 }
 
-pub(crate) fn parse_linsert(mut iter: Iter<Vec<u8>>) -> LINSERT {
-    let key = iter.next().unwrap();
-    let next_arg = iter.next().unwrap();
+pub(crate) fn parse_linsert(mut iter: Args) -> Result<LINSERT> {
+    let key = iter.next_arg()?;
+    let next_arg = iter.next_arg()?;
    // let position;
     let arg_upper = String::from_utf8_lossy(next_arg).to_uppercase();
     let position =  if &arg_upper == "BEFORE" {
@@ -53,24 +53,29 @@ pub(crate) fn parse_linsert(mut iter: Iter<Vec<u8>>) -> LINSERT {
     } else {
         AFTER
     };
-    let pivot = iter.next().unwrap();
-    let element = iter.next().unwrap();
-    LINSERT {
+    let pivot = iter.next_arg()?;
+    let element = iter.next_arg()?;
+    Ok(LINSERT {
         key,
         position,
         pivot,
         element,
-    }
+    })
 }
 
 #[derive(Debug)]
 pub struct LPOP<'a> {
     pub key: &'a [u8],
+    pub count: Option<&'a [u8]>,
 }
 
-pub(crate) fn parse_lpop(mut iter: Iter<Vec<u8>>) -> LPOP {
-    let key = iter.next().unwrap();
-    LPOP { key }
+pub(crate) fn parse_lpop(mut iter: Args) -> Result<LPOP> {
+    let key = iter.next_arg()?;
+    let mut count = None;
+    if let Some(next_arg) = iter.next() {
+        count = Some(next_arg.as_slice());
+    }
+    Ok(LPOP { key, count })
 }
 
 #[derive(Debug)]
@@ -79,13 +84,13 @@ pub struct LPUSH<'a> {
     pub elements: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_lpush(mut iter: Iter<Vec<u8>>) -> LPUSH {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_lpush(mut iter: Args) -> Result<LPUSH> {
+    let key = iter.next_arg()?;
     let mut elements = Vec::new();
     for ele in iter {
         elements.push(ele.as_slice());
     }
-    LPUSH { key, elements }
+    Ok(LPUSH { key, elements })
 }
 
 #[derive(Debug)]
@@ -94,13 +99,13 @@ pub struct LPUSHX<'a> {
     pub elements: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_lpushx(mut iter: Iter<Vec<u8>>) -> LPUSHX {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_lpushx(mut iter: Args) -> Result<LPUSHX> {
+    let key = iter.next_arg()?;
     let mut elements = Vec::new();
     for ele in iter {
         elements.push(ele.as_slice());
     }
-    LPUSHX { key, elements }
+    Ok(LPUSHX { key, elements })
 }
 
 #[derive(Debug)]
@@ -110,11 +115,11 @@ pub struct LREM<'a> {
     pub element: &'a [u8],
 }
 
-pub(crate) fn parse_lrem(mut iter: Iter<Vec<u8>>) -> LREM {
-    let key = iter.next().unwrap();
-    let count = iter.next().unwrap();
-    let element = iter.next().unwrap();
-    LREM { key, count, element }
+pub(crate) fn parse_lrem(mut iter: Args) -> Result<LREM> {
+    let key = iter.next_arg()?;
+    let count = iter.next_arg()?;
+    let element = iter.next_arg()?;
+    Ok(LREM { key, count, element })
 }
 
 #[derive(Debug)]
@@ -124,11 +129,11 @@ pub struct LSET<'a> {
     pub element: &'a [u8],
 }
 
-pub(crate) fn parse_lset(mut iter: Iter<Vec<u8>>) -> LSET {
-    let key = iter.next().unwrap();
-    let index = iter.next().unwrap();
-    let element = iter.next().unwrap();
-    LSET { key, index, element }
+pub(crate) fn parse_lset(mut iter: Args) -> Result<LSET> {
+    let key = iter.next_arg()?;
+    let index = iter.next_arg()?;
+    let element = iter.next_arg()?;
+    Ok(LSET { key, index, element })
 }
 
 #[derive(Debug)]
@@ -138,21 +143,26 @@ pub struct LTRIM<'a> {
     pub stop: &'a [u8],
 }
 
-pub(crate) fn parse_ltrim(mut iter: Iter<Vec<u8>>) -> LTRIM {
-    let key = iter.next().unwrap();
-    let start = iter.next().unwrap();
-    let stop = iter.next().unwrap();
-    LTRIM { key, start, stop }
+pub(crate) fn parse_ltrim(mut iter: Args) -> Result<LTRIM> {
+    let key = iter.next_arg()?;
+    let start = iter.next_arg()?;
+    let stop = iter.next_arg()?;
+    Ok(LTRIM { key, start, stop })
 }
 
 #[derive(Debug)]
 pub struct RPOP<'a> {
     pub key: &'a [u8],
+    pub count: Option<&'a [u8]>,
 }
 
-pub(crate) fn parse_rpop(mut iter: Iter<Vec<u8>>) -> RPOP {
-    let key = iter.next().unwrap();
-    RPOP { key }
+pub(crate) fn parse_rpop(mut iter: Args) -> Result<RPOP> {
+    let key = iter.next_arg()?;
+    let mut count = None;
+    if let Some(next_arg) = iter.next() {
+        count = Some(next_arg.as_slice());
+    }
+    Ok(RPOP { key, count })
 }
 
 #[derive(Debug)]
@@ -161,10 +171,10 @@ pub struct RPOPLPUSH<'a> {
     pub destination: &'a [u8],
 }
 
-pub(crate) fn parse_rpoplpush(mut iter: Iter<Vec<u8>>) -> RPOPLPUSH {
-    let source = iter.next().unwrap();
-    let destination = iter.next().unwrap();
-    RPOPLPUSH { source, destination }
+pub(crate) fn parse_rpoplpush(mut iter: Args) -> Result<RPOPLPUSH> {
+    let source = iter.next_arg()?;
+    let destination = iter.next_arg()?;
+    Ok(RPOPLPUSH { source, destination })
 }
 
 #[derive(Debug)]
@@ -173,13 +183,13 @@ pub struct RPUSH<'a> {
     pub elements: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_rpush(mut iter: Iter<Vec<u8>>) -> RPUSH {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_rpush(mut iter: Args) -> Result<RPUSH> {
+    let key = iter.next_arg()?;
     let mut elements = Vec::new();
     for ele in iter {
         elements.push(ele.as_slice());
     }
-    RPUSH { key, elements }
+    Ok(RPUSH { key, elements })
 }
 
 #[derive(Debug)]
@@ -188,11 +198,11 @@ pub struct RPUSHX<'a> {
     pub elements: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_rpushx(mut iter: Iter<Vec<u8>>) -> RPUSHX {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_rpushx(mut iter: Args) -> Result<RPUSHX> {
+    let key = iter.next_arg()?;
     let mut elements = Vec::new();
     for ele in iter {
         elements.push(ele.as_slice());
     }
-    RPUSHX { key, elements }
+    Ok(RPUSHX { key, elements })
 }
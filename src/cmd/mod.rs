@@ -29,9 +29,15 @@ use crate::cmd::sets::*;
 use crate::cmd::sorted_sets::*;
 use crate::cmd::streams::{XACK, XADD, XCLAIM, XDEL, XGROUP, XTRIM};
 use crate::cmd::strings::*;
+use crate::error::RedisSyncError;
 use crate::{Event, EventHandler};
+use log::warn;
+use std::result;
+use std::slice::Iter;
+use std::str::FromStr;
 
 pub mod connection;
+mod args;
 pub mod hashes;
 pub mod hyperloglog;
 pub mod keys;
@@ -65,9 +71,16 @@ pub enum Command<'a> {
     FLUSHDB(&'a FLUSHDB),
     GETSET(&'a GETSET<'a>),
     HDEL(&'a HDEL<'a>),
+    HEXPIRE(&'a HEXPIRE<'a>),
+    HEXPIREAT(&'a HEXPIREAT<'a>),
+    HGETDEL(&'a HGETDEL<'a>),
     HINCRBY(&'a HINCRBY<'a>),
     HMSET(&'a HMSET<'a>),
+    HPERSIST(&'a HPERSIST<'a>),
+    HPEXPIRE(&'a HPEXPIRE<'a>),
+    HPEXPIREAT(&'a HPEXPIREAT<'a>),
     HSET(&'a HSET<'a>),
+    HSETEX(&'a HSETEX<'a>),
     HSETNX(&'a HSETNX<'a>),
     INCR(&'a INCR<'a>),
     INCRBY(&'a INCRBY<'a>),
@@ -139,346 +152,580 @@ pub struct RawCommand {
     pub args: Vec<Vec<u8>>,
 }
 
-pub(crate) fn parse(data: Vec<Vec<u8>>, cmd_handler: &mut dyn EventHandler) {
+/// 命令解析失败时的处理方式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorPolicy {
+    /// 作为[Command::Other]原样交给`EventHandler`, 继续复制
+    #[default]
+    Other,
+    /// 返回[RedisSyncError::InvalidCommand], 停止复制
+    ///
+    /// [RedisSyncError::InvalidCommand]: ../error/enum.RedisSyncError.html#variant.InvalidCommand
+    Fail,
+}
+
+pub(crate) type Result<T> = result::Result<T, RedisSyncError>;
+
+/// 命令参数的迭代器, 记录读到的位置, 以便出错时指明是第几个参数
+pub(crate) struct Args<'a> {
+    command: &'a str,
+    iter: Iter<'a, Vec<u8>>,
+    /// 下一个参数的下标, 命令名本身为第0个
+    index: usize,
+}
+
+impl<'a> Args<'a> {
+    pub(crate) fn new(command: &'a str, iter: Iter<'a, Vec<u8>>) -> Self {
+        Args { command, iter, index: 1 }
+    }
+
+    /// 读取下一个必需的参数
+    pub(crate) fn next_arg(&mut self) -> Result<&'a Vec<u8>> {
+        self.next().ok_or_else(|| self.missing())
+    }
+
+    /// 读取下一个必需的参数并解析为`T`
+    pub(crate) fn parse_arg<T: FromStr>(&mut self) -> Result<T> {
+        let arg = String::from_utf8_lossy(self.next_arg()?);
+        arg.parse::<T>().map_err(|_| self.invalid(format!("invalid value: {}", arg)))
+    }
+
+    /// 下一个参数缺失
+    pub(crate) fn missing(&self) -> RedisSyncError {
+        self.error(self.index, "missing argument".to_string())
+    }
+
+    /// 上一个读到的参数不合法
+    pub(crate) fn invalid<S: Into<String>>(&self, reason: S) -> RedisSyncError {
+        self.error(self.index - 1, reason.into())
+    }
+
+    fn error(&self, index: usize, reason: String) -> RedisSyncError {
+        RedisSyncError::InvalidCommand {
+            command: self.command.to_string(),
+            index,
+            reason,
+        }
+    }
+}
+
+impl<'a> Iterator for Args<'a> {
+    type Item = &'a Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let arg = self.iter.next()?;
+        self.index += 1;
+        Some(arg)
+    }
+}
+
+/// 解析一条命令并交给`cmd_handler`, 命令的参数有误时按`policy`处理
+pub(crate) fn parse(data: Vec<Vec<u8>>, cmd_handler: &mut dyn EventHandler, policy: ParseErrorPolicy) -> Result<()> {
     let mut iter = data.iter();
     if let Some(cmd_name) = iter.next() {
         let cmd_name = String::from_utf8_lossy(cmd_name).to_uppercase();
-        match cmd_name.as_str() {
-            "APPEND" => {
-                let cmd = strings::parse_append(iter);
-                cmd_handler.handle(Event::AOF(Command::APPEND(&cmd)));
-            }
-            "BITFIELD" => {
-                let cmd = strings::parse_bitfield(iter);
-                cmd_handler.handle(Event::AOF(Command::BITFIELD(&cmd)));
-            }
-            "BITOP" => {
-                let cmd = strings::parse_bitop(iter);
-                cmd_handler.handle(Event::AOF(Command::BITOP(&cmd)));
-            }
-            "BRPOPLPUSH" => {
-                let cmd = lists::parse_brpoplpush(iter);
-                cmd_handler.handle(Event::AOF(Command::BRPOPLPUSH(&cmd)));
-            }
-            "DEL" => {
-                let cmd = keys::parse_del(iter);
-                cmd_handler.handle(Event::AOF(Command::DEL(&cmd)));
-            }
-            "DECR" => {
-                let cmd = strings::parse_decr(iter);
-                cmd_handler.handle(Event::AOF(Command::DECR(&cmd)));
-            }
-            "DECRBY" => {
-                let cmd = strings::parse_decrby(iter);
-                cmd_handler.handle(Event::AOF(Command::DECRBY(&cmd)));
-            }
-            "EVAL" => {
-                let cmd = scripting::parse_eval(iter);
-                cmd_handler.handle(Event::AOF(Command::EVAL(&cmd)));
-            }
-            "EVALSHA" => {
-                let cmd = scripting::parse_evalsha(iter);
-                cmd_handler.handle(Event::AOF(Command::EVALSHA(&cmd)));
-            }
-            "EXPIRE" => {
-                let cmd = keys::parse_expire(iter);
-                cmd_handler.handle(Event::AOF(Command::EXPIRE(&cmd)));
-            }
-            "EXPIREAT" => {
-                let cmd = keys::parse_expireat(iter);
-                cmd_handler.handle(Event::AOF(Command::EXPIREAT(&cmd)));
-            }
-            "EXEC" => {
-                cmd_handler.handle(Event::AOF(Command::EXEC));
-            }
-            "FLUSHALL" => {
-                let cmd = server::parse_flushall(iter);
-                cmd_handler.handle(Event::AOF(Command::FLUSHALL(&cmd)));
-            }
-            "FLUSHDB" => {
-                let cmd = server::parse_flushdb(iter);
-                cmd_handler.handle(Event::AOF(Command::FLUSHDB(&cmd)));
-            }
-            "GETSET" => {
-                let cmd = strings::parse_getset(iter);
-                cmd_handler.handle(Event::AOF(Command::GETSET(&cmd)));
-            }
-            "HDEL" => {
-                let cmd = hashes::parse_hdel(iter);
-                cmd_handler.handle(Event::AOF(Command::HDEL(&cmd)));
-            }
-            "HINCRBY" => {
-                let cmd = hashes::parse_hincrby(iter);
-                cmd_handler.handle(Event::AOF(Command::HINCRBY(&cmd)));
-            }
-            "HMSET" => {
-                let cmd = hashes::parse_hmset(iter);
-                cmd_handler.handle(Event::AOF(Command::HMSET(&cmd)));
-            }
-            "HSET" => {
-                let cmd = hashes::parse_hset(iter);
-                cmd_handler.handle(Event::AOF(Command::HSET(&cmd)));
-            }
-            "HSETNX" => {
-                let cmd = hashes::parse_hsetnx(iter);
-                cmd_handler.handle(Event::AOF(Command::HSETNX(&cmd)));
-            }
-            "INCR" => {
-                let cmd = strings::parse_incr(iter);
-                cmd_handler.handle(Event::AOF(Command::INCR(&cmd)));
-            }
-            "INCRBY" => {
-                let cmd = strings::parse_incrby(iter);
-                cmd_handler.handle(Event::AOF(Command::INCRBY(&cmd)));
-            }
-            "LINSERT" => {
-                let cmd = lists::parse_linsert(iter);
-                cmd_handler.handle(Event::AOF(Command::LINSERT(&cmd)));
-            }
-            "LPOP" => {
-                let cmd = lists::parse_lpop(iter);
-                cmd_handler.handle(Event::AOF(Command::LPOP(&cmd)));
-            }
-            "LPUSH" => {
-                let cmd = lists::parse_lpush(iter);
-                cmd_handler.handle(Event::AOF(Command::LPUSH(&cmd)));
-            }
-            "LPUSHX" => {
-                let cmd = lists::parse_lpushx(iter);
-                cmd_handler.handle(Event::AOF(Command::LPUSHX(&cmd)));
-            }
-            "LREM" => {
-                let cmd = lists::parse_lrem(iter);
-                cmd_handler.handle(Event::AOF(Command::LREM(&cmd)));
-            }
-            "LSET" => {
-                let cmd = lists::parse_lset(iter);
-                cmd_handler.handle(Event::AOF(Command::LSET(&cmd)));
-            }
-            "LTRIM" => {
-                let cmd = lists::parse_ltrim(iter);
-                cmd_handler.handle(Event::AOF(Command::LTRIM(&cmd)));
-            }
-            "RENAME" => {
-                let cmd = keys::parse_rename(iter);
-                cmd_handler.handle(Event::AOF(Command::RENAME(&cmd)));
-            }
-            "RENAMENX" => {
-                let cmd = keys::parse_renamenx(iter);
-                cmd_handler.handle(Event::AOF(Command::RENAMENX(&cmd)));
-            }
-            "RESTORE" => {
-                let cmd = keys::parse_restore(iter);
-                cmd_handler.handle(Event::AOF(Command::RESTORE(&cmd)));
-            }
-            "RPOP" => {
-                let cmd = lists::parse_rpop(iter);
-                cmd_handler.handle(Event::AOF(Command::RPOP(&cmd)));
-            }
-            "RPOPLPUSH" => {
-                let cmd = lists::parse_rpoplpush(iter);
-                cmd_handler.handle(Event::AOF(Command::RPOPLPUSH(&cmd)));
-            }
-            "RPUSH" => {
-                let cmd = lists::parse_rpush(iter);
-                cmd_handler.handle(Event::AOF(Command::RPUSH(&cmd)));
-            }
-            "RPUSHX" => {
-                let cmd = lists::parse_rpushx(iter);
-                cmd_handler.handle(Event::AOF(Command::RPUSHX(&cmd)));
-            }
-            "SADD" => {
-                let cmd = sets::parse_sadd(iter);
-                cmd_handler.handle(Event::AOF(Command::SADD(&cmd)));
-            }
-            "SCRIPT" => {
-                let cmd = iter.next().unwrap();
-                let cmd = String::from_utf8_lossy(cmd).to_uppercase();
-                if &cmd == "LOAD" {
-                    let cmd = scripting::parse_script_load(iter);
-                    cmd_handler.handle(Event::AOF(Command::SCRIPTLOAD(&cmd)));
-                } else if &cmd == "FLUSH" {
-                    cmd_handler.handle(Event::AOF(Command::SCRIPTFLUSH));
-                }
-            }
-            "SDIFFSTORE" => {
-                let cmd = sets::parse_sdiffstore(iter);
-                cmd_handler.handle(Event::AOF(Command::SDIFFSTORE(&cmd)));
-            }
-            "SMOVE" => {
-                let cmd = sets::parse_smove(iter);
-                cmd_handler.handle(Event::AOF(Command::SMOVE(&cmd)));
-            }
-            "SET" => {
-                let cmd = strings::parse_set(iter);
-                cmd_handler.handle(Event::AOF(Command::SET(&cmd)));
-            }
-            "SELECT" => {
-                let cmd = connection::parse_select(iter);
-                cmd_handler.handle(Event::AOF(Command::SELECT(&cmd)));
-            }
-            "SORT" => {
-                let cmd = keys::parse_sort(iter);
-                cmd_handler.handle(Event::AOF(Command::SORT(&cmd)));
-            }
-            "SREM" => {
-                let cmd = sets::parse_srem(iter);
-                cmd_handler.handle(Event::AOF(Command::SREM(&cmd)));
-            }
-            "SUNIONSTORE" => {
-                let cmd = sets::parse_sunionstore(iter);
-                cmd_handler.handle(Event::AOF(Command::SUNIONSTORE(&cmd)));
-            }
-            "SWAPDB" => {
-                let cmd = connection::parse_swapdb(iter);
-                cmd_handler.handle(Event::AOF(Command::SWAPDB(&cmd)));
-            }
-            "UNLINK" => {
-                let cmd = keys::parse_unlink(iter);
-                cmd_handler.handle(Event::AOF(Command::UNLINK(&cmd)));
-            }
-            "MOVE" => {
-                let cmd = keys::parse_move(iter);
-                cmd_handler.handle(Event::AOF(Command::MOVE(&cmd)));
-            }
-            "MSET" => {
-                let cmd = strings::parse_mset(iter);
-                cmd_handler.handle(Event::AOF(Command::MSET(&cmd)));
-            }
-            "MSETNX" => {
-                let cmd = strings::parse_msetnx(iter);
-                cmd_handler.handle(Event::AOF(Command::MSETNX(&cmd)));
-            }
-            "MULTI" => {
-                cmd_handler.handle(Event::AOF(Command::MULTI));
-            }
-            "PFADD" => {
-                let cmd = hyperloglog::parse_pfadd(iter);
-                cmd_handler.handle(Event::AOF(Command::PFADD(&cmd)));
-            }
-            "PFCOUNT" => {
-                let cmd = hyperloglog::parse_pfcount(iter);
-                cmd_handler.handle(Event::AOF(Command::PFCOUNT(&cmd)));
-            }
-            "PFMERGE" => {
-                let cmd = hyperloglog::parse_pfmerge(iter);
-                cmd_handler.handle(Event::AOF(Command::PFMERGE(&cmd)));
-            }
-            "SETEX" => {
-                let cmd = strings::parse_setex(iter);
-                cmd_handler.handle(Event::AOF(Command::SETEX(&cmd)));
-            }
-            "SETNX" => {
-                let cmd = strings::parse_setnx(iter);
-                cmd_handler.handle(Event::AOF(Command::SETNX(&cmd)));
-            }
-            "PSETEX" => {
-                let cmd = strings::parse_psetex(iter);
-                cmd_handler.handle(Event::AOF(Command::PSETEX(&cmd)));
-            }
-            "PUBLISH" => {
-                let cmd = pub_sub::parse_publish(iter);
-                cmd_handler.handle(Event::AOF(Command::PUBLISH(&cmd)));
-            }
-            "PEXPIRE" => {
-                let cmd = keys::parse_pexpire(iter);
-                cmd_handler.handle(Event::AOF(Command::PEXPIRE(&cmd)));
-            }
-            "PEXPIREAT" => {
-                let cmd = keys::parse_pexpireat(iter);
-                cmd_handler.handle(Event::AOF(Command::PEXPIREAT(&cmd)));
-            }
-            "PERSIST" => {
-                let cmd = keys::parse_persist(iter);
-                cmd_handler.handle(Event::AOF(Command::PERSIST(&cmd)));
-            }
-            "SETRANGE" => {
-                let cmd = strings::parse_setrange(iter);
-                cmd_handler.handle(Event::AOF(Command::SETRANGE(&cmd)));
-            }
-            "SETBIT" => {
-                let cmd = strings::parse_setbit(iter);
-                cmd_handler.handle(Event::AOF(Command::SETBIT(&cmd)));
-            }
-            "SINTERSTORE" => {
-                let cmd = sets::parse_sinterstore(iter);
-                cmd_handler.handle(Event::AOF(Command::SINTERSTORE(&cmd)));
-            }
-            "ZADD" => {
-                let cmd = sorted_sets::parse_zadd(iter);
-                cmd_handler.handle(Event::AOF(Command::ZADD(&cmd)));
-            }
-            "ZINCRBY" => {
-                let cmd = sorted_sets::parse_zincrby(iter);
-                cmd_handler.handle(Event::AOF(Command::ZINCRBY(&cmd)));
-            }
-            "ZINTERSTORE" => {
-                let cmd = sorted_sets::parse_zinterstore(iter);
-                cmd_handler.handle(Event::AOF(Command::ZINTERSTORE(&cmd)));
-            }
-            "ZPOPMAX" => {
-                let cmd = sorted_sets::parse_zpopmax(iter);
-                cmd_handler.handle(Event::AOF(Command::ZPOPMAX(&cmd)));
-            }
-            "ZPOPMIN" => {
-                let cmd = sorted_sets::parse_zpopmin(iter);
-                cmd_handler.handle(Event::AOF(Command::ZPOPMIN(&cmd)));
-            }
-            "ZREM" => {
-                let cmd = sorted_sets::parse_zrem(iter);
-                cmd_handler.handle(Event::AOF(Command::ZREM(&cmd)));
-            }
-            "ZREMRANGEBYLEX" => {
-                let cmd = sorted_sets::parse_zremrangebylex(iter);
-                cmd_handler.handle(Event::AOF(Command::ZREMRANGEBYLEX(&cmd)));
-            }
-            "ZREMRANGEBYRANK" => {
-                let cmd = sorted_sets::parse_zremrangebyrank(iter);
-                cmd_handler.handle(Event::AOF(Command::ZREMRANGEBYRANK(&cmd)));
-            }
-            "ZREMRANGEBYSCORE" => {
-                let cmd = sorted_sets::parse_zremrangebyscore(iter);
-                cmd_handler.handle(Event::AOF(Command::ZREMRANGEBYSCORE(&cmd)));
-            }
-            "ZUNIONSTORE" => {
-                let cmd = sorted_sets::parse_zunionstore(iter);
-                cmd_handler.handle(Event::AOF(Command::ZUNIONSTORE(&cmd)));
-            }
-            "XACK" => {
-                let cmd = streams::parse_xack(iter);
-                cmd_handler.handle(Event::AOF(Command::XACK(&cmd)));
-            }
-            "XADD" => {
-                let cmd = streams::parse_xadd(iter);
-                cmd_handler.handle(Event::AOF(Command::XADD(&cmd)));
-            }
-            "XCLAIM" => {
-                let cmd = streams::parse_xclaim(iter);
-                cmd_handler.handle(Event::AOF(Command::XCLAIM(&cmd)));
-            }
-            "XDEL" => {
-                let cmd = streams::parse_xdel(iter);
-                cmd_handler.handle(Event::AOF(Command::XDEL(&cmd)));
-            }
-            "XGROUP" => {
-                let cmd = streams::parse_xgroup(iter);
-                cmd_handler.handle(Event::AOF(Command::XGROUP(&cmd)));
-            }
-            "XTRIM" => {
-                let cmd = streams::parse_xtrim(iter);
-                cmd_handler.handle(Event::AOF(Command::XTRIM(&cmd)));
-            }
-            "PING" => {
-                // PING命令是由Redis master主动发送过来，判断下游节点是否活跃，不需要处理
-            }
-            _ => {
-                let mut args = Vec::new();
-                for arg in iter {
-                    args.push(arg.clone());
+        if let Err(err) = parse_command(&cmd_name, Args::new(&cmd_name, iter.clone()), cmd_handler) {
+            match policy {
+                ParseErrorPolicy::Other => {
+                    warn!("{}", err);
+                    let cmd = RawCommand {
+                        name: cmd_name,
+                        args: iter.cloned().collect(),
+                    };
+                    cmd_handler.handle(Event::AOF(Command::Other(cmd)));
                 }
-                let cmd = RawCommand { name: cmd_name, args };
-                cmd_handler.handle(Event::AOF(Command::Other(cmd)))
+                ParseErrorPolicy::Fail => return Err(err),
             }
+        }
+    }
+    Ok(())
+}
+
+fn parse_command(cmd_name: &str, mut iter: Args, cmd_handler: &mut dyn EventHandler) -> Result<()> {
+    match cmd_name {
+        "APPEND" => {
+            let cmd = strings::parse_append(iter)?;
+            cmd_handler.handle(Event::AOF(Command::APPEND(&cmd)));
+        }
+        "BITFIELD" => {
+            let cmd = strings::parse_bitfield(iter)?;
+            cmd_handler.handle(Event::AOF(Command::BITFIELD(&cmd)));
+        }
+        "BITOP" => {
+            let cmd = strings::parse_bitop(iter)?;
+            cmd_handler.handle(Event::AOF(Command::BITOP(&cmd)));
+        }
+        "BRPOPLPUSH" => {
+            let cmd = lists::parse_brpoplpush(iter)?;
+            cmd_handler.handle(Event::AOF(Command::BRPOPLPUSH(&cmd)));
+        }
+        "DEL" => {
+            let cmd = keys::parse_del(iter)?;
+            cmd_handler.handle(Event::AOF(Command::DEL(&cmd)));
+        }
+        "DECR" => {
+            let cmd = strings::parse_decr(iter)?;
+            cmd_handler.handle(Event::AOF(Command::DECR(&cmd)));
+        }
+        "DECRBY" => {
+            let cmd = strings::parse_decrby(iter)?;
+            cmd_handler.handle(Event::AOF(Command::DECRBY(&cmd)));
+        }
+        "EVAL" => {
+            let cmd = scripting::parse_eval(iter)?;
+            cmd_handler.handle(Event::AOF(Command::EVAL(&cmd)));
+        }
+        "EVALSHA" => {
+            let cmd = scripting::parse_evalsha(iter)?;
+            cmd_handler.handle(Event::AOF(Command::EVALSHA(&cmd)));
+        }
+        "EXPIRE" => {
+            let cmd = keys::parse_expire(iter)?;
+            cmd_handler.handle(Event::AOF(Command::EXPIRE(&cmd)));
+        }
+        "EXPIREAT" => {
+            let cmd = keys::parse_expireat(iter)?;
+            cmd_handler.handle(Event::AOF(Command::EXPIREAT(&cmd)));
+        }
+        "EXEC" => {
+            cmd_handler.handle(Event::AOF(Command::EXEC));
+        }
+        "FLUSHALL" => {
+            let cmd = server::parse_flushall(iter)?;
+            cmd_handler.handle(Event::AOF(Command::FLUSHALL(&cmd)));
+        }
+        "FLUSHDB" => {
+            let cmd = server::parse_flushdb(iter)?;
+            cmd_handler.handle(Event::AOF(Command::FLUSHDB(&cmd)));
+        }
+        "GETSET" => {
+            let cmd = strings::parse_getset(iter)?;
+            cmd_handler.handle(Event::AOF(Command::GETSET(&cmd)));
+        }
+        "HDEL" => {
+            let cmd = hashes::parse_hdel(iter)?;
+            cmd_handler.handle(Event::AOF(Command::HDEL(&cmd)));
+        }
+        "HEXPIRE" => {
+            let cmd = hashes::parse_hexpire(iter)?;
+            cmd_handler.handle(Event::AOF(Command::HEXPIRE(&cmd)));
+        }
+        "HEXPIREAT" => {
+            let cmd = hashes::parse_hexpireat(iter)?;
+            cmd_handler.handle(Event::AOF(Command::HEXPIREAT(&cmd)));
+        }
+        "HGETDEL" => {
+            let cmd = hashes::parse_hgetdel(iter)?;
+            cmd_handler.handle(Event::AOF(Command::HGETDEL(&cmd)));
+        }
+        "HINCRBY" => {
+            let cmd = hashes::parse_hincrby(iter)?;
+            cmd_handler.handle(Event::AOF(Command::HINCRBY(&cmd)));
+        }
+        "HMSET" => {
+            let cmd = hashes::parse_hmset(iter)?;
+            cmd_handler.handle(Event::AOF(Command::HMSET(&cmd)));
+        }
+        "HPERSIST" => {
+            let cmd = hashes::parse_hpersist(iter)?;
+            cmd_handler.handle(Event::AOF(Command::HPERSIST(&cmd)));
+        }
+        "HPEXPIRE" => {
+            let cmd = hashes::parse_hpexpire(iter)?;
+            cmd_handler.handle(Event::AOF(Command::HPEXPIRE(&cmd)));
+        }
+        "HPEXPIREAT" => {
+            let cmd = hashes::parse_hpexpireat(iter)?;
+            cmd_handler.handle(Event::AOF(Command::HPEXPIREAT(&cmd)));
+        }
+        "HSET" => {
+            let cmd = hashes::parse_hset(iter)?;
+            cmd_handler.handle(Event::AOF(Command::HSET(&cmd)));
+        }
+        "HSETEX" => {
+            let cmd = hashes::parse_hsetex(iter)?;
+            cmd_handler.handle(Event::AOF(Command::HSETEX(&cmd)));
+        }
+        "HSETNX" => {
+            let cmd = hashes::parse_hsetnx(iter)?;
+            cmd_handler.handle(Event::AOF(Command::HSETNX(&cmd)));
+        }
+        "INCR" => {
+            let cmd = strings::parse_incr(iter)?;
+            cmd_handler.handle(Event::AOF(Command::INCR(&cmd)));
+        }
+        "INCRBY" => {
+            let cmd = strings::parse_incrby(iter)?;
+            cmd_handler.handle(Event::AOF(Command::INCRBY(&cmd)));
+        }
+        "LINSERT" => {
+            let cmd = lists::parse_linsert(iter)?;
+            cmd_handler.handle(Event::AOF(Command::LINSERT(&cmd)));
+        }
+        "LPOP" => {
+            let cmd = lists::parse_lpop(iter)?;
+            cmd_handler.handle(Event::AOF(Command::LPOP(&cmd)));
+        }
+        "LPUSH" => {
+            let cmd = lists::parse_lpush(iter)?;
+            cmd_handler.handle(Event::AOF(Command::LPUSH(&cmd)));
+        }
+        "LPUSHX" => {
+            let cmd = lists::parse_lpushx(iter)?;
+            cmd_handler.handle(Event::AOF(Command::LPUSHX(&cmd)));
+        }
+        "LREM" => {
+            let cmd = lists::parse_lrem(iter)?;
+            cmd_handler.handle(Event::AOF(Command::LREM(&cmd)));
+        }
+        "LSET" => {
+            let cmd = lists::parse_lset(iter)?;
+            cmd_handler.handle(Event::AOF(Command::LSET(&cmd)));
+        }
+        "LTRIM" => {
+            let cmd = lists::parse_ltrim(iter)?;
+            cmd_handler.handle(Event::AOF(Command::LTRIM(&cmd)));
+        }
+        "RENAME" => {
+            let cmd = keys::parse_rename(iter)?;
+            cmd_handler.handle(Event::AOF(Command::RENAME(&cmd)));
+        }
+        "RENAMENX" => {
+            let cmd = keys::parse_renamenx(iter)?;
+            cmd_handler.handle(Event::AOF(Command::RENAMENX(&cmd)));
+        }
+        "RESTORE" => {
+            let cmd = keys::parse_restore(iter)?;
+            cmd_handler.handle(Event::AOF(Command::RESTORE(&cmd)));
+        }
+        "RPOP" => {
+            let cmd = lists::parse_rpop(iter)?;
+            cmd_handler.handle(Event::AOF(Command::RPOP(&cmd)));
+        }
+        "RPOPLPUSH" => {
+            let cmd = lists::parse_rpoplpush(iter)?;
+            cmd_handler.handle(Event::AOF(Command::RPOPLPUSH(&cmd)));
+        }
+        "RPUSH" => {
+            let cmd = lists::parse_rpush(iter)?;
+            cmd_handler.handle(Event::AOF(Command::RPUSH(&cmd)));
+        }
+        "RPUSHX" => {
+            let cmd = lists::parse_rpushx(iter)?;
+            cmd_handler.handle(Event::AOF(Command::RPUSHX(&cmd)));
+        }
+        "SADD" => {
+            let cmd = sets::parse_sadd(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SADD(&cmd)));
+        }
+        "SCRIPT" => {
+            let cmd = iter.next_arg()?;
+            let cmd = String::from_utf8_lossy(cmd).to_uppercase();
+            if &cmd == "LOAD" {
+                let cmd = scripting::parse_script_load(iter)?;
+                cmd_handler.handle(Event::AOF(Command::SCRIPTLOAD(&cmd)));
+            } else if &cmd == "FLUSH" {
+                cmd_handler.handle(Event::AOF(Command::SCRIPTFLUSH));
+            }
+        }
+        "SDIFFSTORE" => {
+            let cmd = sets::parse_sdiffstore(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SDIFFSTORE(&cmd)));
+        }
+        "SMOVE" => {
+            let cmd = sets::parse_smove(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SMOVE(&cmd)));
+        }
+        "SET" => {
+            let cmd = strings::parse_set(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SET(&cmd)));
+        }
+        "SELECT" => {
+            let cmd = connection::parse_select(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SELECT(&cmd)));
+        }
+        "SORT" => {
+            let cmd = keys::parse_sort(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SORT(&cmd)));
+        }
+        "SREM" => {
+            let cmd = sets::parse_srem(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SREM(&cmd)));
+        }
+        "SUNIONSTORE" => {
+            let cmd = sets::parse_sunionstore(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SUNIONSTORE(&cmd)));
+        }
+        "SWAPDB" => {
+            let cmd = connection::parse_swapdb(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SWAPDB(&cmd)));
+        }
+        "UNLINK" => {
+            let cmd = keys::parse_unlink(iter)?;
+            cmd_handler.handle(Event::AOF(Command::UNLINK(&cmd)));
+        }
+        "MOVE" => {
+            let cmd = keys::parse_move(iter)?;
+            cmd_handler.handle(Event::AOF(Command::MOVE(&cmd)));
+        }
+        "MSET" => {
+            let cmd = strings::parse_mset(iter)?;
+            cmd_handler.handle(Event::AOF(Command::MSET(&cmd)));
+        }
+        "MSETNX" => {
+            let cmd = strings::parse_msetnx(iter)?;
+            cmd_handler.handle(Event::AOF(Command::MSETNX(&cmd)));
+        }
+        "MULTI" => {
+            cmd_handler.handle(Event::AOF(Command::MULTI));
+        }
+        "PFADD" => {
+            let cmd = hyperloglog::parse_pfadd(iter)?;
+            cmd_handler.handle(Event::AOF(Command::PFADD(&cmd)));
+        }
+        "PFCOUNT" => {
+            let cmd = hyperloglog::parse_pfcount(iter)?;
+            cmd_handler.handle(Event::AOF(Command::PFCOUNT(&cmd)));
+        }
+        "PFMERGE" => {
+            let cmd = hyperloglog::parse_pfmerge(iter)?;
+            cmd_handler.handle(Event::AOF(Command::PFMERGE(&cmd)));
+        }
+        "SETEX" => {
+            let cmd = strings::parse_setex(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SETEX(&cmd)));
+        }
+        "SETNX" => {
+            let cmd = strings::parse_setnx(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SETNX(&cmd)));
+        }
+        "PSETEX" => {
+            let cmd = strings::parse_psetex(iter)?;
+            cmd_handler.handle(Event::AOF(Command::PSETEX(&cmd)));
+        }
+        "PUBLISH" => {
+            let cmd = pub_sub::parse_publish(iter)?;
+            cmd_handler.handle(Event::AOF(Command::PUBLISH(&cmd)));
+        }
+        "PEXPIRE" => {
+            let cmd = keys::parse_pexpire(iter)?;
+            cmd_handler.handle(Event::AOF(Command::PEXPIRE(&cmd)));
+        }
+        "PEXPIREAT" => {
+            let cmd = keys::parse_pexpireat(iter)?;
+            cmd_handler.handle(Event::AOF(Command::PEXPIREAT(&cmd)));
+        }
+        "PERSIST" => {
+            let cmd = keys::parse_persist(iter)?;
+            cmd_handler.handle(Event::AOF(Command::PERSIST(&cmd)));
+        }
+        "SETRANGE" => {
+            let cmd = strings::parse_setrange(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SETRANGE(&cmd)));
+        }
+        "SETBIT" => {
+            let cmd = strings::parse_setbit(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SETBIT(&cmd)));
+        }
+        "SINTERSTORE" => {
+            let cmd = sets::parse_sinterstore(iter)?;
+            cmd_handler.handle(Event::AOF(Command::SINTERSTORE(&cmd)));
+        }
+        "ZADD" => {
+            let cmd = sorted_sets::parse_zadd(iter)?;
+            cmd_handler.handle(Event::AOF(Command::ZADD(&cmd)));
+        }
+        "ZINCRBY" => {
+            let cmd = sorted_sets::parse_zincrby(iter)?;
+            cmd_handler.handle(Event::AOF(Command::ZINCRBY(&cmd)));
+        }
+        "ZINTERSTORE" => {
+            let cmd = sorted_sets::parse_zinterstore(iter)?;
+            cmd_handler.handle(Event::AOF(Command::ZINTERSTORE(&cmd)));
+        }
+        "ZPOPMAX" => {
+            let cmd = sorted_sets::parse_zpopmax(iter)?;
+            cmd_handler.handle(Event::AOF(Command::ZPOPMAX(&cmd)));
+        }
+        "ZPOPMIN" => {
+            let cmd = sorted_sets::parse_zpopmin(iter)?;
+            cmd_handler.handle(Event::AOF(Command::ZPOPMIN(&cmd)));
+        }
+        "ZREM" => {
+            let cmd = sorted_sets::parse_zrem(iter)?;
+            cmd_handler.handle(Event::AOF(Command::ZREM(&cmd)));
+        }
+        "ZREMRANGEBYLEX" => {
+            let cmd = sorted_sets::parse_zremrangebylex(iter)?;
+            cmd_handler.handle(Event::AOF(Command::ZREMRANGEBYLEX(&cmd)));
+        }
+        "ZREMRANGEBYRANK" => {
+            let cmd = sorted_sets::parse_zremrangebyrank(iter)?;
+            cmd_handler.handle(Event::AOF(Command::ZREMRANGEBYRANK(&cmd)));
+        }
+        "ZREMRANGEBYSCORE" => {
+            let cmd = sorted_sets::parse_zremrangebyscore(iter)?;
+            cmd_handler.handle(Event::AOF(Command::ZREMRANGEBYSCORE(&cmd)));
+        }
+        "ZUNIONSTORE" => {
+            let cmd = sorted_sets::parse_zunionstore(iter)?;
+            cmd_handler.handle(Event::AOF(Command::ZUNIONSTORE(&cmd)));
+        }
+        "XACK" => {
+            let cmd = streams::parse_xack(iter)?;
+            cmd_handler.handle(Event::AOF(Command::XACK(&cmd)));
+        }
+        "XADD" => {
+            let cmd = streams::parse_xadd(iter)?;
+            cmd_handler.handle(Event::AOF(Command::XADD(&cmd)));
+        }
+        "XCLAIM" => {
+            let cmd = streams::parse_xclaim(iter)?;
+            cmd_handler.handle(Event::AOF(Command::XCLAIM(&cmd)));
+        }
+        "XDEL" => {
+            let cmd = streams::parse_xdel(iter)?;
+            cmd_handler.handle(Event::AOF(Command::XDEL(&cmd)));
+        }
+        "XGROUP" => {
+            let cmd = streams::parse_xgroup(iter)?;
+            cmd_handler.handle(Event::AOF(Command::XGROUP(&cmd)));
+        }
+        "XTRIM" => {
+            let cmd = streams::parse_xtrim(iter)?;
+            cmd_handler.handle(Event::AOF(Command::XTRIM(&cmd)));
+        }
+        "PING" => {
+            // PING命令是由Redis master主动发送过来，判断下游节点是否活跃，不需要处理
+        }
+        _ => {
+            let mut args = Vec::new();
+            for arg in iter {
+                args.push(arg.clone());
+            }
+            let cmd = RawCommand {
+                name: cmd_name.to_string(),
+                args,
+            };
+            cmd_handler.handle(Event::AOF(Command::Other(cmd)))
+        }
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::hashes::{ExpireCondition, Field, FieldExistType, HPEXPIREAT, HSETEX};
+    use super::strings::{ExistType, ExpireType, SET};
+    use super::{parse, Command, ParseErrorPolicy};
+    use crate::error::RedisSyncError;
+    use crate::{Event, EventHandler};
+
+    #[derive(Default)]
+    struct Record {
+        events: Vec<String>,
+    }
+
+    impl EventHandler for Record {
+        fn handle(&mut self, event: Event) {
+            match event {
+                Event::AOF(Command::Other(cmd)) => self.events.push(format!("Other({})", cmd.name)),
+                Event::AOF(cmd) => self.events.push(format!("{:?}", cmd)),
+                _ => {}
+            }
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<Vec<u8>> {
+        args.iter().map(|arg| arg.as_bytes().to_vec()).collect()
+    }
+
+    fn invalid(cmd: &[&str]) -> (String, usize) {
+        let mut handler = Record::default();
+        match parse(args(cmd), &mut handler, ParseErrorPolicy::Fail) {
+            Err(RedisSyncError::InvalidCommand { command, index, .. }) => {
+                assert!(handler.events.is_empty());
+                (command, index)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_error_index() {
+        assert_eq!(invalid(&["append", "key"]), ("APPEND".to_string(), 2));
+        assert_eq!(invalid(&["MSET", "k1", "v1", "k2"]), ("MSET".to_string(), 4));
+        assert_eq!(invalid(&["MSET"]), ("MSET".to_string(), 1));
+        assert_eq!(invalid(&["XADD", "stream", "*", "field"]), ("XADD".to_string(), 4));
+        assert_eq!(invalid(&["FLUSHALL", "LATER"]), ("FLUSHALL".to_string(), 1));
+        assert_eq!(invalid(&["SELECT", "db"]), ("SELECT".to_string(), 1));
+        assert_eq!(invalid(&["BITOP", "NAND", "dest", "src"]), ("BITOP".to_string(), 1));
+        assert_eq!(invalid(&["XTRIM", "stream", "MAXLEN", "~", "many"]), ("XTRIM".to_string(), 4));
+    }
+
+    #[test]
+    fn test_parse_error_policy() {
+        let mut handler = Record::default();
+        parse(args(&["SET", "key"]), &mut handler, ParseErrorPolicy::Other).unwrap();
+        parse(args(&["SET", "key", "value"]), &mut handler, ParseErrorPolicy::Other).unwrap();
+        assert_eq!(handler.events.len(), 2);
+        assert_eq!(handler.events[0], "Other(SET)");
+        assert!(handler.events[1].starts_with("SET("));
+    }
+
+    #[test]
+    fn test_parse_field_expire() {
+        let mut handler = Record::default();
+        let cmd = args(&["HPEXPIREAT", "key", "1700000000000", "GT", "FIELDS", "2", "a", "b"]);
+        parse(cmd, &mut handler, ParseErrorPolicy::Fail).unwrap();
+        let cmd = args(&["HSETEX", "key", "FNX", "PX", "100", "FIELDS", "1", "f", "v"]);
+        parse(cmd, &mut handler, ParseErrorPolicy::Fail).unwrap();
+
+        let expected = HPEXPIREAT {
+            key: b"key",
+            mill_timestamp: b"1700000000000",
+            condition: Some(ExpireCondition::GT),
+            fields: vec![b"a", b"b"],
+        };
+        assert_eq!(handler.events[0], format!("{:?}", Command::HPEXPIREAT(&expected)));
+        let expected = HSETEX {
+            key: b"key",
+            exist_type: Some(FieldExistType::FNX),
+            expire: Some((ExpireType::PX, b"100")),
+            keep_ttl: None,
+            fields: vec![Field { name: b"f", value: b"v" }],
+        };
+        assert_eq!(handler.events[1], format!("{:?}", Command::HSETEX(&expected)));
+
+        assert_eq!(invalid(&["HPERSIST", "key", "FIELDS", "2", "a"]), ("HPERSIST".to_string(), 5));
+        assert_eq!(invalid(&["HGETDEL", "key", "a"]), ("HGETDEL".to_string(), 2));
+        assert_eq!(invalid(&["HEXPIRE", "key", "10", "FIELDS", "n", "a"]), ("HEXPIRE".to_string(), 4));
+    }
+
+    #[test]
+    fn test_parse_set_expire_at() {
+        let mut handler = Record::default();
+        parse(args(&["SET", "key", "value", "EXAT", "1700000000"]), &mut handler, ParseErrorPolicy::Fail).unwrap();
+        let cmd = args(&["set", "key", "value", "pxat", "1700000000000", "NX"]);
+        parse(cmd, &mut handler, ParseErrorPolicy::Fail).unwrap();
+
+        let timestamp = b"1700000000".to_vec();
+        let expected = SET {
+            key: b"key",
+            value: b"value",
+            expire: Some((ExpireType::EXAT, &timestamp)),
+            exist_type: None,
+            keep_ttl: None,
+        };
+        assert_eq!(handler.events[0], format!("{:?}", Command::SET(&expected)));
+        let timestamp = b"1700000000000".to_vec();
+        let expected = SET {
+            key: b"key",
+            value: b"value",
+            expire: Some((ExpireType::PXAT, &timestamp)),
+            exist_type: Some(ExistType::NX),
+            keep_ttl: None,
         };
+        assert_eq!(handler.events[1], format!("{:?}", Command::SET(&expected)));
     }
 }
@@ -6,7 +6,7 @@ Pub/Sub相关的命令定义、解析
 [Redis Command Reference]: https://redis.io/commands#pubsub
 */
 
-use std::slice::Iter;
+use crate::cmd::{Args, Result};
 
 #[derive(Debug)]
 pub struct PUBLISH<'a> {
@@ -14,8 +14,8 @@ pub struct PUBLISH<'a> {
     pub message: &'a [u8],
 }
 
-pub(crate) fn parse_publish(mut iter: Iter<Vec<u8>>) -> PUBLISH {
-    let channel = iter.next().unwrap();
-    let message = iter.next().unwrap();
-    PUBLISH { channel, message }
+pub(crate) fn parse_publish(mut iter: Args) -> Result<PUBLISH> {
+    let channel = iter.next_arg()?;
+    let message = iter.next_arg()?;
+    Ok(PUBLISH { channel, message })
 }
@@ -6,7 +6,7 @@ Scripting相关的命令定义、解析
 [Redis Command Reference]: https://redis.io/commands#scripting
 */
 
-use std::slice::Iter;
+use crate::cmd::{Args, Result};
 
 #[derive(Debug)]
 pub struct EVAL<'a> {
@@ -16,25 +16,24 @@ pub struct EVAL<'a> {
     pub args: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_eval(mut iter: Iter<Vec<u8>>) -> EVAL {
-    let script = iter.next().unwrap();
-    let num_keys = iter.next().unwrap();
-    let num_keys = String::from_utf8_lossy(num_keys).parse::<i32>().unwrap();
+pub(crate) fn parse_eval(mut iter: Args) -> Result<EVAL> {
+    let script = iter.next_arg()?;
+    let num_keys = iter.parse_arg::<i32>()?;
     let mut keys = Vec::with_capacity(num_keys as usize);
     for _ in 0..num_keys {
-        let key = iter.next().unwrap();
+        let key = iter.next_arg()?;
         keys.push(key.as_slice());
     }
     let mut args = Vec::new();
     for arg in iter {
         args.push(arg.as_slice());
     }
-    EVAL {
+    Ok(EVAL {
         script,
         num_keys,
         keys,
         args,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -45,25 +44,24 @@ pub struct EVALSHA<'a> {
     pub args: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_evalsha(mut iter: Iter<Vec<u8>>) -> EVALSHA {
-    let sha1 = iter.next().unwrap();
-    let num_keys = iter.next().unwrap();
-    let num_keys = String::from_utf8_lossy(num_keys).parse::<i32>().unwrap();
+pub(crate) fn parse_evalsha(mut iter: Args) -> Result<EVALSHA> {
+    let sha1 = iter.next_arg()?;
+    let num_keys = iter.parse_arg::<i32>()?;
     let mut keys = Vec::with_capacity(num_keys as usize);
     for _ in 0..num_keys {
-        let key = iter.next().unwrap();
+        let key = iter.next_arg()?;
         keys.push(key.as_slice());
     }
     let mut args = Vec::new();
     for arg in iter {
         args.push(arg.as_slice());
     }
-    EVALSHA {
+    Ok(EVALSHA {
         sha1,
         num_keys,
         keys,
         args,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -71,7 +69,7 @@ pub struct SCRIPTLOAD<'a> {
     pub script: &'a [u8],
 }
 
-pub(crate) fn parse_script_load(mut iter: Iter<Vec<u8>>) -> SCRIPTLOAD {
-    let script = iter.next().unwrap();
-    SCRIPTLOAD { script }
+pub(crate) fn parse_script_load(mut iter: Args) -> Result<SCRIPTLOAD> {
+    let script = iter.next_arg()?;
+    Ok(SCRIPTLOAD { script })
 }
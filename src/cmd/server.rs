@@ -6,24 +6,24 @@ Server相关的命令定义、解析
 [Redis Command Reference]: https://redis.io/commands#server
 */
 
-use std::slice::Iter;
+use crate::cmd::{Args, Result};
 
 #[derive(Debug)]
 pub struct FLUSHDB {
     pub _async: Option<bool>,
 }
 
-pub(crate) fn parse_flushdb(mut iter: Iter<Vec<u8>>) -> FLUSHDB {
+pub(crate) fn parse_flushdb(mut iter: Args) -> Result<FLUSHDB> {
     let mut _async = None;
     if let Some(next_arg) = iter.next() {
         let arg_upper = String::from_utf8_lossy(next_arg).to_uppercase();
         if &arg_upper == "ASYNC" {
             _async = Some(true);
         } else {
-            panic!("Invalid argument")
+            return Err(iter.invalid(format!("unknown option: {}", arg_upper)));
         }
     }
-    FLUSHDB { _async }
+    Ok(FLUSHDB { _async })
 }
 
 #[derive(Debug)]
@@ -31,15 +31,15 @@ pub struct FLUSHALL {
     pub _async: Option<bool>,
 }
 
-pub(crate) fn parse_flushall(mut iter: Iter<Vec<u8>>) -> FLUSHALL {
+pub(crate) fn parse_flushall(mut iter: Args) -> Result<FLUSHALL> {
     let mut _async = None;
     if let Some(next_arg) = iter.next() {
         let arg_upper = String::from_utf8_lossy(next_arg).to_uppercase();
         if &arg_upper == "ASYNC" {
             _async = Some(true);
         } else {
-            panic!("Invalid argument")
+            return Err(iter.invalid(format!("unknown option: {}", arg_upper)));
         }
     }
-    FLUSHALL { _async }
+    Ok(FLUSHALL { _async })
 }
@@ -6,7 +6,7 @@ Sets相关的命令定义、解析
 [Redis Command Reference]: https://redis.io/commands#set
 */
 
-use std::slice::Iter;
+use crate::cmd::{Args, Result};
 
 #[derive(Debug)]
 pub struct SINTERSTORE<'a> {
@@ -14,13 +14,13 @@ pub struct SINTERSTORE<'a> {
     pub keys: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_sinterstore(mut iter: Iter<Vec<u8>>) -> SINTERSTORE {
-    let destination = iter.next().unwrap();
+pub(crate) fn parse_sinterstore(mut iter: Args) -> Result<SINTERSTORE> {
+    let destination = iter.next_arg()?;
     let mut keys = Vec::new();
     for next_arg in iter {
         keys.push(next_arg.as_slice());
     }
-    SINTERSTORE { destination, keys }
+    Ok(SINTERSTORE { destination, keys })
 }
 
 #[derive(Debug)]
@@ -29,13 +29,13 @@ pub struct SADD<'a> {
     pub members: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_sadd(mut iter: Iter<Vec<u8>>) -> SADD {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_sadd(mut iter: Args) -> Result<SADD> {
+    let key = iter.next_arg()?;
     let mut members = Vec::new();
     for member in iter {
         members.push(member.as_slice());
     }
-    SADD { key, members }
+    Ok(SADD { key, members })
 }
 
 #[derive(Debug)]
@@ -44,13 +44,13 @@ pub struct SDIFFSTORE<'a> {
     pub keys: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_sdiffstore(mut iter: Iter<Vec<u8>>) -> SDIFFSTORE {
-    let destination = iter.next().unwrap();
+pub(crate) fn parse_sdiffstore(mut iter: Args) -> Result<SDIFFSTORE> {
+    let destination = iter.next_arg()?;
     let mut keys = Vec::new();
     for key in iter {
         keys.push(key.as_slice());
     }
-    SDIFFSTORE { destination, keys }
+    Ok(SDIFFSTORE { destination, keys })
 }
 
 #[derive(Debug)]
@@ -60,15 +60,15 @@ pub struct SMOVE<'a> {
     pub member: &'a [u8],
 }
 
-pub(crate) fn parse_smove(mut iter: Iter<Vec<u8>>) -> SMOVE {
-    let source = iter.next().unwrap();
-    let destination = iter.next().unwrap();
-    let member = iter.next().unwrap();
-    SMOVE {
+pub(crate) fn parse_smove(mut iter: Args) -> Result<SMOVE> {
+    let source = iter.next_arg()?;
+    let destination = iter.next_arg()?;
+    let member = iter.next_arg()?;
+    Ok(SMOVE {
         source,
         destination,
         member,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -77,13 +77,13 @@ pub struct SREM<'a> {
     pub members: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_srem(mut iter: Iter<Vec<u8>>) -> SREM {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_srem(mut iter: Args) -> Result<SREM> {
+    let key = iter.next_arg()?;
     let mut members = Vec::new();
     for member in iter {
         members.push(member.as_slice());
     }
-    SREM { key, members }
+    Ok(SREM { key, members })
 }
 
 #[derive(Debug)]
@@ -92,11 +92,11 @@ pub struct SUNIONSTORE<'a> {
     pub keys: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_sunionstore(mut iter: Iter<Vec<u8>>) -> SUNIONSTORE {
-    let destination = iter.next().unwrap();
+pub(crate) fn parse_sunionstore(mut iter: Args) -> Result<SUNIONSTORE> {
+    let destination = iter.next_arg()?;
     let mut keys = Vec::new();
     for next_arg in iter {
         keys.push(next_arg.as_slice());
     }
-    SUNIONSTORE { destination, keys }
+    Ok(SUNIONSTORE { destination, keys })
 }
@@ -6,7 +6,7 @@ Sorted Sets相关的命令定义、解析
 [Redis Command Reference]: https://redis.io/commands#sorted_set
 */
 
-use std::slice::Iter;
+use crate::cmd::{Args, Result};
 
 use crate::cmd::sorted_sets::AGGREGATE::{MAX, MIN, SUM};
 use crate::cmd::strings::ExistType;
@@ -29,8 +29,8 @@ pub struct Item<'a> {
     pub member: &'a [u8],
 }
 
-pub(crate) fn parse_zadd(mut iter: Iter<Vec<u8>>) -> ZADD {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_zadd(mut iter: Args) -> Result<ZADD> {
+    let key = iter.next_arg()?;
     let mut exist_type = None;
     let mut ch = None;
     let mut incr = None;
@@ -47,20 +47,20 @@ pub(crate) fn parse_zadd(mut iter: Iter<Vec<u8>>) -> ZADD {
             incr = Some(true);
         } else {
             // score在前，element在后
-            let member = iter.next().unwrap();
+            let member = iter.next_arg()?;
             items.push(Item {
                 score: next_arg,
                 member,
             });
         }
     }
-    ZADD {
+    Ok(ZADD {
         key,
         exist_type,
         ch,
         incr,
         items,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -70,15 +70,15 @@ pub struct ZINCRBY<'a> {
     pub member: &'a [u8],
 }
 
-pub(crate) fn parse_zincrby(mut iter: Iter<Vec<u8>>) -> ZINCRBY {
-    let key = iter.next().unwrap();
-    let increment = iter.next().unwrap();
-    let member = iter.next().unwrap();
-    ZINCRBY {
+pub(crate) fn parse_zincrby(mut iter: Args) -> Result<ZINCRBY> {
+    let key = iter.next_arg()?;
+    let increment = iter.next_arg()?;
+    let member = iter.next_arg()?;
+    Ok(ZINCRBY {
         key,
         increment,
         member,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -97,13 +97,12 @@ pub enum AGGREGATE {
     MAX,
 }
 
-pub(crate) fn parse_zinterstore(mut iter: Iter<Vec<u8>>) -> ZINTERSTORE {
-    let destination = iter.next().unwrap();
-    let num_keys = String::from_utf8_lossy(iter.next().unwrap());
-    let num_keys = num_keys.parse::<i32>().unwrap();
+pub(crate) fn parse_zinterstore(mut iter: Args) -> Result<ZINTERSTORE> {
+    let destination = iter.next_arg()?;
+    let num_keys = iter.parse_arg::<i32>()?;
     let mut keys = Vec::new();
     for _ in 0..num_keys {
-        let next_key = iter.next().unwrap();
+        let next_key = iter.next_arg()?;
         keys.push(next_key.as_slice());
     }
     let mut _weights = Vec::new();
@@ -127,13 +126,13 @@ pub(crate) fn parse_zinterstore(mut iter: Iter<Vec<u8>>) -> ZINTERSTORE {
     } else {
         Some(_weights)
     };
-    ZINTERSTORE {
+    Ok(ZINTERSTORE {
         destination,
         num_keys,
         keys,
         weights,
         aggregate,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -142,13 +141,13 @@ pub struct ZPOPMAX<'a> {
     pub count: Option<&'a [u8]>,
 }
 
-pub(crate) fn parse_zpopmax(mut iter: Iter<Vec<u8>>) -> ZPOPMAX {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_zpopmax(mut iter: Args) -> Result<ZPOPMAX> {
+    let key = iter.next_arg()?;
     let mut count = None;
     if let Some(next_arg) = iter.next() {
         count = Some(next_arg.as_slice());
     }
-    ZPOPMAX { key, count }
+    Ok(ZPOPMAX { key, count })
 }
 
 #[derive(Debug)]
@@ -157,13 +156,13 @@ pub struct ZPOPMIN<'a> {
     pub count: Option<&'a [u8]>,
 }
 
-pub(crate) fn parse_zpopmin(mut iter: Iter<Vec<u8>>) -> ZPOPMIN {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_zpopmin(mut iter: Args) -> Result<ZPOPMIN> {
+    let key = iter.next_arg()?;
     let mut count = None;
     if let Some(next_arg) = iter.next() {
         count = Some(next_arg.as_slice());
     }
-    ZPOPMIN { key, count }
+    Ok(ZPOPMIN { key, count })
 }
 
 #[derive(Debug)]
@@ -172,13 +171,13 @@ pub struct ZREM<'a> {
     pub members: Vec<&'a [u8]>,
 }
 
-pub(crate) fn parse_zrem(mut iter: Iter<Vec<u8>>) -> ZREM {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_zrem(mut iter: Args) -> Result<ZREM> {
+    let key = iter.next_arg()?;
     let mut members = Vec::new();
     for next_arg in iter {
         members.push(next_arg.as_slice());
     }
-    ZREM { key, members }
+    Ok(ZREM { key, members })
 }
 
 #[derive(Debug)]
@@ -188,11 +187,11 @@ pub struct ZREMRANGEBYLEX<'a> {
     pub max: &'a [u8],
 }
 
-pub(crate) fn parse_zremrangebylex(mut iter: Iter<Vec<u8>>) -> ZREMRANGEBYLEX {
-    let key = iter.next().unwrap();
-    let min = iter.next().unwrap();
-    let max = iter.next().unwrap();
-    ZREMRANGEBYLEX { key, min, max }
+pub(crate) fn parse_zremrangebylex(mut iter: Args) -> Result<ZREMRANGEBYLEX> {
+    let key = iter.next_arg()?;
+    let min = iter.next_arg()?;
+    let max = iter.next_arg()?;
+    Ok(ZREMRANGEBYLEX { key, min, max })
 }
 
 #[derive(Debug)]
@@ -202,11 +201,11 @@ pub struct ZREMRANGEBYRANK<'a> {
     pub stop: &'a [u8],
 }
 
-pub(crate) fn parse_zremrangebyrank(mut iter: Iter<Vec<u8>>) -> ZREMRANGEBYRANK {
-    let key = iter.next().unwrap();
-    let start = iter.next().unwrap();
-    let stop = iter.next().unwrap();
-    ZREMRANGEBYRANK { key, start, stop }
+pub(crate) fn parse_zremrangebyrank(mut iter: Args) -> Result<ZREMRANGEBYRANK> {
+    let key = iter.next_arg()?;
+    let start = iter.next_arg()?;
+    let stop = iter.next_arg()?;
+    Ok(ZREMRANGEBYRANK { key, start, stop })
 }
 
 #[derive(Debug)]
@@ -216,11 +215,11 @@ pub struct ZREMRANGEBYSCORE<'a> {
     pub max: &'a [u8],
 }
 
-pub(crate) fn parse_zremrangebyscore(mut iter: Iter<Vec<u8>>) -> ZREMRANGEBYSCORE {
-    let key = iter.next().unwrap();
-    let min = iter.next().unwrap();
-    let max = iter.next().unwrap();
-    ZREMRANGEBYSCORE { key, min, max }
+pub(crate) fn parse_zremrangebyscore(mut iter: Args) -> Result<ZREMRANGEBYSCORE> {
+    let key = iter.next_arg()?;
+    let min = iter.next_arg()?;
+    let max = iter.next_arg()?;
+    Ok(ZREMRANGEBYSCORE { key, min, max })
 }
 
 #[derive(Debug)]
@@ -232,13 +231,12 @@ pub struct ZUNIONSTORE<'a> {
     pub aggregate: Option<AGGREGATE>,
 }
 
-pub(crate) fn parse_zunionstore(mut iter: Iter<Vec<u8>>) -> ZUNIONSTORE {
-    let destination = iter.next().unwrap();
-    let num_keys = String::from_utf8_lossy(iter.next().unwrap());
-    let num_keys = num_keys.parse::<i32>().unwrap();
+pub(crate) fn parse_zunionstore(mut iter: Args) -> Result<ZUNIONSTORE> {
+    let destination = iter.next_arg()?;
+    let num_keys = iter.parse_arg::<i32>()?;
     let mut keys = Vec::new();
     for _ in 0..num_keys {
-        let next_key = iter.next().unwrap();
+        let next_key = iter.next_arg()?;
         keys.push(next_key.as_slice());
     }
     let mut _weights = Vec::new();
@@ -262,11 +260,11 @@ pub(crate) fn parse_zunionstore(mut iter: Iter<Vec<u8>>) -> ZUNIONSTORE {
     } else {
         Some(_weights)
     };
-    ZUNIONSTORE {
+    Ok(ZUNIONSTORE {
         destination,
         num_keys,
         keys,
         weights,
         aggregate,
-    }
+    })
 }
@@ -6,7 +6,7 @@ Stream相关的命令定义、解析
 [Redis Command Reference]: https://redis.io/commands#stream
 */
 
-use core::slice::Iter;
+use crate::cmd::{Args, Result};
 
 use crate::cmd::hashes::Field;
 
@@ -17,14 +17,14 @@ pub struct XACK<'a> {
     pub ids: Vec<&'a Vec<u8>>,
 }
 
-pub(crate) fn parse_xack(mut iter: Iter<Vec<u8>>) -> XACK {
-    let key = iter.next().unwrap();
-    let group = iter.next().unwrap();
+pub(crate) fn parse_xack(mut iter: Args) -> Result<XACK> {
+    let key = iter.next_arg()?;
+    let group = iter.next_arg()?;
     let mut ids = Vec::new();
     for id in iter {
         ids.push(id);
     }
-    XACK { key, group, ids }
+    Ok(XACK { key, group, ids })
 }
 
 #[derive(Debug)]
@@ -34,20 +34,16 @@ pub struct XADD<'a> {
     pub fields: Vec<Field<'a>>,
 }
 
-pub(crate) fn parse_xadd(mut iter: Iter<Vec<u8>>) -> XADD {
-    let key = iter.next().unwrap();
-    let id = iter.next().unwrap();
+pub(crate) fn parse_xadd(mut iter: Args) -> Result<XADD> {
+    let key = iter.next_arg()?;
+    let id = iter.next_arg()?;
     let mut fields = Vec::new();
     
     while let Some(field) = iter.next() {
-        if let Some(value) = iter.next() {
-            let field = Field { name: field, value };
-            fields.push(field);
-        } else {
-            panic!("XADD缺失field value");
-        }
+        let value = iter.next_arg()?;
+        fields.push(Field { name: field, value });
     };
-    XADD { key, id, fields }
+    Ok(XADD { key, id, fields })
 }
 
 #[derive(Debug)]
@@ -64,13 +60,13 @@ pub struct XCLAIM<'a> {
     pub just_id: Option<bool>,
 }
 
-pub(crate) fn parse_xclaim(mut iter: Iter<Vec<u8>>) -> XCLAIM {
-    let key = iter.next().unwrap();
-    let group = iter.next().unwrap();
-    let consumer = iter.next().unwrap();
-    let min_idle_time = iter.next().unwrap();
+pub(crate) fn parse_xclaim(mut iter: Args) -> Result<XCLAIM> {
+    let key = iter.next_arg()?;
+    let group = iter.next_arg()?;
+    let consumer = iter.next_arg()?;
+    let min_idle_time = iter.next_arg()?;
     let mut ids = Vec::new();
-    let id = iter.next().unwrap();
+    let id = iter.next_arg()?;
     ids.push(id);
     let mut idle = None;
     let mut time = None;
@@ -82,13 +78,13 @@ pub(crate) fn parse_xclaim(mut iter: Iter<Vec<u8>>) -> XCLAIM {
         let arg_string = String::from_utf8_lossy(arg);
         let p_arg = &arg_string.to_uppercase();
         if p_arg == "IDLE" {
-            let _idle = iter.next().unwrap();
+            let _idle = iter.next_arg()?;
             idle = Some(_idle);
         } else if p_arg == "TIME" {
-            let _time = iter.next().unwrap();
+            let _time = iter.next_arg()?;
             time = Some(_time);
         } else if p_arg == "RETRYCOUNT" {
-            let _retry_count = iter.next().unwrap();
+            let _retry_count = iter.next_arg()?;
             retry_count = Some(_retry_count);
         } else if p_arg == "FORCE" {
             force = Some(true);
@@ -98,7 +94,7 @@ pub(crate) fn parse_xclaim(mut iter: Iter<Vec<u8>>) -> XCLAIM {
             ids.push(arg);
         }
     }
-    XCLAIM {
+    Ok(XCLAIM {
         key,
         group,
         consumer,
@@ -109,7 +105,7 @@ pub(crate) fn parse_xclaim(mut iter: Iter<Vec<u8>>) -> XCLAIM {
         retry_count,
         force,
         just_id,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -118,13 +114,13 @@ pub struct XDEL<'a> {
     pub ids: Vec<&'a Vec<u8>>,
 }
 
-pub(crate) fn parse_xdel(mut iter: Iter<Vec<u8>>) -> XDEL {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_xdel(mut iter: Args) -> Result<XDEL> {
+    let key = iter.next_arg()?;
     let mut ids = Vec::new();
     for id in iter {
         ids.push(id);
     }
-    XDEL { key, ids }
+    Ok(XDEL { key, ids })
 }
 
 #[derive(Debug)]
@@ -162,7 +158,7 @@ pub struct DelConsumer<'a> {
     pub consumer_name: &'a [u8],
 }
 
-pub(crate) fn parse_xgroup(mut iter: Iter<Vec<u8>>) -> XGROUP {
+pub(crate) fn parse_xgroup(mut iter: Args) -> Result<XGROUP> {
     let mut create = None;
     let mut set_id = None;
     let mut destroy = None;
@@ -172,23 +168,23 @@ pub(crate) fn parse_xgroup(mut iter: Iter<Vec<u8>>) -> XGROUP {
         let arg_string = String::from_utf8_lossy(arg);
         let p_arg = &arg_string.to_uppercase();
         if p_arg == "CREATE" {
-            let key = iter.next().unwrap();
-            let group_name = iter.next().unwrap();
-            let id = iter.next().unwrap();
+            let key = iter.next_arg()?;
+            let group_name = iter.next_arg()?;
+            let id = iter.next_arg()?;
             create = Some(Create { key, group_name, id })
         } else if p_arg == "SETID" {
-            let key = iter.next().unwrap();
-            let group_name = iter.next().unwrap();
-            let id = iter.next().unwrap();
+            let key = iter.next_arg()?;
+            let group_name = iter.next_arg()?;
+            let id = iter.next_arg()?;
             set_id = Some(SetID { key, group_name, id })
         } else if p_arg == "DESTROY" {
-            let key = iter.next().unwrap();
-            let group_name = iter.next().unwrap();
+            let key = iter.next_arg()?;
+            let group_name = iter.next_arg()?;
             destroy = Some(Destroy { key, group_name })
         } else if p_arg == "DELCONSUMER" {
-            let key = iter.next().unwrap();
-            let group_name = iter.next().unwrap();
-            let consumer_name = iter.next().unwrap();
+            let key = iter.next_arg()?;
+            let group_name = iter.next_arg()?;
+            let consumer_name = iter.next_arg()?;
             del_consumer = Some(DelConsumer {
                 key,
                 group_name,
@@ -196,12 +192,12 @@ pub(crate) fn parse_xgroup(mut iter: Iter<Vec<u8>>) -> XGROUP {
             })
         }
     }
-    XGROUP {
+    Ok(XGROUP {
         create,
         set_id,
         destroy,
         del_consumer,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -211,24 +207,23 @@ pub struct XTRIM<'a> {
     pub count: u64,
 }
 
-pub(crate) fn parse_xtrim(mut iter: Iter<Vec<u8>>) -> XTRIM {
-    let key = iter.next().unwrap();
-    iter.next().unwrap();
-    let third = iter.next().unwrap();
+pub(crate) fn parse_xtrim(mut iter: Args) -> Result<XTRIM> {
+    let key = iter.next_arg()?;
+    iter.next_arg()?;
+    let third = iter.next_arg()?;
     let third = String::from_utf8_lossy(third);
     let approximation;
     let count;
     if "~" == third {
         approximation = true;
-        let arg = String::from_utf8_lossy(iter.next().unwrap());
-        count = arg.parse::<u64>().unwrap();
+        count = iter.parse_arg::<u64>()?;
     } else {
         approximation = false;
-        count = third.parse::<u64>().unwrap();
+        count = third.parse::<u64>().map_err(|_| iter.invalid("invalid count"))?;
     }
-    XTRIM {
+    Ok(XTRIM {
         key,
         approximation,
         count,
-    }
+    })
 }
@@ -7,7 +7,7 @@ Strings相关的命令定义、解析
 [Redis Command Reference]: https://redis.io/commands#string
 */
 
-use core::slice::Iter;
+use crate::cmd::{Args, Result};
 
 use crate::cmd::strings::Op::{AND, NOT, OR, XOR};
 
@@ -17,15 +17,16 @@ pub struct APPEND<'a> {
     pub value: &'a [u8],
 }
 
-pub(crate) fn parse_append(mut iter: Iter<Vec<u8>>) -> APPEND {
-    let key = iter.next().unwrap();
-    let value = iter.next().unwrap();
-    APPEND { key, value }
+pub(crate) fn parse_append(mut iter: Args) -> Result<APPEND> {
+    let key = iter.next_arg()?;
+    let value = iter.next_arg()?;
+    Ok(APPEND { key, value })
 }
 
 #[derive(Debug)]
 pub struct BITFIELD<'a> {
     pub key: &'a [u8],
+    /// 按原命令中的顺序排列的各个操作, 包括`OVERFLOW`
     pub statements: Option<Vec<Operation<'a>>>,
     pub overflows: Option<Vec<Overflow>>,
 }
@@ -35,6 +36,8 @@ pub enum Operation<'a> {
     GET(Get<'a>),
     INCRBY(IncrBy<'a>),
     SET(Set<'a>),
+    /// 对之后的`INCRBY`和`SET`生效
+    OVERFLOW(Overflow),
 }
 
 #[derive(Debug)]
@@ -57,48 +60,52 @@ pub struct Set<'a> {
     pub value: &'a [u8],
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Overflow {
     WRAP,
     SAT,
     FAIL,
 }
 
-pub(crate) fn parse_bitfield(mut iter: Iter<Vec<u8>>) -> BITFIELD {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_bitfield(mut iter: Args) -> Result<BITFIELD> {
+    let key = iter.next_arg()?;
 
     let mut statements = Vec::new();
     let mut overflows = Vec::new();
     while let Some(next_arg) = iter.next() {
         let arg_upper = &String::from_utf8_lossy(next_arg).to_uppercase();
         if arg_upper == "GET" {
-            let _type = iter.next().expect("bitfield 缺失get type");
-            let offset = iter.next().expect("bitfield 缺失get offset");
+            let _type = iter.next_arg()?;
+            let offset = iter.next_arg()?;
             statements.push(Operation::GET(Get { _type, offset }));
         } else if arg_upper == "SET" {
-            let _type = iter.next().unwrap();
-            let offset = iter.next().expect("bitfield 缺失SET offset");
-            let value = iter.next().expect("bitfield 缺失SET offset");
+            let _type = iter.next_arg()?;
+            let offset = iter.next_arg()?;
+            let value = iter.next_arg()?;
             statements.push(Operation::SET(Set { _type, offset, value }));
         } else if arg_upper == "INCRBY" {
-            let _type = iter.next().expect("bitfield 缺失INCR type");
-            let offset = iter.next().expect("bitfield 缺失INCR offset");
-            let increment = iter.next().expect("bitfield 缺失INCR offset");
+            let _type = iter.next_arg()?;
+            let offset = iter.next_arg()?;
+            let increment = iter.next_arg()?;
             statements.push(Operation::INCRBY(IncrBy {
                 _type,
                 offset,
                 increment,
             }));
         } else if arg_upper == "OVERFLOW" {
-            let _type = String::from_utf8_lossy(iter.next().expect("bitfield 缺失OVERFLOW type"));
+            let _type = String::from_utf8_lossy(iter.next_arg()?);
             let type_upper = &_type.to_uppercase();
-            if type_upper == "FAIL" {
-                overflows.push(Overflow::FAIL);
+            let overflow = if type_upper == "FAIL" {
+                Overflow::FAIL
             } else if type_upper == "SAT" {
-                overflows.push(Overflow::SAT);
+                Overflow::SAT
             } else if type_upper == "WRAP" {
-                overflows.push(Overflow::WRAP);
-            }
+                Overflow::WRAP
+            } else {
+                continue;
+            };
+            statements.push(Operation::OVERFLOW(overflow));
+            overflows.push(overflow);
         }
     }
 
@@ -112,11 +119,11 @@ pub(crate) fn parse_bitfield(mut iter: Iter<Vec<u8>>) -> BITFIELD {
     } else {
         Some(overflows)
     };
-    BITFIELD {
+    Ok(BITFIELD {
         key,
         statements: _statements,
         overflows: _overflows,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -134,9 +141,9 @@ pub enum Op {
     NOT,
 }
 
-pub(crate) fn parse_bitop(mut iter: Iter<Vec<u8>>) -> BITOP {
+pub(crate) fn parse_bitop(mut iter: Args) -> Result<BITOP> {
     let operation;
-    let op = String::from_utf8_lossy(iter.next().unwrap()).to_uppercase();
+    let op = String::from_utf8_lossy(iter.next_arg()?).to_uppercase();
     if &op == "AND" {
         operation = AND;
     } else if &op == "OR" {
@@ -146,22 +153,22 @@ pub(crate) fn parse_bitop(mut iter: Iter<Vec<u8>>) -> BITOP {
     } else if &op == "NOT" {
         operation = NOT;
     } else {
-        panic!("bitop命令缺失operation")
+        return Err(iter.invalid(format!("unknown operation: {}", op)));
     }
-    let dest_key = iter.next().unwrap();
+    let dest_key = iter.next_arg()?;
 
     let mut keys = Vec::new();
-    for next_arg in iter {
+    for next_arg in &mut iter {
         keys.push(next_arg);
     }
     if keys.is_empty() {
-        panic!("bitop命令缺失input key")
+        return Err(iter.missing());
     }
-    BITOP {
+    Ok(BITOP {
         operation,
         dest_key,
         keys,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -179,6 +186,10 @@ pub enum ExpireType {
     EX,
     // milliseconds -- Set the specified expire time, in milliseconds.
     PX,
+    // timestamp-seconds -- Set the specified Unix time at which the key will expire, in seconds.
+    EXAT,
+    // timestamp-milliseconds -- Set the specified Unix time at which the key will expire, in milliseconds.
+    PXAT,
 }
 
 #[derive(Debug)]
@@ -189,10 +200,10 @@ pub enum ExistType {
     XX,
 }
 
-pub(crate) fn parse_set(mut iter: Iter<Vec<u8>>) -> SET {
-    let key = iter.next().unwrap();
+pub(crate) fn parse_set(mut iter: Args) -> Result<SET> {
+    let key = iter.next_arg()?;
 
-    let value = iter.next().unwrap();
+    let value = iter.next_arg()?;
 
     let mut expire_time = None;
     let mut expire_type = None;
@@ -207,6 +218,10 @@ pub(crate) fn parse_set(mut iter: Iter<Vec<u8>>) -> SET {
             expire_type = Some(ExpireType::EX);
         } else if p_arg == "PX" {
             expire_type = Some(ExpireType::PX);
+        } else if p_arg == "EXAT" {
+            expire_type = Some(ExpireType::EXAT);
+        } else if p_arg == "PXAT" {
+            expire_type = Some(ExpireType::PXAT);
         } else if p_arg == "NX" {
             exist_type = Some(ExistType::NX);
         } else if p_arg == "XX" {
@@ -225,13 +240,13 @@ pub(crate) fn parse_set(mut iter: Iter<Vec<u8>>) -> SET {
         expire = Some((x,y))
     };
     
-    SET {
+    Ok(SET {
         key,
         value,
         exist_type,
         expire,
         keep_ttl,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -241,11 +256,11 @@ pub struct SETEX<'a> {
     pub value: &'a [u8],
 }
 
-pub(crate) fn parse_setex(mut iter: Iter<Vec<u8>>) -> SETEX {
-    let key = iter.next().unwrap();
-    let seconds = iter.next().unwrap();
-    let value = iter.next().unwrap();
-    SETEX { key, seconds, value }
+pub(crate) fn parse_setex(mut iter: Args) -> Result<SETEX> {
+    let key = iter.next_arg()?;
+    let seconds = iter.next_arg()?;
+    let value = iter.next_arg()?;
+    Ok(SETEX { key, seconds, value })
 }
 
 #[derive(Debug)]
@@ -254,10 +269,10 @@ pub struct SETNX<'a> {
     pub value: &'a [u8],
 }
 
-pub(crate) fn parse_setnx(mut iter: Iter<Vec<u8>>) -> SETNX {
-    let key = iter.next().unwrap();
-    let value = iter.next().unwrap();
-    SETNX { key, value }
+pub(crate) fn parse_setnx(mut iter: Args) -> Result<SETNX> {
+    let key = iter.next_arg()?;
+    let value = iter.next_arg()?;
+    Ok(SETNX { key, value })
 }
 
 #[derive(Debug)]
@@ -267,15 +282,15 @@ pub struct PSETEX<'a> {
     pub value: &'a [u8],
 }
 
-pub(crate) fn parse_psetex(mut iter: Iter<Vec<u8>>) -> PSETEX {
-    let key = iter.next().unwrap();
-    let milliseconds = iter.next().unwrap();
-    let value = iter.next().unwrap();
-    PSETEX {
+pub(crate) fn parse_psetex(mut iter: Args) -> Result<PSETEX> {
+    let key = iter.next_arg()?;
+    let milliseconds = iter.next_arg()?;
+    let value = iter.next_arg()?;
+    Ok(PSETEX {
         key,
         milliseconds,
         value,
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -285,11 +300,11 @@ pub struct SETRANGE<'a> {
     pub value: &'a [u8],
 }
 
-pub(crate) fn parse_setrange(mut iter: Iter<Vec<u8>>) -> SETRANGE {
-    let key = iter.next().unwrap();
-    let offset = iter.next().unwrap();
-    let value = iter.next().unwrap();
-    SETRANGE { key, offset, value }
+pub(crate) fn parse_setrange(mut iter: Args) -> Result<SETRANGE> {
+    let key = iter.next_arg()?;
+    let offset = iter.next_arg()?;
+    let value = iter.next_arg()?;
+    Ok(SETRANGE { key, offset, value })
 }
 
 #[derive(Debug)]
@@ -297,9 +312,9 @@ pub struct DECR<'a> {
     pub key: &'a [u8],
 }
 
-pub(crate) fn parse_decr(mut iter: Iter<Vec<u8>>) -> DECR {
-    let key = iter.next().unwrap();
-    DECR { key }
+pub(crate) fn parse_decr(mut iter: Args) -> Result<DECR> {
+    let key = iter.next_arg()?;
+    Ok(DECR { key })
 }
 
 #[derive(Debug)]
@@ -308,10 +323,10 @@ pub struct DECRBY<'a> {
     pub decrement: &'a [u8],
 }
 
-pub(crate) fn parse_decrby(mut iter: Iter<Vec<u8>>) -> DECRBY {
-    let key = iter.next().unwrap();
-    let decrement = iter.next().unwrap();
-    DECRBY { key, decrement }
+pub(crate) fn parse_decrby(mut iter: Args) -> Result<DECRBY> {
+    let key = iter.next_arg()?;
+    let decrement = iter.next_arg()?;
+    Ok(DECRBY { key, decrement })
 }
 
 #[derive(Debug)]
@@ -319,9 +334,9 @@ pub struct INCR<'a> {
     pub key: &'a [u8],
 }
 
-pub(crate) fn parse_incr(mut iter: Iter<Vec<u8>>) -> INCR {
-    let key = iter.next().unwrap();
-    INCR { key }
+pub(crate) fn parse_incr(mut iter: Args) -> Result<INCR> {
+    let key = iter.next_arg()?;
+    Ok(INCR { key })
 }
 
 #[derive(Debug)]
@@ -330,10 +345,10 @@ pub struct INCRBY<'a> {
     pub increment: &'a [u8],
 }
 
-pub(crate) fn parse_incrby(mut iter: Iter<Vec<u8>>) -> INCRBY {
-    let key = iter.next().unwrap();
-    let increment = iter.next().unwrap();
-    INCRBY { key, increment }
+pub(crate) fn parse_incrby(mut iter: Args) -> Result<INCRBY> {
+    let key = iter.next_arg()?;
+    let increment = iter.next_arg()?;
+    Ok(INCRBY { key, increment })
 }
 
 #[derive(Debug)]
@@ -347,17 +362,16 @@ pub struct MSET<'a> {
     pub key_values: Vec<KeyValue<'a>>,
 }
 
-pub(crate) fn parse_mset(mut iter: Iter<Vec<u8>>) -> MSET {
+pub(crate) fn parse_mset(mut iter: Args) -> Result<MSET> {
     let mut key_values = Vec::new();
     while let Some(key) = iter.next() {
-        if let Some(value) = iter.next() {
-            key_values.push(KeyValue { key, value });
-        }
+        let value = iter.next_arg()?;
+        key_values.push(KeyValue { key, value });
     }
     if key_values.is_empty() {
-        panic!("mset命令缺失key value");
+        return Err(iter.missing());
     }
-    MSET { key_values }
+    Ok(MSET { key_values })
 }
 
 #[derive(Debug)]
@@ -365,17 +379,16 @@ pub struct MSETNX<'a> {
     pub key_values: Vec<KeyValue<'a>>,
 }
 
-pub(crate) fn parse_msetnx(mut iter: Iter<Vec<u8>>) -> MSETNX {
+pub(crate) fn parse_msetnx(mut iter: Args) -> Result<MSETNX> {
     let mut key_values = Vec::new();
     while let Some(key) = iter.next() {
-        if let Some(value) = iter.next() {
-            key_values.push(KeyValue { key, value });
-        }
+        let value = iter.next_arg()?;
+        key_values.push(KeyValue { key, value });
     }
     if key_values.is_empty() {
-        panic!("msetnx命令缺失key value");
+        return Err(iter.missing());
     }
-    MSETNX { key_values }
+    Ok(MSETNX { key_values })
 }
 
 #[derive(Debug)]
@@ -385,11 +398,11 @@ pub struct SETBIT<'a> {
     pub value: &'a [u8],
 }
 
-pub(crate) fn parse_setbit(mut iter: Iter<Vec<u8>>) -> SETBIT {
-    let key = iter.next().unwrap();
-    let offset = iter.next().unwrap();
-    let value = iter.next().unwrap();
-    SETBIT { key, value, offset }
+pub(crate) fn parse_setbit(mut iter: Args) -> Result<SETBIT> {
+    let key = iter.next_arg()?;
+    let offset = iter.next_arg()?;
+    let value = iter.next_arg()?;
+    Ok(SETBIT { key, value, offset })
 }
 
 #[derive(Debug)]
@@ -398,8 +411,8 @@ pub struct GETSET<'a> {
     pub value: &'a [u8],
 }
 
-pub(crate) fn parse_getset(mut iter: Iter<Vec<u8>>) -> GETSET {
-    let key = iter.next().unwrap();
-    let value = iter.next().unwrap();
-    GETSET { key, value }
+pub(crate) fn parse_getset(mut iter: Args) -> Result<GETSET> {
+    let key = iter.next_arg()?;
+    let value = iter.next_arg()?;
+    Ok(GETSET { key, value })
 }
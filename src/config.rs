@@ -1,15 +1,28 @@
 use std::time;
 
+use crate::cmd::ParseErrorPolicy;
+
 #[derive(Default)]
 pub struct Config{
     pub host: String,
     pub port: u16,
+    /// 读超时, 接收命令流时超过此时长未收到master的任何数据(包括PING)即认为连接已断开
     pub read_timeout: Option<time::Duration>,
     pub write_timeout: Option<time::Duration>,
     pub username: Option<String>,
     pub password: Option<String>,
+    /// 向master发送`REPLCONF ACK`的间隔, 默认1秒
+    pub heartbeat_interval: Option<time::Duration>,
+    /// 使用TLS连接master
     pub is_tls_enabled: bool,
+    /// 不校验master的证书与主机名
     pub is_tls_insecure: bool,
+    /// PKCS#12格式的客户端证书文件路径, 用于双向认证
     pub identity: Option<String>,
+    /// 客户端证书文件的密码
     pub identity_passwd: Option<String>,
-}
\ No newline at end of file
+    /// 命令解析失败时的处理方式, 默认作为[Command::Other]继续复制
+    ///
+    /// [Command::Other]: ../cmd/enum.Command.html#variant.Other
+    pub command_error_policy: ParseErrorPolicy,
+}
@@ -1,5 +1,8 @@
 use anyhow::{anyhow, Result};
+use log::debug;
 
+use crate::error::RedisSyncError;
+use crate::rdb::RDB_EOF_MARK_LEN;
 use crate::resp::{Resp, RespDecode, Type};
 use redis::ToRedisArgs;
 use std::io::{Read, Write};
@@ -55,7 +58,7 @@ pub trait Connect: Read + Write {
     fn reply(&mut self) -> Result<()> {
         match self.decode_resp()? {
             Resp::String(s) => {
-                println!("{:?}", s);
+                debug!("{:?}", s);
             }
             Resp::Error(err) => {
                 if (err.contains("NOAUTH") || err.contains("NOPERM"))
@@ -134,69 +137,19 @@ pub trait Connect: Read + Write {
         let result = redis::pack_command(&args);
         self.write_all(&result)?;
 
-        match self.decode_resp() {
-            Err(err) => {
-                return Err(anyhow!(" decode_resp err:{:?} ", err));
-            }
-            Ok(response) => {
-                if let Resp::String(resp) = &response {
-                    println!("{:?}", resp);
-                    let mut next_type = NextStep::ChangeMode;
-                    let (mut repl_id, mut repl_offset, mut length) = ("".to_string(), 0i64, 0i64);
-                    if resp.starts_with("FULLRESYNC") {
-                        let mut iter = resp.split_whitespace();
-
-                        if let Some(_repl_id) = iter.nth(1) {
-                            repl_id = _repl_id.to_string();
-                        } else {
-                            return Err(anyhow!("Expect replication id, but got None"));
-                        }
-                        if let Some(_repl_offset) = iter.next() {
-                            repl_offset = _repl_offset.parse::<i64>().expect("parse offset err");
-                        } else {
-                            return Err(anyhow!("Expect replication offset, but got None"));
-                        }
-
-                        if let Type::BulkString = self.decode_type()? {
-                            let reply = self.decode_string()?;
-                            if reply.starts_with("EOF") {
-                                length = -1;
-                            } else {
-                                length = reply.parse::<i64>().expect("lenth parse err");
-                            }
-                        } else {
-                            return Err(anyhow!("Expect BulkString response"));
-                        }
-
-                        next_type = NextStep::FullSync;
-                    } else if resp.starts_with("CONTINUE") {
-                        let mut iter = resp.split_whitespace();
-                        if let Some(_repl_id) = iter.nth(1) {
-                            if repl_id != _repl_id {
-                                repl_id = _repl_id.to_owned();
-                            }
-                        }
-                        next_type = NextStep::PartialResync;
-                        length = -1;
-                    } else if resp.starts_with("NOMASTERLINK") || resp.starts_with("LOADING") {
-                        next_type = NextStep::Wait;
-                        length = -1;
-                    };
-                    return Ok(PsyncResp {
-                        next_step: next_type,
-                        repl_id,
-                        repl_offset,
-                        length,
-                    });
-                }
+        let response = self.decode_resp().map_err(|err| err.context("decode_resp err"))?;
+        debug!("{:?}", response);
+        let mut psync_resp = psync_reply(response)?;
+        if psync_resp.next_step == NextStep::FullSync {
+            if let Type::BulkString = self.decode_type()? {
+                let reply = self.decode_string()?;
+                psync_resp.length = parse_rdb_length(&reply)?;
+                psync_resp.eof_mark = parse_eof_mark(&reply)?;
+            } else {
+                return Err(anyhow!("Expect BulkString response"));
             }
         }
-        Ok(PsyncResp {
-            next_step: NextStep::Wait,
-            repl_id: "".to_string(),
-            repl_offset: 0,
-            length: 0,
-        })
+        Ok(psync_resp)
     }
 
     fn replconf_ack(&mut self, repl_offset: String) -> Result<()> {
@@ -216,12 +169,84 @@ pub trait Connect: Read + Write {
     }
 }
 
+/// 解析PSYNC的应答, master尚未就绪(`NOMASTERLINK`, `LOADING`)时等待后重试, 其他错误应答返回[RedisSyncError::Protocol]
+///
+/// [RedisSyncError::Protocol]: ../error/enum.RedisSyncError.html#variant.Protocol
+pub(crate) fn psync_reply(response: Resp) -> Result<PsyncResp> {
+    match response {
+        Resp::String(resp) => parse_psync_reply(&resp),
+        Resp::Error(err) if err.starts_with("NOMASTERLINK") || err.starts_with("LOADING") => Ok(PsyncResp {
+            next_step: NextStep::Wait,
+            repl_id: "".to_string(),
+            repl_offset: 0,
+            length: -1,
+            eof_mark: None,
+        }),
+        Resp::Error(err) => Err(RedisSyncError::Protocol(format!("psync err: {}", err)).into()),
+        response => Err(RedisSyncError::Protocol(format!("unexpected psync reply: {:?}", response)).into()),
+    }
+}
+
+/// 解析PSYNC的应答, 全量同步时RDB的长度需要调用方从随后的BulkString中读取
+pub(crate) fn parse_psync_reply(resp: &str) -> Result<PsyncResp> {
+    let protocol = |reason: String| anyhow::Error::from(RedisSyncError::Protocol(reason));
+    let mut next_type = NextStep::Wait;
+    let (mut repl_id, mut repl_offset, mut length) = ("".to_string(), 0i64, -1i64);
+    if resp.starts_with("FULLRESYNC") {
+        let mut iter = resp.split_whitespace().skip(1);
+        repl_id = iter
+            .next()
+            .ok_or_else(|| protocol(format!("expect replication id in psync reply: {}", resp)))?
+            .to_string();
+        repl_offset = iter
+            .next()
+            .and_then(|offset| offset.parse::<i64>().ok())
+            .ok_or_else(|| protocol(format!("expect replication offset in psync reply: {}", resp)))?;
+        next_type = NextStep::FullSync;
+        length = 0;
+    } else if resp.starts_with("CONTINUE") {
+        // master切换后会带上新的复制ID
+        if let Some(_repl_id) = resp.split_whitespace().nth(1) {
+            repl_id = _repl_id.to_owned();
+        }
+        next_type = NextStep::PartialResync;
+    } else if !(resp.starts_with("NOMASTERLINK") || resp.starts_with("LOADING")) {
+        return Err(protocol(format!("unexpected psync reply: {}", resp)));
+    }
+    Ok(PsyncResp {
+        next_step: next_type,
+        repl_id,
+        repl_offset,
+        length,
+        eof_mark: None,
+    })
+}
+
+/// 解析全量同步时RDB的长度, 无盘复制(`EOF:<40 bytes delimiter>`)时为-1
+pub(crate) fn parse_rdb_length(reply: &str) -> Result<i64> {
+    if reply.starts_with("EOF") {
+        return Ok(-1);
+    }
+    match reply.parse::<i64>() {
+        Ok(length) if length >= 0 => Ok(length),
+        _ => Err(RedisSyncError::Protocol(format!("invalid rdb length: {}", reply)).into()),
+    }
+}
+
+/// 无盘复制时`$EOF:<mark>`中的结束标记, 非无盘复制时为`None`
+pub(crate) fn parse_eof_mark(reply: &str) -> Result<Option<Vec<u8>>> {
+    match reply.strip_prefix("EOF:") {
+        Some(mark) if mark.len() == RDB_EOF_MARK_LEN => Ok(Some(mark.as_bytes().to_vec())),
+        Some(mark) => Err(RedisSyncError::Protocol(format!("invalid eof mark: {}", mark)).into()),
+        None => Ok(None),
+    }
+}
+
 #[warn(dead_code)]
 #[derive(Debug, PartialEq)]
 pub enum NextStep {
     FullSync,
     PartialResync,
-    ChangeMode,
     Wait,
 }
 
@@ -232,26 +257,27 @@ pub struct PsyncResp {
     pub repl_id: String,
     pub repl_offset: i64,
     pub length: i64,
+    /// 无盘复制时RDB之后紧跟的40字节结束标记
+    pub eof_mark: Option<Vec<u8>>,
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
         cmd,
-        rdb::{RDBDecode, RDBParser},
+        rdb::RDBParser,
         resp::{Resp, RespDecode},
     };
 
-    use super::Connect;
+    use super::{Connect, NextStep};
+    use crate::error::RedisSyncError;
     use crate::{Event, EventHandler};
-    use byteorder::ReadBytesExt;
     use redis::ToRedisArgs;
     use std::{
-        io::{self, Read, Write},
         net::TcpStream,
         sync::{atomic::{AtomicBool, AtomicI64, Ordering}, Arc},
         thread::{sleep, self},
-        time::Duration, ops::Add, process::Command,
+        time::Duration, process::Command,
     };
 
     pub struct PrintlnEventHandler {}
@@ -286,6 +312,42 @@ mod test {
             .expect("ping fail");
     }
 
+    #[test]
+    fn test_parse_eof_mark() {
+        let mark = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(super::parse_eof_mark(&format!("EOF:{}", mark)).unwrap(), Some(mark.as_bytes().to_vec()));
+        assert_eq!(super::parse_eof_mark("1024").unwrap(), None);
+        assert!(super::parse_eof_mark("EOF:abc").is_err());
+    }
+
+    #[test]
+    fn test_parse_psync_reply() {
+        let resp = super::parse_psync_reply("FULLRESYNC 8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb 100").unwrap();
+        assert_eq!(resp.next_step, NextStep::FullSync);
+        assert_eq!((resp.repl_id.as_str(), resp.repl_offset), ("8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb", 100));
+        let resp = super::parse_psync_reply("CONTINUE 8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb").unwrap();
+        assert_eq!(resp.next_step, NextStep::PartialResync);
+        assert_eq!(resp.repl_id, "8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb");
+        assert_eq!(super::parse_psync_reply("CONTINUE").unwrap().repl_id, "");
+
+        for reply in ["FULLRESYNC", "FULLRESYNC 8371b4fb 1x", "OK"] {
+            let err = super::parse_psync_reply(reply).unwrap_err();
+            assert!(matches!(err.downcast_ref::<RedisSyncError>(), Some(RedisSyncError::Protocol(_))), "{}", reply);
+        }
+
+        let wait = super::psync_reply(Resp::Error("NOMASTERLINK Can't SYNC while not connected with my master".to_string()));
+        assert_eq!(wait.unwrap().next_step, NextStep::Wait);
+        assert_eq!(super::psync_reply(Resp::Error("LOADING".to_string())).unwrap().next_step, NextStep::Wait);
+        let err = super::psync_reply(Resp::Error("ERR unknown command".to_string())).unwrap_err();
+        assert!(err.to_string().contains("ERR unknown command"));
+        assert!(super::psync_reply(Resp::Int(1)).is_err());
+
+        assert_eq!(super::parse_rdb_length("1024").unwrap(), 1024);
+        assert_eq!(super::parse_rdb_length("EOF:0123456789abcdef0123456789abcdef01234567").unwrap(), -1);
+        assert!(super::parse_rdb_length("10x").is_err());
+        assert!(super::parse_rdb_length("-5").is_err());
+    }
+
     #[test]
     fn test_eof() {
         assert_eq!("eof".to_redis_args()[0], b"eof")
@@ -329,17 +391,17 @@ mod test {
                         println!("Disk-less replication.");
                     }
                     let mut handler = PrintlnEventHandler {};
-                    let _ = stream
+                    stream
                         .parse(&mut handler, Arc::new(AtomicBool::new(true)))
                         .expect("pars rdb err");
-                    if res.length == -1 {
-                        println!("skip rdb io");
-                        crate::io::skip(&mut stream, 40).expect("skip err");
+                    if let Some(expected) = &res.eof_mark {
+                        let mut mark = [0; crate::rdb::RDB_EOF_MARK_LEN];
+                        std::io::Read::read_exact(&mut stream, &mut mark).expect("read eof mark err");
+                        crate::rdb::check_eof_mark(expected, &mark).expect("eof mark err");
                     }
                     break;
                 }
                 super::NextStep::PartialResync => todo!(),
-                super::NextStep::ChangeMode => todo!(),
                 super::NextStep::Wait => {sleep(Duration::from_secs(1))},
             };
         }
@@ -347,7 +409,7 @@ mod test {
         let repl_offset = Arc::new(AtomicI64::from(res.repl_offset));
         let repl_offset_arc = Arc::clone(&repl_offset);
         let mut conn_clone = stream.try_clone().unwrap();
-        let handle = thread::Builder::new()
+        let _handle = thread::Builder::new()
         .name("redis-sync background".to_string())
         .spawn(move || {
             loop{
@@ -379,7 +441,7 @@ mod test {
                         panic!("Expected BulkString response");
                     }
                 }
-                cmd::parse(vec, &mut handler);
+                cmd::parse(vec, &mut handler, cmd::ParseErrorPolicy::default()).expect("parse err");
                 let offset = repl_offset.load(Ordering::Relaxed);
                 repl_offset.store(offset+size, Ordering::SeqCst);
                 // clone_stream.replconf_ack(offset.to_string()).expect("err");
@@ -389,6 +451,7 @@ mod test {
         }
     }
 
+    #[allow(clippy::zombie_processes)]
     fn start_redis_server(rdb: &str, port: u16) -> u32 {
         // redis-server --port 6379 --daemonize no --dbfilename rdb --dir ./tests/rdb
         let child = Command::new("redis-server")
@@ -406,9 +469,10 @@ mod test {
             .arg(port.to_string())
             .spawn()
             .expect("failed to start redis-server");
-        return child.id();
+        child.id()
     }
 
+    #[allow(clippy::zombie_processes)]
     fn start_auth_redis_server(rdb: &str, port: u16) -> u32 {
         // redis-server --port 6379 --daemonize no --dbfilename rdb --dir ./tests/rdb
         let child = Command::new("redis-server")
@@ -428,7 +492,7 @@ mod test {
             .arg("123")
             .spawn()
             .expect("failed to start redis-server");
-        return child.id();
+        child.id()
     }
 
     fn shutdown_redis(pid: u32) {
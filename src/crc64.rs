@@ -0,0 +1,44 @@
+// Redis使用的crc64算法, Jones多项式, 输入输出均反转, 初始值及结果异或值都为0
+const POLY: u64 = 0xad93d23594c935a9;
+
+const TABLE: [u64; 256] = table();
+
+const fn table() -> [u64; 256] {
+    // 反转后的多项式
+    let poly = POLY.reverse_bits();
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ poly } else { crc >> 1 };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// 在`crc`的基础上继续计算`data`的crc64, 初始值为0
+pub(crate) fn crc64(mut crc: u64, data: &[u8]) -> u64 {
+    for byte in data {
+        crc = TABLE[((crc ^ *byte as u64) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod test {
+    use crate::crc64::crc64;
+
+    #[test]
+    fn test_crc64() {
+        assert_eq!(crc64(0, b"123456789"), 0xe9c6d914c4b8d9ca);
+        assert_eq!(crc64(0, b""), 0);
+        // 分段计算与一次计算的结果相同
+        let crc = crc64(0, b"1234");
+        assert_eq!(crc64(crc, b"56789"), 0xe9c6d914c4b8d9ca);
+    }
+}
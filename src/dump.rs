@@ -0,0 +1,320 @@
+/*!
+DUMP payload的编解码
+
+`DUMP`命令返回、`RESTORE`命令接收的payload由三部分组成:
+
+* 值的类型及值, 与RDB中的格式相同, 但不包括key
+* 2个字节的RDB版本, 小端
+* 8个字节的crc64, 小端, 覆盖之前的全部内容
+
+[decode]将[RESTORE]中的payload解析为[OwnedObject], [encode]则将[OwnedObject]编码为payload,
+可以通过一条`RESTORE`命令将数据发送给目标端。
+
+[decode]: fn.decode.html
+[encode]: fn.encode.html
+[RESTORE]: ../cmd/keys/struct.RESTORE.html
+[OwnedObject]: enum.OwnedObject.html
+*/
+
+use std::io::{self, Cursor, Read};
+
+use crate::crc64::crc64;
+use crate::error::RedisSyncError;
+use crate::rdb::*;
+use crate::writer::{RDBEncode, RDBWriter, WriteOptions, MAX_RDB_VERSION};
+use crate::{Event, EventHandler};
+
+/// payload末尾的RDB版本及crc64的长度
+const FOOTER_LEN: usize = 10;
+
+/// DUMP中没有元信息, 过期时间等由`RESTORE`命令的参数指定
+static META: Meta = Meta {
+    db: 0,
+    expire: None,
+    evict: None,
+};
+
+/// 持有全部数据的[Object], 集合类数据分批产生的事件已合并为一个值
+///
+/// [Object]: ../rdb/enum.Object.html
+#[derive(Debug)]
+pub enum OwnedObject {
+    String(Vec<u8>, Vec<u8>),
+    List(Vec<u8>, Vec<Vec<u8>>),
+    Set(Vec<u8>, Vec<Vec<u8>>),
+    SortedSet(Vec<u8>, Vec<Item>),
+    Hash(Vec<u8>, Vec<Field>),
+    Module(Vec<u8>, Box<dyn Module>),
+    Stream(Vec<u8>, Stream<'static>),
+}
+
+impl OwnedObject {
+    pub fn key(&self) -> &[u8] {
+        match self {
+            OwnedObject::String(key, _)
+            | OwnedObject::List(key, _)
+            | OwnedObject::Set(key, _)
+            | OwnedObject::SortedSet(key, _)
+            | OwnedObject::Hash(key, _)
+            | OwnedObject::Module(key, _)
+            | OwnedObject::Stream(key, _) => key,
+        }
+    }
+}
+
+/// 收集[read_object]产生的事件, 合并为一个[OwnedObject]
+///
+/// [read_object]: ../rdb/trait.DefaultRDBParser.html#method.read_object
+#[derive(Default)]
+struct Collector {
+    object: Option<OwnedObject>,
+}
+
+impl EventHandler for Collector {
+    fn handle(&mut self, event: Event) {
+        let object = match event {
+            Event::RDB(object) => object,
+            _ => return,
+        };
+        match (&mut self.object, object) {
+            (Some(OwnedObject::List(_, values)), Object::List(list)) => values.extend_from_slice(list.values),
+            (Some(OwnedObject::Set(_, members)), Object::Set(set)) => members.extend_from_slice(set.members),
+            (Some(OwnedObject::SortedSet(_, items)), Object::SortedSet(zset)) => items.extend_from_slice(zset.items),
+            (Some(OwnedObject::Hash(_, fields)), Object::Hash(hash)) => fields.extend_from_slice(hash.fields),
+            (_, object) => {
+                self.object = match object {
+                    Object::String(kv) => Some(OwnedObject::String(kv.key.to_vec(), kv.value.to_vec())),
+                    Object::List(list) => Some(OwnedObject::List(list.key.to_vec(), list.values.to_vec())),
+                    Object::Set(set) => Some(OwnedObject::Set(set.key.to_vec(), set.members.to_vec())),
+                    Object::SortedSet(zset) => Some(OwnedObject::SortedSet(zset.key.to_vec(), zset.items.to_vec())),
+                    Object::Hash(hash) => Some(OwnedObject::Hash(hash.key.to_vec(), hash.fields.to_vec())),
+                    Object::Module(key, module, _) => Some(OwnedObject::Module(key, module)),
+                    Object::Stream(key, stream) => Some(OwnedObject::Stream(
+                        key,
+                        Stream {
+                            entries: stream.entries,
+                            groups: stream.groups,
+                            last_id: stream.last_id,
+                            first_id: stream.first_id,
+                            max_deleted_id: stream.max_deleted_id,
+                            added_entries_count: stream.added_entries_count,
+                            meta: &META,
+                        },
+                    )),
+                    _ => return,
+                };
+            }
+        }
+    }
+}
+
+fn dump_error(reason: String) -> RedisSyncError {
+    RedisSyncError::MalformedRDB {
+        value_type: None,
+        key: None,
+        offset: 0,
+        reason,
+    }
+}
+
+/// 解析`key`对应的DUMP payload, 先校验末尾的RDB版本及crc64
+///
+/// payload的格式错误或版本高于支持的版本时返回[RedisSyncError::MalformedRDB], crc64不一致时返回[RedisSyncError::Checksum]
+///
+/// [RedisSyncError::MalformedRDB]: ../error/enum.RedisSyncError.html#variant.MalformedRDB
+/// [RedisSyncError::Checksum]: ../error/enum.RedisSyncError.html#variant.Checksum
+pub fn decode(key: &[u8], payload: &[u8], modules: &mut ModuleRegistry) -> Result<OwnedObject, RedisSyncError> {
+    if payload.len() <= FOOTER_LEN {
+        return Err(dump_error(format!("dump payload is too short: {} bytes", payload.len())));
+    }
+    let (value, footer) = payload.split_at(payload.len() - FOOTER_LEN);
+    let rdb_version = u16::from_le_bytes([footer[0], footer[1]]);
+    if rdb_version > MAX_RDB_VERSION as u16 {
+        return Err(dump_error(format!("unsupported dump rdb version: {}", rdb_version)));
+    }
+    // 与Redis一致, DUMP的crc64不能关闭
+    let expected = u64::from_le_bytes(footer[2..].try_into().unwrap());
+    let actual = crc64(0, &payload[..payload.len() - 8]);
+    if expected != actual {
+        return Err(RedisSyncError::Checksum { expected, actual });
+    }
+
+    // payload中没有key, 补上key后交给read_object解析
+    let value_type = value[0];
+    let mut encoded_key = Vec::new();
+    encoded_key.write_string(key)?;
+    let mut input = Cursor::new(encoded_key).chain(Cursor::new(&value[1..]));
+    let mut collector = Collector::default();
+    input
+        .read_object(value_type, &mut collector, modules, &META)
+        .and_then(|_| match input.read(&mut [0])? {
+            0 => Ok(()),
+            _ => Err(malformed("dump payload is not fully consumed")),
+        })
+        .map_err(|err| rdb_error(err, Some(value_type), 0))?;
+    collector
+        .object
+        .ok_or_else(|| dump_error(format!("dump payload of type {} holds no value", value_type)))
+}
+
+/// 将`object`编码为DUMP payload, RDB版本及编码方式由`options`决定
+///
+/// 与[RDBWriter]相同, 只有[RawModule]类型的module值可以编码
+///
+/// [RDBWriter]: ../writer/struct.RDBWriter.html
+/// [RawModule]: ../rdb/struct.RawModule.html
+pub fn encode(object: &OwnedObject, options: WriteOptions) -> io::Result<Vec<u8>> {
+    RDBWriter::dump(object, options)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use crate::crc64::crc64;
+    use crate::dump::{decode, encode, OwnedObject};
+    use crate::error::RedisSyncError;
+    use crate::rdb::{Entry, Field, Item, ModuleRegistry, Stream, ID};
+    use crate::writer::WriteOptions;
+
+    fn round_trip(object: &OwnedObject, options: WriteOptions) -> OwnedObject {
+        let payload = encode(object, options).unwrap();
+        decode(object.key(), &payload, &mut ModuleRegistry::new()).unwrap()
+    }
+
+    #[test]
+    fn test_decode_redis_payload() {
+        // Redis文档中的示例: SET mykey 10, DUMP mykey
+        let payload = b"\x00\xc0\x0a\x09\x00\xbe\x6d\x06\x89\x5a\x28\x00\x0a";
+        match decode(b"mykey", payload, &mut ModuleRegistry::new()).unwrap() {
+            OwnedObject::String(key, value) => assert_eq!((key, value), (b"mykey".to_vec(), b"10".to_vec())),
+            object => panic!("unexpected object: {:?}", object),
+        }
+        let options = WriteOptions { version: 9, ..Default::default() };
+        assert_eq!(encode(&OwnedObject::String(b"mykey".to_vec(), b"10".to_vec()), options).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_dump_round_trip() {
+        let values: Vec<Vec<u8>> = (0..200).map(|i| format!("value-{}", i).into_bytes()).collect();
+        let items: Vec<Item> = (0..100).map(|i| Item { member: format!("m{}", i).into_bytes(), score: i as f64 / 2.0 }).collect();
+        let fields: Vec<Field> = (0..100)
+            .map(|i| Field { name: format!("f{}", i).into_bytes(), value: vec![b'v'; i], expire: None })
+            .collect();
+        for version in [9, 10, 11, 12] {
+            for (compact, compress) in [(false, false), (true, true)] {
+                let options = || WriteOptions { version, compact, compress };
+                match round_trip(&OwnedObject::String(b"s".to_vec(), b"x".repeat(100)), options()) {
+                    OwnedObject::String(_, value) => assert_eq!(value, b"x".repeat(100)),
+                    object => panic!("unexpected object: {:?}", object),
+                }
+                match round_trip(&OwnedObject::List(b"l".to_vec(), values.clone()), options()) {
+                    OwnedObject::List(_, list) => assert_eq!(list, values),
+                    object => panic!("unexpected object: {:?}", object),
+                }
+                match round_trip(&OwnedObject::Set(b"set".to_vec(), values[..10].to_vec()), options()) {
+                    OwnedObject::Set(_, members) => assert_eq!(members, values[..10]),
+                    object => panic!("unexpected object: {:?}", object),
+                }
+                match round_trip(&OwnedObject::SortedSet(b"z".to_vec(), items.clone()), options()) {
+                    OwnedObject::SortedSet(_, zset) => {
+                        assert_eq!(zset.len(), items.len());
+                        assert!(zset.iter().zip(&items).all(|(a, b)| a.member == b.member && a.score == b.score));
+                    }
+                    object => panic!("unexpected object: {:?}", object),
+                }
+                match round_trip(&OwnedObject::Hash(b"h".to_vec(), fields.clone()), options()) {
+                    OwnedObject::Hash(_, hash) => {
+                        assert_eq!(hash.len(), fields.len());
+                        assert!(hash.iter().zip(&fields).all(|(a, b)| a.name == b.name && a.value == b.value));
+                    }
+                    object => panic!("unexpected object: {:?}", object),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dump_stream() {
+        let mut entries = BTreeMap::new();
+        for seq in 0..3 {
+            let id = ID { ms: 1700000000000, seq };
+            let fields = BTreeMap::from([(b"temp".to_vec(), format!("{}", 20 + seq).into_bytes())]);
+            entries.insert(id, Entry { id, deleted: false, fields });
+        }
+        let stream = Stream {
+            entries,
+            groups: Vec::new(),
+            last_id: None,
+            first_id: None,
+            max_deleted_id: None,
+            added_entries_count: None,
+            meta: &super::META,
+        };
+        match round_trip(&OwnedObject::Stream(b"st".to_vec(), stream), WriteOptions::default()) {
+            OwnedObject::Stream(_, stream) => {
+                let ids: Vec<i64> = stream.entries.keys().map(|id| id.seq).collect();
+                assert_eq!(ids, vec![0, 1, 2]);
+                assert_eq!(stream.last_id, Some(ID { ms: 1700000000000, seq: 2 }));
+            }
+            object => panic!("unexpected object: {:?}", object),
+        }
+    }
+
+    #[cfg(feature = "module-json")]
+    #[test]
+    fn test_dump_module() {
+        use crate::modules::json::Json;
+        use crate::modules::register_builtin;
+        use crate::rdb::RawModule;
+
+        // module值以原始数据编码, 注册解析器后可解析为具体的类型
+        let mut modules = ModuleRegistry::new();
+        register_builtin(&mut modules);
+        let raw = RawModule {
+            name: "ReJSON-RL".to_string(),
+            version: 3,
+            data: b"\x05\x0b{\"a\":[1,2]}\x00".to_vec(),
+        };
+        let payload = encode(&OwnedObject::Module(b"doc".to_vec(), Box::new(raw)), WriteOptions::default()).unwrap();
+        match decode(b"doc", &payload, &mut modules).unwrap() {
+            OwnedObject::Module(_, module) => {
+                let json = module.as_any().downcast_ref::<Json>().unwrap();
+                assert_eq!(json.document, "{\"a\":[1,2]}");
+            }
+            object => panic!("unexpected object: {:?}", object),
+        }
+    }
+
+    #[test]
+    fn test_decode_malformed() {
+        let mut modules = ModuleRegistry::new();
+        let payload = encode(&OwnedObject::String(b"foo".to_vec(), b"bar".to_vec()), WriteOptions::default()).unwrap();
+        assert!(matches!(decode(b"foo", &payload[..8], &mut modules), Err(RedisSyncError::MalformedRDB { .. })));
+
+        let mut corrupted = payload.clone();
+        corrupted[2] = b'x';
+        assert!(matches!(decode(b"foo", &corrupted, &mut modules), Err(RedisSyncError::Checksum { .. })));
+
+        // 高于支持的RDB版本
+        let mut newer = payload[..payload.len() - 10].to_vec();
+        newer.extend_from_slice(&99u16.to_le_bytes());
+        let crc = crc64(0, &newer);
+        newer.extend_from_slice(&crc.to_le_bytes());
+        assert!(matches!(decode(b"foo", &newer, &mut modules), Err(RedisSyncError::MalformedRDB { .. })));
+
+        // 值之后多出的数据
+        let mut trailing = payload[..payload.len() - 10].to_vec();
+        trailing.push(0);
+        trailing.extend_from_slice(&11u16.to_le_bytes());
+        let crc = crc64(0, &trailing);
+        trailing.extend_from_slice(&crc.to_le_bytes());
+        match decode(b"foo", &trailing, &mut modules) {
+            Err(RedisSyncError::MalformedRDB { value_type, key, reason, .. }) => {
+                assert_eq!((value_type, key), (Some(0), None));
+                assert_eq!(reason, "dump payload is not fully consumed");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
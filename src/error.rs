@@ -5,4 +5,36 @@ use std::io;
 pub enum RedisSyncError {
     #[error("disconnected")]
     Disconnect(#[from] io::Error),
-}
\ No newline at end of file
+    #[error("protocol error: {0}")]
+    Protocol(String),
+    /// RDB的内容不符合格式, 或包含尚不支持的数据类型
+    #[error("malformed rdb at offset {offset}, value type: {value_type:?}, key: {key:?}: {reason}")]
+    MalformedRDB {
+        /// 出错记录的类型, 解析RDB头部时为`None`
+        value_type: Option<u8>,
+        /// 出错记录的key, 未读到key时为`None`
+        key: Option<String>,
+        /// 出错记录在RDB中的起始位置
+        offset: u64,
+        reason: String,
+    },
+    /// AOF文件或manifest的内容不符合格式
+    #[error("malformed aof {file} at offset {offset}: {reason}")]
+    MalformedAOF {
+        file: String,
+        /// 出错位置在文件中的偏移, manifest中为行号
+        offset: u64,
+        reason: String,
+    },
+    /// RDB末尾的checksum与实际内容的crc64不一致, RDB可能被截断或损坏
+    #[error("rdb checksum mismatch, expected: {expected:#018x}, actual: {actual:#018x}")]
+    Checksum { expected: u64, actual: u64 },
+    /// 命令的参数缺失或不合法
+    #[error("invalid command {command} at argument {index}: {reason}")]
+    InvalidCommand {
+        command: String,
+        /// 出错参数的下标, 命令名为第0个
+        index: usize,
+        reason: String,
+    },
+}
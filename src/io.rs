@@ -1,15 +1,23 @@
 
-use std::io::{self,BufReader, Error, ErrorKind, Read, Result};
+use std::io::{self,BufReader, Error, Read, Result, Write};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
 
+use tokio::io::{AsyncRead, ReadBuf};
 
-pub(crate) struct CountReader<'a> {
-    input: BufReader<&'a mut dyn Read>,
+use crate::crc64::crc64;
+
+
+pub(crate) struct CountReader<R: Read> {
+    input: BufReader<R>,
     len: i64,
     marked: bool,
 }
 
 
-impl Read for CountReader<'_> {
+impl<R: Read> Read for CountReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         let len = self.input.read(buf)?;
         if self.marked {
@@ -27,8 +35,8 @@ impl Read for CountReader<'_> {
     }
 }
 
-impl CountReader<'_> {
-    pub(crate) fn new(input: &mut dyn Read) -> CountReader {
+impl<R: Read> CountReader<R> {
+    pub(crate) fn new(input: R) -> CountReader<R> {
         CountReader {
             input: BufReader::new(input),
             len: 0,
@@ -36,6 +44,10 @@ impl CountReader<'_> {
         }
     }
 
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        self.input.get_mut()
+    }
+
     pub(crate) fn mark(&mut self) {
         self.marked = true;
     }
@@ -47,7 +59,7 @@ impl CountReader<'_> {
             self.marked = false;
             return Ok(len);
         }
-        Err(Error::new(ErrorKind::Other, "not marked"))
+        Err(Error::other("not marked"))
     }
 }
 
@@ -61,3 +73,118 @@ pub(crate) fn skip(input: &mut dyn Read, length: u64) -> Result<()> {
         Ok(())
     }
 
+
+/// 统计已读取字节数的Reader, 计数可在其他地方读取, 用于报告RDB的传输进度
+pub(crate) struct ProgressReader<R> {
+    input: R,
+    read: Arc<AtomicU64>,
+}
+
+impl<R> ProgressReader<R> {
+    pub(crate) fn new(input: R, read: Arc<AtomicU64>) -> ProgressReader<R> {
+        ProgressReader { input, read }
+    }
+
+    pub(crate) fn get_ref(&self) -> &R {
+        &self.input
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.input.read(buf)?;
+        self.read.fetch_add(len as u64, Ordering::Relaxed);
+        Ok(len)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ProgressReader<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.input).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            self.read.fetch_add((buf.filled().len() - before) as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+}
+
+/// 记录读取过的原始字节的Reader
+pub(crate) struct RecordReader<R> {
+    input: R,
+    record: Vec<u8>,
+}
+
+impl<R> RecordReader<R> {
+    pub(crate) fn new(input: R) -> RecordReader<R> {
+        RecordReader { input, record: Vec::new() }
+    }
+
+    pub(crate) fn into_record(self) -> Vec<u8> {
+        self.record
+    }
+}
+
+impl<R: Read> Read for RecordReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.input.read(buf)?;
+        self.record.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+}
+
+/// 计算读取过的字节的crc64的Reader, 用于校验RDB末尾的checksum
+pub(crate) struct Crc64Reader<R> {
+    input: R,
+    crc: u64,
+}
+
+impl<R> Crc64Reader<R> {
+    pub(crate) fn new(input: R) -> Crc64Reader<R> {
+        Crc64Reader { input, crc: 0 }
+    }
+
+    pub(crate) fn crc(&self) -> u64 {
+        self.crc
+    }
+}
+
+impl<R: Read> Read for Crc64Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.input.read(buf)?;
+        self.crc = crc64(self.crc, &buf[..len]);
+        Ok(len)
+    }
+}
+
+/// 计算写入过的字节的crc64的Writer, 用于生成RDB末尾的checksum
+pub(crate) struct Crc64Writer<W> {
+    output: W,
+    crc: u64,
+}
+
+impl<W> Crc64Writer<W> {
+    pub(crate) fn new(output: W) -> Crc64Writer<W> {
+        Crc64Writer { output, crc: 0 }
+    }
+
+    pub(crate) fn crc(&self) -> u64 {
+        self.crc
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+impl<W: Write> Write for Crc64Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let len = self.output.write(buf)?;
+        self.crc = crc64(self.crc, &buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.output.flush()
+    }
+}
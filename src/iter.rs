@@ -3,7 +3,7 @@ use std::io::{Cursor, Error, ErrorKind, Read};
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
-use crate::rdb::{read_zip_list_entry, read_zm_len, Field, Item, RDBDecode};
+use crate::rdb::{malformed, read_bytes, read_zip_list_entry, read_zm_len, Field, Item, RDBDecode};
 
 pub(crate) trait Iter {
     fn next<T: Read>(&mut self, input: T) -> io::Result<Vec<u8>>;
@@ -125,24 +125,24 @@ impl ZipMapIter<'_> {
             self.has_more = false;
             return Err(Error::new(ErrorKind::NotFound, "No element left"));
         }
-        let mut field = vec![0; zm_len];
-        self.cursor.read_exact(&mut field)?;
+        let field = read_bytes(self.cursor, zm_len)?;
         let zm_len = read_zm_len(self.cursor)?;
         if zm_len == 255 {
             self.has_more = false;
             return Ok(Field {
                 name: field,
                 value: Vec::new(),
+                expire: None,
             });
         };
         let free = self.cursor.read_i8()?;
-        let mut val = vec![0; zm_len];
-        self.cursor.read_exact(&mut val)?;
+        let val = read_bytes(self.cursor, zm_len)?;
         self.cursor
             .set_position(self.cursor.position() + free as u64);
         Ok(Field {
             name: field,
             value: val,
+            expire: None,
         })
     }
 }
@@ -173,7 +173,7 @@ impl Iter for IntSetIter<'_> {
                     
                     member.to_string().into_bytes()
                 }
-                _ => panic!("Invalid integer size: {}", self.encoding),
+                _ => return Err(malformed(format!("Invalid intset encoding: {}", self.encoding))),
             };
             self.count -= 1;
             return Ok(val);
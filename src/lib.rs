@@ -7,17 +7,27 @@ use std::io::Read;
 use std::io::Result;
 
 
-mod config;
-mod error;
+pub mod config;
+pub mod error;
 mod resp;
 mod connect;
-mod rdb;
-mod cmd;
+pub mod rdb;
+pub mod cmd;
+pub mod listener;
+pub mod checkpoint;
+pub mod aio;
+pub mod modules;
+pub mod writer;
+pub mod dump;
+pub mod aof;
 mod iter;
 mod lzf;
+mod crc64;
 mod io;
+mod stream;
 use crate::rdb::{Module, Object};
 use crate::cmd::Command;
+use crate::listener::SyncEvent;
 
 pub trait RedisListener {
     /// 开启事件监听
@@ -28,6 +38,8 @@ pub trait RedisListener {
 pub enum Event<'a> {
     RDB(Object<'a>),
     AOF(Command<'a>),
+    /// 与master之间的同步状态变化
+    Sync(SyncEvent<'a>),
 }
 
 pub trait EventHandler {
@@ -55,13 +67,17 @@ pub trait ModuleParser {
     /// * `input`: RDB输入流
     /// * `module_name`: Module的名字
     /// * `module_version`: Module的版本
-    fn parse(&mut self, input: &mut dyn Read, module_name: &str, module_version: usize) -> Box<dyn Module>;
+    ///
+    /// 对于`RDB_TYPE_MODULE_2`类型的值, 解析失败时会保留原始数据, 作为[RawModule]返回
+    ///
+    /// [RawModule]: rdb/struct.RawModule.html
+    fn parse(&mut self, input: &mut dyn Read, module_name: &str, module_version: usize) -> Result<Box<dyn Module>>;
 }
 
 #[allow(dead_code)]
 fn to_string(bytes: Vec<u8>) -> String {
-    return  unsafe {
+    unsafe {
         std::str::from_utf8_unchecked(&bytes).to_string()
-    };
+    }
 }
 
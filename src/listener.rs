@@ -0,0 +1,1029 @@
+/*!
+Redis复制监听器
+
+以replica的身份连接Redis master, 依次完成`AUTH`、`REPLCONF`、`PSYNC`握手, 先解析全量同步的RDB数据,
+再持续接收master传播过来的命令流, 所有数据均以[Event]的形式交给[EventHandler]处理。
+
+接收命令流期间, 监听器按`heartbeat_interval`向master发送`REPLCONF ACK`, 收到`REPLCONF GETACK`时立即应答, 超过`read_timeout`未收到master的任何数据(包括PING)时视为断线并重连。
+
+`Config`中开启`is_tls_enabled`时, 与master之间的连接使用TLS, 可通过`identity`指定PKCS#12格式的客户端证书。
+
+连接断开后, 监听器会自动重连, 并使用已记录的复制ID与偏移量尝试部分同步, 只有master回复`FULLRESYNC`时才会重新全量同步。
+全量同步传输RDB期间断线时, 监听器同样会重连, 并重新全量同步。
+设置了[Checkpoint]时, 复制进度会在handler处理完数据后被保存, 进程重启后同样以部分同步的方式继续。
+
+[Event]: ../enum.Event.html
+[EventHandler]: ../trait.EventHandler.html
+[Checkpoint]: ../checkpoint/trait.Checkpoint.html
+*/
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+
+use crate::checkpoint::{Checkpoint, Position};
+use crate::cmd;
+use crate::config::Config;
+use crate::connect::{Connect, NextStep};
+use crate::error::RedisSyncError;
+use crate::io::{CountReader, ProgressReader};
+use crate::rdb::{check_eof_mark, ModuleRegistry, RDBParser, RDB_EOF_MARK_LEN};
+use crate::resp::{Resp, RespDecode};
+use crate::stream::{BufStream, Stream};
+use crate::{Event, EventHandler, ModuleParser, RedisListener};
+
+/// 断线后重连的间隔
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// 接收命令时保存复制进度的最小间隔
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+/// 未配置`heartbeat_interval`时发送`REPLCONF ACK`的间隔
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 与master之间的同步事件
+#[derive(Debug)]
+pub enum SyncEvent<'a> {
+    /// master要求全量同步, 随后会收到完整的RDB数据
+    FullResync { repl_id: &'a str, repl_offset: i64 },
+    /// master接受了部分同步, 从`repl_offset`之后继续传播命令
+    PartialResync { repl_id: &'a str, repl_offset: i64 },
+    /// RDB的传输进度, `total`为RDB的总字节数, 无盘复制时为`None`
+    Progress { read: u64, total: Option<u64> },
+}
+
+/// 每传输这么多字节报告一次RDB的传输进度
+const PROGRESS_STEP: u64 = 1024 * 1024;
+
+/// 在转发RDB事件的同时报告传输进度, 已读取的字节数由[ProgressReader]统计
+///
+/// [ProgressReader]: ../io/struct.ProgressReader.html
+pub(crate) struct ProgressHandler<'a, H: EventHandler + ?Sized> {
+    handler: &'a mut H,
+    read: Arc<AtomicU64>,
+    total: Option<u64>,
+    reported: u64,
+}
+
+impl<'a, H: EventHandler + ?Sized> ProgressHandler<'a, H> {
+    pub(crate) fn new(handler: &'a mut H, read: Arc<AtomicU64>, total: Option<u64>) -> ProgressHandler<'a, H> {
+        ProgressHandler {
+            handler,
+            read,
+            total,
+            reported: 0,
+        }
+    }
+
+    fn report(&mut self, read: u64) {
+        self.reported = read;
+        self.handler.handle(Event::Sync(SyncEvent::Progress { read, total: self.total }));
+    }
+
+    /// RDB解析完成, 报告最终的进度
+    pub(crate) fn finish(&mut self) {
+        self.report(self.read.load(Ordering::Relaxed));
+    }
+}
+
+impl<H: EventHandler + ?Sized> EventHandler for ProgressHandler<'_, H> {
+    fn handle(&mut self, event: Event) {
+        let read = self.read.load(Ordering::Relaxed);
+        if read >= self.reported + PROGRESS_STEP {
+            self.report(read);
+        }
+        self.handler.handle(event);
+    }
+}
+
+/// 检查RDB是否恰好读完了`length`字节
+///
+/// 方法参数:
+///
+/// * `result`: RDB解析的结果
+/// * `length`: PSYNC应答中RDB的字节数, 无盘复制时为`None`
+/// * `left`: 解析完成后剩余未读的字节数
+/// * `completed`: 解析是否进行到了RDB结尾, 中途停止时不检查剩余字节
+pub(crate) fn check_payload(
+    result: std::result::Result<(), RedisSyncError>,
+    length: Option<u64>,
+    left: u64,
+    completed: bool,
+) -> Result<()> {
+    match (result, length) {
+        (Err(RedisSyncError::Disconnect(err)), Some(length))
+            if left == 0 && err.kind() == io::ErrorKind::UnexpectedEof =>
+        {
+            Err(RedisSyncError::Protocol(format!("rdb exceeds the declared length of {} bytes", length)).into())
+        }
+        // 保持io::Error的类型, 以便按断线处理
+        (Err(RedisSyncError::Disconnect(err)), _) => Err(err.into()),
+        (Err(err), _) => Err(err.into()),
+        (Ok(()), Some(length)) if completed && left != 0 => Err(RedisSyncError::Protocol(format!(
+            "rdb ends before the declared length of {} bytes, {} bytes left",
+            length, left
+        ))
+        .into()),
+        (Ok(()), _) => Ok(()),
+    }
+}
+
+/// 从`input`中解析全量同步的RDB, 最多读取`length`字节, 并向handler报告传输进度
+fn parse_rdb<R: Read>(
+    input: &mut R,
+    length: Option<u64>,
+    handler: &mut dyn EventHandler,
+    modules: &mut ModuleRegistry,
+    running: Arc<AtomicBool>,
+) -> Result<()> {
+    let read = Arc::new(AtomicU64::new(0));
+    let mut handler = ProgressHandler::new(handler, Arc::clone(&read), length);
+    let mut payload = ProgressReader::new(input.take(length.unwrap_or(u64::MAX)), read);
+    let result = payload.parse_with_modules(&mut handler, modules, Arc::clone(&running));
+    let left = payload.get_ref().limit();
+    check_payload(result, length, left, running.load(Ordering::Relaxed))?;
+    handler.finish();
+    Ok(())
+}
+
+/// 复制进度, 由同步与异步的监听器共用
+pub(crate) struct Replication {
+    pub(crate) repl_id: String,
+    pub(crate) repl_offset: i64,
+    checkpoint: Option<Box<dyn Checkpoint + Send>>,
+    last_checkpoint: Instant,
+    /// 全量同步已开始但RDB尚未处理完, 此时断线需要重新全量同步
+    full_sync: bool,
+}
+
+impl Replication {
+    pub(crate) fn new() -> Replication {
+        Replication {
+            repl_id: "?".to_string(),
+            repl_offset: -1,
+            checkpoint: None,
+            last_checkpoint: Instant::now(),
+            full_sync: false,
+        }
+    }
+
+    pub(crate) fn set_checkpoint(&mut self, checkpoint: Box<dyn Checkpoint + Send>) {
+        self.checkpoint = Some(checkpoint);
+    }
+
+    /// 是否已有可用于部分同步的复制进度
+    pub(crate) fn resumable(&self) -> bool {
+        self.repl_id != "?"
+    }
+
+    /// 断线后是否应当重连: 可以部分同步, 或全量同步在传输RDB期间中断, 重连后重新全量同步
+    pub(crate) fn reconnectable(&self) -> bool {
+        self.resumable() || self.full_sync
+    }
+
+    /// PSYNC请求的偏移量, 没有可用的复制进度时为-1
+    pub(crate) fn psync_offset(&self) -> i64 {
+        if self.resumable() {
+            self.repl_offset + 1
+        } else {
+            -1
+        }
+    }
+
+    pub(crate) fn load_checkpoint(&mut self) -> Result<()> {
+        if let Some(checkpoint) = &mut self.checkpoint {
+            if let Some(position) = checkpoint.load()? {
+                info!("resume from checkpoint {}:{}", position.repl_id, position.repl_offset);
+                self.repl_id = position.repl_id;
+                self.repl_offset = position.repl_offset;
+            }
+        }
+        Ok(())
+    }
+
+    /// 保存当前的复制进度, `force`为`false`时距上次保存不足[CHECKPOINT_INTERVAL]则跳过
+    pub(crate) fn save_checkpoint(&mut self, force: bool) -> Result<()> {
+        if let Some((mut checkpoint, position)) = self.checkpoint_due(force) {
+            let result = checkpoint.save(&position);
+            self.checkpoint_saved(checkpoint);
+            result?;
+        }
+        Ok(())
+    }
+
+    /// 需要保存复制进度时, 取出checkpoint及要保存的进度, 以便在其他线程中保存, 保存后通过`checkpoint_saved`放回
+    pub(crate) fn checkpoint_due(&mut self, force: bool) -> Option<(Box<dyn Checkpoint + Send>, Position)> {
+        if !force && self.last_checkpoint.elapsed() < CHECKPOINT_INTERVAL {
+            return None;
+        }
+        let checkpoint = self.checkpoint.take()?;
+        Some((
+            checkpoint,
+            Position {
+                repl_id: self.repl_id.clone(),
+                repl_offset: self.repl_offset,
+            },
+        ))
+    }
+
+    pub(crate) fn checkpoint_saved(&mut self, checkpoint: Box<dyn Checkpoint + Send>) {
+        self.checkpoint = Some(checkpoint);
+        self.last_checkpoint = Instant::now();
+    }
+
+    /// 全量同步开始, RDB处理完之前的进度不可用于恢复, 需随后保存以将旧进度作废
+    pub(crate) fn invalidate(&mut self) {
+        self.repl_id = "?".to_string();
+        self.repl_offset = -1;
+        self.full_sync = true;
+    }
+
+    /// RDB已处理完, 从`repl_offset`之后继续接收命令, 需随后保存复制进度
+    pub(crate) fn full_synced(&mut self, repl_id: String, repl_offset: i64) {
+        self.repl_id = repl_id;
+        self.repl_offset = repl_offset;
+        self.full_sync = false;
+    }
+}
+
+/// 是否为master询问复制偏移量的`REPLCONF GETACK`, 此命令由监听器应答, 不交给handler
+pub(crate) fn is_getack(args: &[Vec<u8>]) -> bool {
+    args.len() >= 2 && args[0].eq_ignore_ascii_case(b"REPLCONF") && args[1].eq_ignore_ascii_case(b"GETACK")
+}
+
+/// 将master传播的命令拆分为参数列表
+pub(crate) fn command_args(resp: Resp) -> Result<Vec<Vec<u8>>> {
+    if let Resp::Array(array) = resp {
+        let mut args = Vec::with_capacity(array.len());
+        for arg in array {
+            if let Resp::BulkBytes(bytes) = arg {
+                args.push(bytes);
+            } else {
+                return Err(anyhow!("Expected BulkString response"));
+            }
+        }
+        Ok(args)
+    } else {
+        Err(anyhow!("Expected array response"))
+    }
+}
+
+/// 接收命令流时的心跳
+///
+/// 底层连接的读超时被设为心跳间隔, 每次读超时或读取前发现已到间隔时, 向master发送`REPLCONF ACK <offset>`;
+/// 超过`timeout`未收到任何数据则认为master已失联, 返回`TimedOut`错误以触发重连;
+/// 等待数据期间`running`被置为`false`时返回`ConnectionAborted`错误, 使监听器及时退出
+struct Heartbeat<'a, S: Read + Write> {
+    stream: &'a mut S,
+    interval: Duration,
+    timeout: Option<Duration>,
+    running: Arc<AtomicBool>,
+    /// 已处理完的复制偏移量, 随ACK发送给master
+    offset: i64,
+    last_ack: Option<Instant>,
+    last_read: Instant,
+}
+
+impl<'a, S: Read + Write> Heartbeat<'a, S> {
+    fn new(
+        stream: &'a mut S,
+        interval: Duration,
+        timeout: Option<Duration>,
+        running: Arc<AtomicBool>,
+        offset: i64,
+    ) -> Heartbeat<'a, S> {
+        Heartbeat {
+            stream,
+            interval,
+            timeout,
+            running,
+            offset,
+            last_ack: None,
+            last_read: Instant::now(),
+        }
+    }
+
+    /// 不等心跳间隔, 立即发送ACK
+    fn ack_now(&mut self) -> io::Result<()> {
+        self.last_ack = None;
+        self.ack()
+    }
+
+    fn ack(&mut self) -> io::Result<()> {
+        if self.last_ack.is_none_or(|last_ack| last_ack.elapsed() >= self.interval) {
+            self.stream
+                .replconf_ack(self.offset.to_string())
+                .map_err(|err| err.downcast::<io::Error>().unwrap_or_else(io::Error::other))?;
+            self.last_ack = Some(Instant::now());
+        }
+        Ok(())
+    }
+}
+
+impl<S: Read + Write> Read for Heartbeat<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            self.ack()?;
+            match self.stream.read(buf) {
+                Ok(len) => {
+                    self.last_read = Instant::now();
+                    return Ok(len);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => {
+                    if !self.running.load(Ordering::Relaxed) {
+                        return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "listener stopped"));
+                    }
+                    if let Some(timeout) = self.timeout {
+                        if self.last_read.elapsed() >= timeout {
+                            return Err(io::Error::new(
+                                io::ErrorKind::TimedOut,
+                                format!("no data from master in {:?}", timeout),
+                            ));
+                        }
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// 单机Redis的复制监听器
+pub struct Listener {
+    pub config: Config,
+    replication: Replication,
+    event_handler: Box<dyn EventHandler + Send>,
+    modules: ModuleRegistry,
+    running: Arc<AtomicBool>,
+}
+
+impl Listener {
+    /// 创建监听器
+    ///
+    /// 方法参数:
+    ///
+    /// * `config`: master的连接配置
+    /// * `event_handler`: 处理RDB数据与命令的handler
+    /// * `running`: 置为`false`后, 监听器在处理完当前数据后退出
+    pub fn new(
+        config: Config,
+        event_handler: Box<dyn EventHandler + Send>,
+        running: Arc<AtomicBool>,
+    ) -> Listener {
+        Listener {
+            config,
+            replication: Replication::new(),
+            event_handler,
+            modules: ModuleRegistry::new(),
+            running,
+        }
+    }
+
+    /// 设置复制进度的存储, `start`时会从中恢复复制进度
+    pub fn set_checkpoint(&mut self, checkpoint: Box<dyn Checkpoint + Send>) {
+        self.replication.set_checkpoint(checkpoint);
+    }
+
+    /// 注册`module_name`的解析器, 未注册的module以[RawModule]的形式交给handler
+    ///
+    /// [RawModule]: ../rdb/struct.RawModule.html
+    pub fn register_module_parser(&mut self, module_name: &str, parser: Box<dyn ModuleParser + Send>) {
+        self.modules.register(module_name, parser);
+    }
+
+    /// master的复制ID
+    pub fn repl_id(&self) -> &str {
+        &self.replication.repl_id
+    }
+
+    /// 已处理完的复制偏移量
+    pub fn repl_offset(&self) -> i64 {
+        self.replication.repl_offset
+    }
+
+    fn connect(&self) -> Result<BufStream<Stream>> {
+        let stream = Stream::connect(&self.config)?;
+        info!("connected to {}:{}", self.config.host, self.config.port);
+        Ok(BufStream::new(stream))
+    }
+
+    fn handshake(&self, stream: &mut BufStream<Stream>) -> Result<()> {
+        if self.config.password.is_some() {
+            stream.auth(self.config.password.clone(), self.config.username.clone())?;
+        }
+        let local_addr = stream.get_ref().local_addr()?;
+        stream.replconf(local_addr.ip().to_string(), local_addr.port())
+    }
+
+    /// 发送PSYNC, 如master要求全量同步, 则解析随后的RDB
+    fn sync(&mut self, stream: &mut BufStream<Stream>) -> Result<()> {
+        while self.running.load(Ordering::Relaxed) {
+            let offset = self.replication.psync_offset();
+            let resp = stream.psync(self.replication.repl_id.clone(), offset.to_string())?;
+            match resp.next_step {
+                NextStep::FullSync => {
+                    if resp.length != -1 {
+                        info!("full sync, size: {}bytes", resp.length);
+                    } else {
+                        info!("full sync, disk-less replication");
+                    }
+                    self.replication.invalidate();
+                    self.replication.save_checkpoint(true)?;
+
+                    self.event_handler.handle(Event::Sync(SyncEvent::FullResync {
+                        repl_id: &resp.repl_id,
+                        repl_offset: resp.repl_offset,
+                    }));
+                    let length = u64::try_from(resp.length).ok();
+                    parse_rdb(stream, length, &mut *self.event_handler, &mut self.modules, Arc::clone(&self.running))?;
+                    if !self.running.load(Ordering::Relaxed) {
+                        // RDB未解析完整
+                        return Ok(());
+                    }
+                    if let Some(expected) = &resp.eof_mark {
+                        let mut mark = [0; RDB_EOF_MARK_LEN];
+                        stream.read_exact(&mut mark)?;
+                        check_eof_mark(expected, &mark)?;
+                    }
+                    self.replication.full_synced(resp.repl_id, resp.repl_offset);
+                    self.replication.save_checkpoint(true)?;
+                    return Ok(());
+                }
+                NextStep::PartialResync => {
+                    // master切换后会在CONTINUE中带上新的复制ID, 偏移量保持连续
+                    if !resp.repl_id.is_empty() {
+                        self.replication.repl_id = resp.repl_id;
+                    }
+                    info!("partial resync from offset {}", offset);
+                    self.event_handler.handle(Event::Sync(SyncEvent::PartialResync {
+                        repl_id: &self.replication.repl_id,
+                        repl_offset: self.replication.repl_offset,
+                    }));
+                    return Ok(());
+                }
+                NextStep::Wait => sleep(Duration::from_secs(1)),
+            }
+        }
+        Ok(())
+    }
+
+    /// 持续接收master传播过来的命令, 同时定期向master发送ACK
+    fn receive_aof(&mut self, stream: &mut BufStream<Stream>) -> Result<()> {
+        let interval = self.config.heartbeat_interval.unwrap_or(HEARTBEAT_INTERVAL);
+        stream.get_ref().tcp().set_read_timeout(Some(interval))?;
+        let heartbeat = Heartbeat::new(
+            stream,
+            interval,
+            self.config.read_timeout,
+            Arc::clone(&self.running),
+            self.replication.repl_offset,
+        );
+        let mut reader = CountReader::new(heartbeat);
+        while self.running.load(Ordering::Relaxed) {
+            reader.mark();
+            let resp = reader.decode_resp()?;
+            let size = reader.reset()?;
+            let args = command_args(resp)?;
+            if is_getack(&args) {
+                // 应答的偏移量不包括GETACK命令本身
+                reader.get_mut().ack_now()?;
+            } else {
+                cmd::parse(args, &mut *self.event_handler, self.config.command_error_policy)?;
+            }
+            self.replication.repl_offset += size;
+            reader.get_mut().offset = self.replication.repl_offset;
+            self.replication.save_checkpoint(false)?;
+        }
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<()> {
+        let mut stream = self.connect()?;
+        self.handshake(&mut stream)?;
+        self.sync(&mut stream)?;
+        self.receive_aof(&mut stream)
+    }
+}
+
+impl RedisListener for Listener {
+    fn start(&mut self) -> io::Result<()> {
+        self.replication.load_checkpoint().map_err(io::Error::other)?;
+        loop {
+            let result = self.run();
+            // 已处理完的数据都已计入偏移量, 无论是否出错都保存一次
+            if let Err(err) = self.replication.save_checkpoint(true) {
+                warn!("save checkpoint err: {}", err);
+            }
+            let err = match result {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+            // 网络层的错误视为连接断开, 有复制进度时重连并尝试部分同步, 全量同步中断时重连并重新全量同步
+            match err.downcast::<io::Error>() {
+                // 已被要求停止, 等待数据时被中断
+                Ok(_) if !self.running.load(Ordering::Relaxed) => return Ok(()),
+                Ok(err) if self.replication.reconnectable() => {
+                    warn!("connection lost: {}, reconnect in {:?}", err, RECONNECT_INTERVAL);
+                    sleep(RECONNECT_INTERVAL);
+                }
+                Ok(err) => return Err(err),
+                Err(err) => return Err(io::Error::other(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::io::{Read, Write};
+    use std::net::{Shutdown, TcpListener};
+    use std::path::Path;
+    use std::process::Command;
+    use std::time::Duration;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread::{self, JoinHandle};
+
+    use super::Listener;
+    use crate::checkpoint::{Checkpoint, FileCheckpoint, Position};
+    use crate::config::Config;
+    use crate::resp::{Resp, RespDecode};
+    use crate::{Event, EventHandler, RedisListener};
+
+    pub(crate) const REPL_ID: &str = "8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb";
+
+    /// 只包含`foo => bar`一个key的RDB
+    pub(crate) fn rdb() -> Vec<u8> {
+        let mut rdb = b"REDIS0009".to_vec();
+        rdb.extend_from_slice(&[0xFE, 0x00]);
+        rdb.extend_from_slice(&[0x00, 3, b'f', b'o', b'o', 3, b'b', b'a', b'r']);
+        rdb.push(0xFF);
+        rdb.extend_from_slice(&[0; 8]);
+        rdb
+    }
+
+    pub(crate) fn pack(args: &[&str]) -> Vec<u8> {
+        let mut buf = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            buf.extend_from_slice(format!("${}\r\n{}\r\n", arg.len(), arg).as_bytes());
+        }
+        buf
+    }
+
+    /// 读取replica发来的一条命令
+    pub(crate) fn read_command<S: Read>(stream: &mut S) -> Vec<String> {
+        match stream.decode_resp().expect("decode err") {
+            Resp::Array(array) => array
+                .into_iter()
+                .map(|arg| match arg {
+                    Resp::BulkBytes(bytes) => String::from_utf8(bytes).unwrap(),
+                    _ => panic!("Expected BulkString"),
+                })
+                .collect::<Vec<_>>(),
+            _ => panic!("Expected array"),
+        }
+    }
+
+    /// 应答replica的握手命令, 返回收到的PSYNC参数
+    pub(crate) fn handshake<S: Read + Write>(stream: &mut S) -> Vec<String> {
+        loop {
+            let args = read_command(stream);
+            match args[0].to_uppercase().as_str() {
+                "PING" => stream.write_all(b"+PONG\r\n").unwrap(),
+                "PSYNC" => return args,
+                _ => stream.write_all(b"+OK\r\n").unwrap(),
+            }
+        }
+    }
+
+    pub(crate) fn full_resync<S: Write>(stream: &mut S) {
+        let rdb = rdb();
+        stream
+            .write_all(format!("+FULLRESYNC {} 100\r\n${}\r\n", REPL_ID, rdb.len()).as_bytes())
+            .unwrap();
+        stream.write_all(&rdb).unwrap();
+    }
+
+    pub(crate) const EOF_MARK: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    /// 以无盘复制的格式发送[rdb], RDB之后跟着`trailer`
+    pub(crate) fn diskless_resync<S: Write>(stream: &mut S, trailer: &str) {
+        stream
+            .write_all(format!("+FULLRESYNC {} 100\r\n$EOF:{}\r\n", REPL_ID, EOF_MARK).as_bytes())
+            .unwrap();
+        stream.write_all(&rdb()).unwrap();
+        stream.write_all(trailer.as_bytes()).unwrap();
+    }
+
+    /// 模拟一个master: 全量同步[rdb], 随后传播`commands`
+    pub(crate) fn fake_master(listener: TcpListener, commands: Vec<Vec<u8>>) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            handshake(&mut stream);
+            full_resync(&mut stream);
+            for command in commands {
+                stream.write_all(&command).unwrap();
+            }
+            // 等待replica主动断开
+            while stream.decode_resp().is_ok() {}
+        })
+    }
+
+    /// 记录收到的事件, 收到包含`stop_at`的事件后停止监听器
+    pub(crate) struct RecordHandler {
+        pub(crate) events: Arc<Mutex<Vec<String>>>,
+        pub(crate) stop_at: &'static str,
+        pub(crate) running: Arc<AtomicBool>,
+    }
+
+    impl EventHandler for RecordHandler {
+        fn handle(&mut self, event: Event) {
+            let event = format!("{:?}", event);
+            if event.contains(self.stop_at) {
+                self.running.store(false, Ordering::SeqCst);
+            }
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    /// 创建`running`与handler, 返回handler及其记录的事件
+    pub(crate) fn record(stop_at: &'static str) -> (Arc<AtomicBool>, Box<RecordHandler>, Arc<Mutex<Vec<String>>>) {
+        let running = Arc::new(AtomicBool::new(true));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let handler = RecordHandler {
+            events: Arc::clone(&events),
+            stop_at,
+            running: Arc::clone(&running),
+        };
+        (running, Box::new(handler), events)
+    }
+
+    pub(crate) fn config(listener: &TcpListener) -> Config {
+        Config {
+            host: "127.0.0.1".to_string(),
+            port: listener.local_addr().unwrap().port(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_full_sync() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = config(&tcp);
+        let ping = pack(&["PING"]);
+        let set = pack(&["SET", "hello", "world"]);
+        let offset = 100 + (ping.len() + set.len()) as i64;
+        let master = fake_master(tcp, vec![ping, set]);
+
+        let (running, handler, events) = record("SET(");
+        let mut listener = Listener::new(conf, handler, running);
+        listener.start().expect("listener err");
+        assert_eq!(listener.repl_id(), REPL_ID);
+        assert_eq!(listener.repl_offset(), offset);
+        drop(listener);
+        master.join().unwrap();
+
+        let events = events.lock().unwrap();
+        assert!(events[0].contains("FullResync"));
+        assert!(events[1].contains("BOR"));
+        assert!(events.iter().any(|e| e.contains("String") && e.contains("[102, 111, 111]")));
+        assert!(events.iter().any(|e| e.contains("EOR")));
+        let total = rdb().len();
+        assert!(events.contains(&format!("Sync(Progress {{ read: {}, total: Some({}) }})", total, total)));
+        assert!(events.last().unwrap().contains("SET"));
+    }
+
+    #[test]
+    fn test_partial_resync() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = config(&tcp);
+        let set = pack(&["SET", "hello", "world"]);
+        let next_offset = 100 + set.len() as i64 + 1;
+        let master = thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            handshake(&mut stream);
+            full_resync(&mut stream);
+            stream.write_all(&set).unwrap();
+            // 模拟断线, 读完replica发来的ACK再关闭, 避免未读数据导致RST
+            stream.shutdown(Shutdown::Write).unwrap();
+            while stream.decode_resp().is_ok() {}
+
+            let (mut stream, _) = tcp.accept().unwrap();
+            let psync = handshake(&mut stream);
+            stream.write_all(format!("+CONTINUE {}\r\n", REPL_ID).as_bytes()).unwrap();
+            stream.write_all(&pack(&["DEL", "hello"])).unwrap();
+            while stream.decode_resp().is_ok() {}
+            psync
+        });
+
+        let (running, handler, events) = record("DEL(");
+        let mut listener = Listener::new(conf, handler, running);
+        listener.start().expect("listener err");
+        drop(listener);
+        let psync = master.join().unwrap();
+        assert_eq!(psync, vec!["PSYNC".to_string(), REPL_ID.to_string(), next_offset.to_string()]);
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.iter().filter(|e| e.contains("FullResync")).count(), 1);
+        assert!(events.iter().any(|e| e.contains("PartialResync")));
+        assert!(events.last().unwrap().contains("DEL"));
+    }
+
+    #[test]
+    fn test_disconnect_during_full_sync() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = config(&tcp);
+        let master = thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            handshake(&mut stream);
+            let rdb = rdb();
+            stream
+                .write_all(format!("+FULLRESYNC {} 100\r\n${}\r\n", REPL_ID, rdb.len()).as_bytes())
+                .unwrap();
+            // RDB只发送一半就断开
+            stream.write_all(&rdb[..rdb.len() / 2]).unwrap();
+            drop(stream);
+
+            let (mut stream, _) = tcp.accept().unwrap();
+            let psync = handshake(&mut stream);
+            full_resync(&mut stream);
+            stream.write_all(&pack(&["SET", "hello", "world"])).unwrap();
+            while stream.decode_resp().is_ok() {}
+            psync
+        });
+
+        let (running, handler, events) = record("SET(");
+        let mut listener = Listener::new(conf, handler, running);
+        listener.start().expect("listener err");
+        assert_eq!(listener.repl_id(), REPL_ID);
+        drop(listener);
+        let psync = master.join().unwrap();
+        assert_eq!(psync, vec!["PSYNC", "?", "-1"]);
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.iter().filter(|e| e.contains("FullResync")).count(), 2);
+        assert!(events.last().unwrap().contains("SET"));
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let path = std::env::temp_dir().join(format!("redis-sync-listener-{}", std::process::id()));
+        let mut checkpoint = FileCheckpoint::new(&path);
+        checkpoint
+            .save(&Position {
+                repl_id: REPL_ID.to_string(),
+                repl_offset: 500,
+            })
+            .unwrap();
+
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = config(&tcp);
+        let del = pack(&["DEL", "hello"]);
+        let offset = 500 + del.len() as i64;
+        let master = thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            let psync = handshake(&mut stream);
+            stream.write_all(format!("+CONTINUE {}\r\n", REPL_ID).as_bytes()).unwrap();
+            stream.write_all(&del).unwrap();
+            while stream.decode_resp().is_ok() {}
+            psync
+        });
+
+        let (running, handler, _) = record("DEL(");
+        let mut listener = Listener::new(conf, handler, running);
+        listener.set_checkpoint(Box::new(FileCheckpoint::new(&path)));
+        listener.start().expect("listener err");
+        drop(listener);
+        let psync = master.join().unwrap();
+        assert_eq!(psync, vec!["PSYNC".to_string(), REPL_ID.to_string(), "501".to_string()]);
+        assert_eq!(checkpoint.load().unwrap().unwrap().repl_offset, offset);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// 在`dir`中生成自签名证书及PKCS#12格式的客户端证书, 为`conf`开启TLS, 返回master端使用的acceptor
+    pub(crate) fn tls(dir: &Path, conf: &mut Config) -> native_tls::TlsAcceptor {
+        std::fs::create_dir_all(dir).unwrap();
+        let (key, cert, identity) = (dir.join("key.pem"), dir.join("cert.pem"), dir.join("identity.p12"));
+        let status = Command::new("openssl")
+            .args(["req", "-x509", "-newkey", "rsa:2048", "-nodes", "-days", "1", "-subj", "/CN=localhost"])
+            .arg("-keyout")
+            .arg(&key)
+            .arg("-out")
+            .arg(&cert)
+            .output()
+            .expect("openssl not found")
+            .status;
+        assert!(status.success());
+        let status = Command::new("openssl")
+            .args(["pkcs12", "-export", "-passout", "pass:secret"])
+            .arg("-inkey")
+            .arg(&key)
+            .arg("-in")
+            .arg(&cert)
+            .arg("-out")
+            .arg(&identity)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success());
+
+        let der = std::fs::read(&identity).unwrap();
+        conf.is_tls_enabled = true;
+        conf.is_tls_insecure = true;
+        conf.identity = Some(identity.to_string_lossy().to_string());
+        conf.identity_passwd = Some("secret".to_string());
+        native_tls::TlsAcceptor::new(native_tls::Identity::from_pkcs12(&der, "secret").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_tls_full_sync() {
+        let dir = std::env::temp_dir().join(format!("redis-sync-tls-{}", std::process::id()));
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = config(&tcp);
+        let acceptor = tls(&dir, &mut conf);
+        let master = thread::spawn(move || {
+            let (stream, _) = tcp.accept().unwrap();
+            let mut stream = acceptor.accept(stream).unwrap();
+            handshake(&mut stream);
+            full_resync(&mut stream);
+            stream.write_all(&pack(&["SET", "hello", "world"])).unwrap();
+            while stream.decode_resp().is_ok() {}
+        });
+
+        let (running, handler, events) = record("SET(");
+        let mut listener = Listener::new(conf, handler, running);
+        listener.start().expect("listener err");
+        assert_eq!(listener.repl_id(), REPL_ID);
+        drop(listener);
+        master.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let events = events.lock().unwrap();
+        assert!(events.iter().any(|e| e.contains("String") && e.contains("[102, 111, 111]")));
+        assert!(events.last().unwrap().contains("SET"));
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = config(&tcp);
+        conf.heartbeat_interval = Some(Duration::from_millis(50));
+        conf.read_timeout = Some(Duration::from_millis(300));
+        let master = thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            handshake(&mut stream);
+            full_resync(&mut stream);
+            let ack = read_command(&mut stream);
+            // 不再发送任何数据, 等待replica判定连接超时
+            let mut acks = 1;
+            while stream.decode_resp().is_ok() {
+                acks += 1;
+            }
+
+            let (mut stream, _) = tcp.accept().unwrap();
+            let psync = handshake(&mut stream);
+            stream.write_all(format!("+CONTINUE {}\r\n", REPL_ID).as_bytes()).unwrap();
+            stream.write_all(&pack(&["DEL", "hello"])).unwrap();
+            while stream.decode_resp().is_ok() {}
+            (ack, acks, psync)
+        });
+
+        let (running, handler, _) = record("DEL(");
+        let mut listener = Listener::new(conf, handler, running);
+        listener.start().expect("listener err");
+        drop(listener);
+        let (ack, acks, psync) = master.join().unwrap();
+        assert_eq!(ack, vec!["REPLCONF", "ACK", "100"]);
+        assert!(acks > 1);
+        assert_eq!(psync, vec!["PSYNC".to_string(), REPL_ID.to_string(), "101".to_string()]);
+    }
+
+    #[test]
+    fn test_stop_while_idle() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = config(&tcp);
+        conf.heartbeat_interval = Some(Duration::from_millis(50));
+        conf.read_timeout = None;
+        let master = thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            handshake(&mut stream);
+            full_resync(&mut stream);
+            // 之后不再发送任何数据
+            while stream.decode_resp().is_ok() {}
+        });
+
+        let (running, handler, _) = record("never");
+        let stop = Arc::clone(&running);
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            stop.store(false, Ordering::SeqCst);
+        });
+        let mut listener = Listener::new(conf, handler, running);
+        listener.start().expect("listener err");
+        assert_eq!(listener.repl_offset(), 100);
+        drop(listener);
+        stopper.join().unwrap();
+        master.join().unwrap();
+    }
+
+    #[test]
+    fn test_getack() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = config(&tcp);
+        conf.heartbeat_interval = Some(Duration::from_secs(60));
+        let set = pack(&["SET", "hello", "world"]);
+        let offset = 100 + set.len() as i64;
+        let master = thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            handshake(&mut stream);
+            full_resync(&mut stream);
+            assert_eq!(read_command(&mut stream), vec!["REPLCONF", "ACK", "100"]);
+            stream.write_all(&set).unwrap();
+            stream.write_all(&pack(&["REPLCONF", "GETACK", "*"])).unwrap();
+            let ack = read_command(&mut stream);
+            stream.write_all(&pack(&["DEL", "hello"])).unwrap();
+            while stream.decode_resp().is_ok() {}
+            ack
+        });
+
+        let (running, handler, events) = record("DEL(");
+        let mut listener = Listener::new(conf, handler, running);
+        listener.start().expect("listener err");
+        drop(listener);
+        let ack = master.join().unwrap();
+        assert_eq!(ack, vec!["REPLCONF".to_string(), "ACK".to_string(), offset.to_string()]);
+        assert!(!events.lock().unwrap().iter().any(|e| e.contains("REPLCONF")));
+    }
+
+    #[test]
+    fn test_diskless_sync() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = config(&tcp);
+        let set = pack(&["SET", "hello", "world"]);
+        let offset = 100 + set.len() as i64;
+        let master = thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            handshake(&mut stream);
+            diskless_resync(&mut stream, EOF_MARK);
+            stream.write_all(&set).unwrap();
+            while stream.decode_resp().is_ok() {}
+        });
+
+        let (running, handler, events) = record("SET(");
+        let mut listener = Listener::new(conf, handler, running);
+        listener.start().expect("listener err");
+        assert_eq!(listener.repl_offset(), offset);
+        drop(listener);
+        master.join().unwrap();
+        assert!(events.lock().unwrap().last().unwrap().contains("SET"));
+    }
+
+    #[test]
+    fn test_diskless_mark_mismatch() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = config(&tcp);
+        let master = thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            handshake(&mut stream);
+            diskless_resync(&mut stream, &EOF_MARK.replace('0', "x"));
+            while stream.decode_resp().is_ok() {}
+        });
+
+        let (running, handler, _) = record("SET(");
+        let mut listener = Listener::new(conf, handler, running);
+        let err = listener.start().expect_err("mark mismatch should fail");
+        assert!(err.to_string().contains("eof mark mismatch"));
+        drop(listener);
+        master.join().unwrap();
+    }
+
+    #[test]
+    fn test_rdb_length_mismatch() {
+        for delta in [-3i64, 3] {
+            let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+            let conf = config(&tcp);
+            let master = thread::spawn(move || {
+                let (mut stream, _) = tcp.accept().unwrap();
+                handshake(&mut stream);
+                let rdb = rdb();
+                let length = rdb.len() as i64 + delta;
+                stream
+                    .write_all(format!("+FULLRESYNC {} 100\r\n${}\r\n", REPL_ID, length).as_bytes())
+                    .unwrap();
+                stream.write_all(&rdb).unwrap();
+                stream.write_all(&pack(&["SET", "hello", "world"])).unwrap();
+                while stream.decode_resp().is_ok() {}
+            });
+
+            let (running, handler, _) = record("SET(");
+            let mut listener = Listener::new(conf, handler, running);
+            let err = listener.start().expect_err("length mismatch should fail");
+            assert!(err.to_string().contains("declared length"), "{}", err);
+            drop(listener);
+            master.join().unwrap();
+        }
+    }
+}
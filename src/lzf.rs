@@ -1,51 +1,295 @@
-// lzf解压缩算法
-pub(crate) fn decompress(input: &mut [u8], input_len: isize, output: &mut [u8], output_len: isize) {
-    let mut iidx: isize = 0;
-    let mut oidx: isize = 0;
+// lzf压缩算法, 与Redis使用的liblzf 3.6(HLOG=16, VERY_FAST)兼容
+use std::io::Result;
 
-    while iidx < input_len {
-        let mut ctrl = input[iidx as usize] as isize;
+use crate::rdb::malformed;
+
+const HLOG: u32 = 16;
+const HSIZE: usize = 1 << HLOG;
+// 单个literal段的最大长度
+const MAX_LIT: usize = 1 << 5;
+// 回溯引用的最大距离
+const MAX_OFF: usize = 1 << 13;
+// 回溯引用的最大长度
+const MAX_REF: usize = (1 << 8) + (1 << 3);
+// 每个输入字节最多解压出的字节数, 3个字节的引用最长可展开为MAX_REF + 1个字节
+const MAX_EXPANSION: usize = (MAX_REF + 1) / 3;
+
+// lzf解压缩算法, 输入被截断、引用越界或解压后的长度不等于`output_len`时返回错误
+pub(crate) fn decompress(input: &[u8], output_len: usize) -> Result<Vec<u8>> {
+    // `output_len`来自rdb, 预分配前先排除输入不可能解压出的长度
+    if output_len > input.len().saturating_mul(MAX_EXPANSION) {
+        return Err(malformed(format!(
+            "lzf output length {} is too large for {} bytes of input",
+            output_len,
+            input.len()
+        )));
+    }
+    let mut output = Vec::with_capacity(output_len);
+    let mut iidx = 0;
+
+    while iidx < input.len() {
+        let ctrl = input[iidx] as usize;
         iidx += 1;
 
         if ctrl < (1 << 5) {
-            ctrl += 1;
-
-            if oidx + ctrl > output_len {
-                return;
+            let length = ctrl + 1;
+            if iidx + length > input.len() {
+                return Err(malformed("lzf literal run exceeds input"));
             }
-
-            while ctrl > 0 {
-                output[oidx as usize] = input[iidx as usize];
-                oidx += 1;
-                iidx += 1;
-                ctrl -= 1;
+            if output.len() + length > output_len {
+                return Err(malformed("lzf output exceeds expected length"));
             }
+            output.extend_from_slice(&input[iidx..iidx + length]);
+            iidx += length;
         } else {
             let mut length = ctrl >> 5;
-            let mut reference = oidx - ((ctrl & 0x1f) << 8) - 1;
             if length == 7 {
-                length += input[iidx as usize] as isize;
+                length += *input.get(iidx).ok_or_else(|| malformed("lzf back reference is truncated"))? as usize;
                 iidx += 1;
             }
-            reference -= input[iidx as usize] as isize;
+            let low = *input.get(iidx).ok_or_else(|| malformed("lzf back reference is truncated"))? as usize;
             iidx += 1;
-            if ((oidx + length + 2) > output_len) || reference < 0 {
-                return;
+            let distance = ((ctrl & 0x1f) << 8) + low + 1;
+            if distance > output.len() {
+                return Err(malformed("lzf back reference points before output"));
+            }
+            let length = length + 2;
+            if output.len() + length > output_len {
+                return Err(malformed("lzf output exceeds expected length"));
+            }
+            // 引用的区间可能与正在写入的区间重叠, 需要逐字节复制
+            let reference = output.len() - distance;
+            for i in reference..reference + length {
+                output.push(output[i]);
+            }
+        }
+    }
+    if output.len() != output_len {
+        return Err(malformed(format!(
+            "lzf output length {} is not the expected {}",
+            output.len(),
+            output_len
+        )));
+    }
+    Ok(output)
+}
+
+fn frst(input: &[u8], ip: usize) -> u32 {
+    (input[ip] as u32) << 8 | input[ip + 1] as u32
+}
+
+fn next(hval: u32, input: &[u8], ip: usize) -> u32 {
+    hval << 8 | input[ip + 2] as u32
+}
+
+fn idx(hval: u32) -> usize {
+    ((hval >> (3 * 8 - HLOG)).wrapping_sub(hval.wrapping_mul(5)) as usize) & (HSIZE - 1)
+}
+
+// lzf压缩算法, 逐字节照搬liblzf的lzf_compress, 输出与Redis一致,
+// 压缩后超过`output_len`时返回`None`, 对应lzf_compress返回0
+pub(crate) fn compress(input: &[u8], output_len: usize) -> Option<Vec<u8>> {
+    let in_len = input.len();
+    if in_len == 0 || output_len == 0 {
+        return None;
+    }
+    let mut output = vec![0u8; output_len];
+    // 记录每个hash最近一次出现的位置, 0表示未出现过, 位置0也因此不会被引用
+    let mut htab = vec![0usize; HSIZE];
+    let mut ip = 0;
+    // 当前literal段的长度, 段首预留一个字节记录长度
+    let mut lit = 0;
+    let mut op = 1;
+
+    let mut hval = if in_len >= 2 { frst(input, ip) } else { 0 };
+    while ip + 2 < in_len {
+        hval = next(hval, input, ip);
+        let slot = idx(hval);
+        let reference = htab[slot];
+        htab[slot] = ip;
+
+        if reference > 0
+            && ip - reference - 1 < MAX_OFF
+            && input[reference + 2] == input[ip + 2]
+            && input[reference..reference + 2] == input[ip..ip + 2]
+        {
+            let off = ip - reference - 1;
+            let mut len = 2;
+            let maxlen = (in_len - ip - len).min(MAX_REF);
+
+            if op + 3 + 1 >= output_len && op - (lit == 0) as usize + 3 + 1 >= output_len {
+                return None;
+            }
+            // 结束当前literal段, 长度为0时撤销
+            output[op - lit - 1] = (lit as u8).wrapping_sub(1);
+            op -= (lit == 0) as usize;
+
+            // liblzf在maxlen > 16时先不检查maxlen比较16个字节
+            let mut matched = false;
+            if maxlen > 16 {
+                for _ in 0..16 {
+                    len += 1;
+                    if input[reference + len] != input[ip + len] {
+                        matched = true;
+                        break;
+                    }
+                }
+            }
+            if !matched {
+                loop {
+                    len += 1;
+                    if !(len < maxlen && input[reference + len] == input[ip + len]) {
+                        break;
+                    }
+                }
+            }
+
+            len -= 2;
+            ip += 1;
+            if len < 7 {
+                output[op] = ((off >> 8) + (len << 5)) as u8;
+                op += 1;
+            } else {
+                output[op] = ((off >> 8) + (7 << 5)) as u8;
+                output[op + 1] = (len - 7) as u8;
+                op += 2;
             }
+            output[op] = off as u8;
+            op += 1;
+            lit = 0;
+            op += 1;
 
-            output[oidx as usize] = output[reference as usize];
-            oidx += 1;
-            reference += 1;
-            output[oidx as usize] = output[reference as usize];
-            oidx += 1;
-            reference += 1;
+            ip += len + 1;
+            if ip + 2 >= in_len {
+                break;
+            }
+            // 只记录引用末尾两个位置的hash
+            ip -= 2;
+            hval = frst(input, ip);
+            hval = next(hval, input, ip);
+            htab[idx(hval)] = ip;
+            ip += 1;
+            hval = next(hval, input, ip);
+            htab[idx(hval)] = ip;
+            ip += 1;
+        } else {
+            if op >= output_len {
+                return None;
+            }
+            lit += 1;
+            output[op] = input[ip];
+            op += 1;
+            ip += 1;
+            if lit == MAX_LIT {
+                output[op - lit - 1] = (lit - 1) as u8;
+                lit = 0;
+                op += 1;
+            }
+        }
+    }
+
+    if op + 3 > output_len {
+        return None;
+    }
+    while ip < in_len {
+        lit += 1;
+        output[op] = input[ip];
+        op += 1;
+        ip += 1;
+        if lit == MAX_LIT {
+            output[op - lit - 1] = (lit - 1) as u8;
+            lit = 0;
+            op += 1;
+        }
+    }
+    output[op - lit - 1] = (lit as u8).wrapping_sub(1);
+    op -= (lit == 0) as usize;
+    output.truncate(op);
+    Some(output)
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use crate::lzf::{compress, decompress};
+
+    // 由照Redis的lzf_c.c(HLOG=16, VERY_FAST)编译的C程序以`outlen = len - 4`压缩得到,
+    // 并非从运行中的Redis导出
+    const FIXTURES: [(&[u8], &[u8]); 4] = [
+        (include_bytes!("testdata/lzf/json.txt"), include_bytes!("testdata/lzf/json.txt.lzf")),
+        (include_bytes!("testdata/lzf/runs.bin"), include_bytes!("testdata/lzf/runs.bin.lzf")),
+        (include_bytes!("testdata/lzf/numbers.txt"), include_bytes!("testdata/lzf/numbers.txt.lzf")),
+        (include_bytes!("testdata/lzf/mixed.bin"), include_bytes!("testdata/lzf/mixed.bin.lzf")),
+    ];
 
-            while length > 0 {
-                output[oidx as usize] = output[reference as usize];
-                oidx += 1;
-                reference += 1;
-                length -= 1;
+    #[test]
+    fn test_compress_same_as_redis() {
+        for (input, expected) in FIXTURES {
+            assert_eq!(compress(input, input.len() - 4).unwrap(), expected);
+            assert_eq!(decompress(expected, input.len()).unwrap(), input);
+        }
+    }
+
+    /// 任意字节或只由少数几种字节组成、容易被压缩的数据
+    fn input() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![vec(any::<u8>(), 0..10000), vec(0..4u8, 0..20000)]
+    }
+
+    proptest! {
+        #[test]
+        fn test_compress_round_trip(input in input()) {
+            let len = input.len();
+            // 最坏情况下每32个字节多出1个字节的长度, 另留出结尾的余量
+            let compressed = compress(&input, len + len / 32 + 16);
+            prop_assert_eq!(compressed.is_none(), input.is_empty());
+            if let Some(compressed) = compressed {
+                prop_assert_eq!(&decompress(&compressed, len).unwrap(), &input);
+                // 限制输出长度时, 要么结果相同, 要么因放不下而失败
+                match compress(&input, len.saturating_sub(4)) {
+                    Some(shorter) => prop_assert_eq!(&shorter, &compressed),
+                    None => prop_assert!(compressed.len() + 4 > len),
+                }
             }
         }
+
+        #[test]
+        fn test_decompress_arbitrary(input in vec(any::<u8>(), 0..64), output_len in 0..1000usize) {
+            // 任意输入都只能返回错误, 不能panic
+            if let Ok(output) = decompress(&input, output_len) {
+                prop_assert_eq!(output.len(), output_len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compress_short_input() {
+        assert_eq!(compress(b"", 10), None);
+        assert_eq!(compress(b"ab", 10).unwrap(), vec![1, b'a', b'b']);
+        assert_eq!(decompress(&[], 0).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_decompress_malformed() {
+        let (input, compressed) = FIXTURES[0];
+        // 截断的输入
+        for len in [1, compressed.len() / 2, compressed.len() - 1] {
+            assert!(decompress(&compressed[..len], input.len()).is_err());
+        }
+        // 长度与预期不符
+        assert!(decompress(compressed, input.len() + 1).is_err());
+        assert!(decompress(compressed, input.len() - 1).is_err());
+        // literal段超出输入
+        assert!(decompress(&[5, b'a', b'b'], 6).is_err());
+        // 引用位于输出之前
+        assert!(decompress(&[0, b'a', 0x20, 1], 4).is_err());
+        // 缺少引用的偏移字节
+        assert!(decompress(&[0, b'a', 0xE0, 3], 13).is_err());
+        // 1个字节的literal加上最长的引用, 共解压出265个字节; 超过每个输入字节88个的上限时直接拒绝
+        let longest = [0, b'a', 0xE0, 0xFF, 0];
+        assert_eq!(decompress(&longest, 265).unwrap(), vec![b'a'; 265]);
+        assert!(decompress(&longest, 5 * 88 + 1).is_err());
+        assert!(decompress(&[0, b'a'], usize::MAX).is_err());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,250 @@
+/*!
+RedisBloom的解析器, 包括:
+- Scalable Bloom Filter(`MBbloom--`), encver 0 ~ 4
+- Count-Min Sketch(`CMSk-TYPE`), encver 0
+- Top-K(`TopK-TYPE`), encver 0
+*/
+
+use std::any::Any;
+use std::io::{Cursor, Read, Result};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::rdb::{malformed, Module, ModuleDecode};
+use crate::ModuleParser;
+
+pub const BLOOM_MODULE_NAME: &str = "MBbloom--";
+pub const CMS_MODULE_NAME: &str = "CMSk-TYPE";
+pub const TOPK_MODULE_NAME: &str = "TopK-TYPE";
+
+const BLOOM_MAX_ENCVER: usize = 4;
+const BLOOM_MIN_OPTIONS_ENCVER: usize = 2;
+const BLOOM_MIN_GROWTH_ENCVER: usize = 4;
+/// Top-K中每个`HeapBucket`结构体的大小, 依次为u32 fp, u32 itemlen, 8字节指针, u32 count及4字节对齐填充
+const TOPK_HEAP_BUCKET_SIZE: usize = 24;
+
+/// 由多个Bloom Filter组成的Scalable Bloom Filter
+#[derive(Debug)]
+pub struct Bloom {
+    /// 已添加的元素总数
+    pub size: u64,
+    /// `BF.RESERVE`的选项, 如NOSCALING
+    pub options: u64,
+    /// 扩容时新filter的容量倍数
+    pub growth: u64,
+    pub filters: Vec<BloomFilter>,
+}
+
+#[derive(Debug)]
+pub struct BloomFilter {
+    /// filter的容量
+    pub entries: u64,
+    /// 期望的误判率
+    pub error: f64,
+    /// hash函数的个数
+    pub hashes: u64,
+    /// 每个元素占用的bit数
+    pub bpe: f64,
+    /// bit数组的长度
+    pub bits: u64,
+    /// bit数组长度以2为底的对数, 非2的幂时为0
+    pub n2: u64,
+    /// filter中已添加的元素个数
+    pub size: u64,
+    /// bit数组
+    pub bit_array: Vec<u8>,
+}
+
+impl Module for Bloom {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub struct BloomParser;
+
+impl ModuleParser for BloomParser {
+    fn parse(&mut self, input: &mut dyn Read, _: &str, module_version: usize) -> Result<Box<dyn Module>> {
+        if module_version > BLOOM_MAX_ENCVER {
+            return Err(malformed(format!("unsupported {} encver: {}", BLOOM_MODULE_NAME, module_version)));
+        }
+        let size = input.load_unsigned()?;
+        let nfilters = input.load_unsigned()?;
+        let options = if module_version >= BLOOM_MIN_OPTIONS_ENCVER { input.load_unsigned()? } else { 0 };
+        let growth = if module_version >= BLOOM_MIN_GROWTH_ENCVER { input.load_unsigned()? } else { 2 };
+        let mut filters = Vec::new();
+        for _ in 0..nfilters {
+            let entries = input.load_unsigned()?;
+            let error = input.load_double()?;
+            let hashes = input.load_unsigned()?;
+            let bpe = input.load_double()?;
+            let (bits, n2) = if module_version == 0 {
+                ((entries as f64 * bpe) as u64, 0)
+            } else {
+                (input.load_unsigned()?, input.load_unsigned()?)
+            };
+            let bit_array = input.load_string()?;
+            let size = input.load_unsigned()?;
+            filters.push(BloomFilter { entries, error, hashes, bpe, bits, n2, size, bit_array });
+        }
+        Ok(Box::new(Bloom { size, options, growth, filters }))
+    }
+}
+
+/// Count-Min Sketch
+#[derive(Debug)]
+pub struct CountMinSketch {
+    pub width: u64,
+    pub depth: u64,
+    /// 已计数的总数
+    pub count: u64,
+    /// 按行存储的`depth * width`个计数器
+    pub counters: Vec<u32>,
+}
+
+impl Module for CountMinSketch {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub struct CountMinSketchParser;
+
+impl ModuleParser for CountMinSketchParser {
+    fn parse(&mut self, input: &mut dyn Read, _: &str, module_version: usize) -> Result<Box<dyn Module>> {
+        if module_version != 0 {
+            return Err(malformed(format!("unsupported {} encver: {}", CMS_MODULE_NAME, module_version)));
+        }
+        let width = input.load_unsigned()?;
+        let depth = input.load_unsigned()?;
+        let count = input.load_unsigned()?;
+        let buf = input.load_string()?;
+        if width.checked_mul(depth).and_then(|n| n.checked_mul(4)) != Some(buf.len() as u64) {
+            return Err(malformed(format!("invalid count-min sketch size: {}", buf.len())));
+        }
+        let mut counters = vec![0; buf.len() / 4];
+        Cursor::new(&buf).read_u32_into::<LittleEndian>(&mut counters)?;
+        Ok(Box::new(CountMinSketch { width, depth, count, counters }))
+    }
+}
+
+/// Top-K
+#[derive(Debug)]
+pub struct TopK {
+    pub k: u64,
+    pub width: u64,
+    pub depth: u64,
+    pub decay: f64,
+    /// 当前的top k元素及其计数, 按堆中的顺序排列, 不包括空位
+    pub items: Vec<TopKItem>,
+}
+
+#[derive(Debug)]
+pub struct TopKItem {
+    pub item: Vec<u8>,
+    pub count: u32,
+}
+
+impl Module for TopK {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub struct TopKParser;
+
+impl ModuleParser for TopKParser {
+    fn parse(&mut self, input: &mut dyn Read, _: &str, module_version: usize) -> Result<Box<dyn Module>> {
+        if module_version != 0 {
+            return Err(malformed(format!("unsupported {} encver: {}", TOPK_MODULE_NAME, module_version)));
+        }
+        let k = input.load_unsigned()?;
+        let width = input.load_unsigned()?;
+        let depth = input.load_unsigned()?;
+        let decay = input.load_double()?;
+        // 各bucket的指纹与计数, 不影响top k的结果
+        input.load_string()?;
+        let heap = input.load_string()?;
+        if k.checked_mul(TOPK_HEAP_BUCKET_SIZE as u64) != Some(heap.len() as u64) {
+            return Err(malformed(format!("invalid top-k heap size: {}", heap.len())));
+        }
+        let mut items = Vec::new();
+        for bucket in heap.chunks(TOPK_HEAP_BUCKET_SIZE) {
+            let count = Cursor::new(&bucket[16..]).read_u32::<LittleEndian>()?;
+            // 元素以'\0'结尾保存, 空位只有一个'\0'
+            let mut item = input.load_string()?;
+            if item.len() <= 1 {
+                continue;
+            }
+            item.pop();
+            items.push(TopKItem { item, count });
+        }
+        Ok(Box::new(TopK { k, width, depth, decay, items }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::modules::bloom::{Bloom, CountMinSketch, TopK};
+    use crate::modules::test::{parse_module, ModuleValue};
+    use crate::rdb::RawModule;
+
+    #[test]
+    fn test_parse_bloom() {
+        parse_module(include_bytes!("testdata/bloom.rdb"), |module| {
+            let bloom = module.as_any().downcast_ref::<Bloom>().unwrap();
+            assert_eq!((bloom.size, bloom.options, bloom.growth), (3, 0, 2));
+            assert_eq!(bloom.filters.len(), 1);
+            let filter = &bloom.filters[0];
+            assert_eq!((filter.entries, filter.hashes, filter.bits, filter.n2, filter.size), (100, 7, 1024, 10, 3));
+            assert_eq!(filter.error, 0.01);
+            assert_eq!(filter.bit_array.len(), 128);
+            assert_eq!(&filter.bit_array[..4], &[0x81, 0, 0x24, 0]);
+        });
+    }
+
+    #[test]
+    fn test_parse_count_min_sketch() {
+        parse_module(include_bytes!("testdata/cms.rdb"), |module| {
+            let cms = module.as_any().downcast_ref::<CountMinSketch>().unwrap();
+            assert_eq!((cms.width, cms.depth, cms.count), (4, 2, 5));
+            assert_eq!(cms.counters, vec![0, 3, 0, 2, 2, 0, 3, 0]);
+        });
+    }
+
+    #[test]
+    fn test_parse_top_k() {
+        parse_module(include_bytes!("testdata/topk.rdb"), |module| {
+            let topk = module.as_any().downcast_ref::<TopK>().unwrap();
+            assert_eq!((topk.k, topk.width, topk.depth, topk.decay), (3, 8, 2, 0.9));
+            let items: Vec<_> = topk.items.iter().map(|i| (String::from_utf8_lossy(&i.item), i.count)).collect();
+            assert_eq!(items, vec![("foo".into(), 5), ("bar".into(), 2)]);
+        });
+    }
+
+    #[test]
+    fn test_parse_size_overflow() {
+        // width * depth * 4及k * 24溢出后恰好等于实际的长度, 不能被当作合法的值
+        let cms = ModuleValue::default().unsigned((1 << 62) + 4).unsigned(2).unsigned(0).string([0; 32]);
+        let topk = ModuleValue::default().unsigned(1 << 61).unsigned(8).unsigned(2).double(0.9).string([]).string([]);
+        for (rdb, name) in [(cms.rdb("CMSk-TYPE", 0), "CMSk-TYPE"), (topk.rdb("TopK-TYPE", 0), "TopK-TYPE")] {
+            parse_module(&rdb, |module| {
+                let raw = module.as_any().downcast_ref::<RawModule>().unwrap();
+                assert_eq!(raw.name, name);
+            });
+        }
+    }
+
+    #[test]
+    fn test_parse_bloom_truncated_filter() {
+        // 声明了2个filter, 实际只有1个, 解析失败后保留原始数据
+        let mut rdb = include_bytes!("testdata/bloom.rdb").to_vec();
+        rdb[26] = 2;
+        parse_module(&rdb, |module| {
+            let raw = module.as_any().downcast_ref::<RawModule>().unwrap();
+            assert_eq!(raw.name, "MBbloom--");
+            assert_eq!(raw.version, 4);
+            assert_eq!(raw.data[..6], [2, 3, 2, 2, 2, 0]);
+        });
+    }
+}
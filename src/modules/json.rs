@@ -0,0 +1,66 @@
+/*!
+RedisJSON(`ReJSON-RL`)的解析器
+
+encver 2及以上的版本将整个文档序列化为一个JSON字符串保存, 早期的树形格式不支持, 会作为原始数据保留。
+*/
+
+use std::any::Any;
+use std::io::{Read, Result};
+
+use crate::rdb::{malformed, Module, ModuleDecode};
+use crate::ModuleParser;
+
+pub const MODULE_NAME: &str = "ReJSON-RL";
+
+/// 一个JSON文档
+#[derive(Debug)]
+pub struct Json {
+    /// 序列化后的JSON文本
+    pub document: String,
+}
+
+impl Module for Json {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub struct JsonParser;
+
+impl ModuleParser for JsonParser {
+    fn parse(&mut self, input: &mut dyn Read, _: &str, module_version: usize) -> Result<Box<dyn Module>> {
+        if module_version < 2 {
+            return Err(malformed(format!("unsupported {} encver: {}", MODULE_NAME, module_version)));
+        }
+        let document = String::from_utf8(input.load_string()?).map_err(|_| malformed("JSON document is not valid UTF-8"))?;
+        Ok(Box::new(Json { document }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::modules::json::Json;
+    use crate::modules::test::parse_module;
+    use crate::rdb::RawModule;
+
+    #[test]
+    fn test_parse_json() {
+        let rdb = include_bytes!("testdata/json.rdb");
+        parse_module(rdb, |module| {
+            let json = module.as_any().downcast_ref::<Json>().unwrap();
+            assert_eq!(json.document, r#"{"name":"redis","tags":["a","b"]}"#);
+        });
+    }
+
+    #[test]
+    fn test_parse_json_unsupported() {
+        // encver 1的树形格式, 保留原始数据
+        let mut rdb = include_bytes!("testdata/json.rdb").to_vec();
+        rdb[22] = (rdb[22] & !3) | 1;
+        parse_module(&rdb, |module| {
+            let raw = module.as_any().downcast_ref::<RawModule>().unwrap();
+            assert_eq!(raw.name, "ReJSON-RL");
+            assert_eq!(raw.version, 1);
+        });
+    }
+}
@@ -0,0 +1,121 @@
+/*!
+常用Redis module的内置解析器
+
+每个module的解析器都由单独的cargo feature控制, 默认全部开启:
+- `module-json`: RedisJSON, 见[json]
+- `module-bloom`: RedisBloom中的Bloom Filter, Count-Min Sketch与Top-K, 见[bloom]
+- `module-timeseries`: RedisTimeSeries, 见[timeseries]
+
+解析器不会自动生效, 需要通过[register_builtin]或逐个注册到[ModuleRegistry]中。
+解析得到的值可通过`Module::as_any`向下转型为对应的结构体。
+
+[json]: json/index.html
+[bloom]: bloom/index.html
+[timeseries]: timeseries/index.html
+[register_builtin]: fn.register_builtin.html
+[ModuleRegistry]: ../rdb/struct.ModuleRegistry.html
+*/
+
+use crate::rdb::ModuleRegistry;
+
+#[cfg(feature = "module-bloom")]
+pub mod bloom;
+#[cfg(feature = "module-json")]
+pub mod json;
+#[cfg(feature = "module-timeseries")]
+pub mod timeseries;
+
+/// 将已开启feature的内置解析器全部注册到`registry`
+#[allow(unused_variables)]
+pub fn register_builtin(registry: &mut ModuleRegistry) {
+    #[cfg(feature = "module-json")]
+    registry.register(json::MODULE_NAME, Box::new(json::JsonParser));
+    #[cfg(feature = "module-bloom")]
+    {
+        registry.register(bloom::BLOOM_MODULE_NAME, Box::new(bloom::BloomParser));
+        registry.register(bloom::CMS_MODULE_NAME, Box::new(bloom::CountMinSketchParser));
+        registry.register(bloom::TOPK_MODULE_NAME, Box::new(bloom::TopKParser));
+    }
+    #[cfg(feature = "module-timeseries")]
+    registry.register(timeseries::MODULE_NAME, Box::new(timeseries::TimeSeriesParser));
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::io::Cursor;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use crate::rdb::{Module, ModuleRegistry, Object, RDBParser, MODULE_SET};
+    use crate::writer::RDBEncode;
+    use crate::{Event, EventHandler};
+
+    struct Collect<F: FnMut(&dyn Module)>(F, usize);
+
+    impl<F: FnMut(&dyn Module)> EventHandler for Collect<F> {
+        fn handle(&mut self, event: Event) {
+            if let Event::RDB(Object::Module(_, module, _)) = event {
+                (self.0)(module.as_ref());
+                self.1 += 1;
+            }
+        }
+    }
+
+    /// 使用内置解析器解析`rdb`, 并对其中唯一的module值调用`f`
+    ///
+    /// testdata下的rdb文件均是参照各module的保存代码手工构造的, 没有用真实的module生成
+    pub(crate) fn parse_module<F: FnMut(&dyn Module)>(rdb: &[u8], f: F) {
+        let mut modules = ModuleRegistry::new();
+        super::register_builtin(&mut modules);
+        let mut handler = Collect(f, 0);
+        RDBParser::parse_with_modules(&mut Cursor::new(rdb), &mut handler, &mut modules, Arc::new(AtomicBool::new(true)))
+            .unwrap();
+        assert_eq!(handler.1, 1);
+    }
+
+    /// 按module API的`RedisModule_Save*`依次写入一个module值
+    #[derive(Default)]
+    pub(crate) struct ModuleValue(Vec<u8>);
+
+    impl ModuleValue {
+        pub(crate) fn unsigned(mut self, value: u64) -> ModuleValue {
+            self.0.push(2);
+            self.0.write_length(value).unwrap();
+            self
+        }
+
+        pub(crate) fn signed(mut self, value: i64) -> ModuleValue {
+            self.0.push(1);
+            self.0.write_length(value as u64).unwrap();
+            self
+        }
+
+        pub(crate) fn double(mut self, value: f64) -> ModuleValue {
+            self.0.push(4);
+            self.0.extend_from_slice(&value.to_le_bytes());
+            self
+        }
+
+        pub(crate) fn string<S: AsRef<[u8]>>(mut self, value: S) -> ModuleValue {
+            self.0.push(5);
+            self.0.write_string(value.as_ref()).unwrap();
+            self
+        }
+
+        /// 以`name`及`encver`生成只有这一个值的RDB, key为"key"
+        pub(crate) fn rdb(self, name: &str, encver: u64) -> Vec<u8> {
+            let mut id = encver;
+            for (i, chr) in name.chars().enumerate() {
+                let index = MODULE_SET.iter().position(|c| *c == chr).unwrap() as u64;
+                id |= index << (10 + (8 - i) * 6);
+            }
+            let mut rdb = b"REDIS0009\x07\x03key\x81".to_vec();
+            rdb.extend_from_slice(&id.to_be_bytes());
+            rdb.extend_from_slice(&self.0);
+            // module值的EOF, RDB的EOF及checksum
+            rdb.extend_from_slice(&[0, 0xFF]);
+            rdb.extend_from_slice(&[0; 8]);
+            rdb
+        }
+    }
+}
@@ -0,0 +1,738 @@
+/*!
+RedisTimeSeries(`TSDB-TYPE`)的解析器
+
+支持encver 4 ~ 6(RedisTimeSeries 1.4 ~ 1.8)的格式:
+- 未压缩的chunk直接读出全部样本
+- Gorilla压缩的chunk解码为样本, 解码结果与chunk中保存的最后一个样本不一致时视为数据有误
+- compaction规则连同其尚未写入目标序列的聚合状态一起解析, TWA聚合的状态暂不支持
+
+encver 5在compaction规则中增加了bucket对齐时间, encver 6在聚合状态中增加了重置标志。
+更早的encver不以chunk为单位保存样本, 更新的encver增加了插入过滤等字段,
+这些版本的值以及无法解析的值均作为原始数据保留。
+
+格式参照RedisTimeSeries的`rdb.c`, `gorilla.c`及`compaction.c`实现,
+测试数据按同样的格式构造, 没有用真实的RedisTimeSeries导出。
+*/
+
+use std::any::Any;
+use std::io::{Cursor, Read, Result};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::rdb::{malformed, Module, ModuleDecode};
+use crate::ModuleParser;
+
+pub const MODULE_NAME: &str = "TSDB-TYPE";
+
+const MIN_ENCVER: usize = 4;
+const MAX_ENCVER: usize = 6;
+/// 从该encver起compaction规则保存bucket的对齐时间
+const ALIGNMENT_ENCVER: usize = 5;
+/// 从该encver起聚合状态保存重置标志
+const RESETTED_ENCVER: usize = 6;
+/// 未压缩chunk的选项标志位
+const SERIES_OPT_UNCOMPRESSED: u64 = 0x1;
+/// 每个未压缩的样本为u64时间戳加f64值
+const SAMPLE_SIZE: usize = 16;
+/// Gorilla编码中时间戳二阶差分的各级位数, 超出最后一级时使用64位
+const DOD_BITS: [u8; 5] = [5, 8, 11, 14, 32];
+
+/// 一条时间序列
+#[derive(Debug)]
+pub struct TimeSeries {
+    pub key: Vec<u8>,
+    /// 数据保留时长, 毫秒, 0表示永久保留
+    pub retention: u64,
+    /// 每个chunk的字节数
+    pub chunk_size: u64,
+    pub options: u64,
+    pub last_timestamp: u64,
+    pub last_value: f64,
+    pub total_samples: u64,
+    pub duplicate_policy: u64,
+    /// 作为compaction目标时的源序列
+    pub src_key: Option<Vec<u8>>,
+    pub labels: Vec<(Vec<u8>, Vec<u8>)>,
+    pub rules: Vec<CompactionRule>,
+    pub chunks: Vec<Chunk>,
+}
+
+impl TimeSeries {
+    /// 按时间顺序遍历所有chunk中的样本
+    pub fn samples(&self) -> impl Iterator<Item = &Sample> {
+        self.chunks.iter().flat_map(|chunk| match chunk {
+            Chunk::Uncompressed(samples) | Chunk::Compressed(samples) => samples.iter(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum Chunk {
+    Uncompressed(Vec<Sample>),
+    /// Gorilla压缩的chunk, 已解码
+    Compressed(Vec<Sample>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Sample {
+    pub timestamp: u64,
+    pub value: f64,
+}
+
+/// `TS.CREATERULE`创建的compaction规则
+#[derive(Debug)]
+pub struct CompactionRule {
+    /// 聚合结果写入的目标序列
+    pub dest_key: Vec<u8>,
+    /// bucket时长, 毫秒
+    pub bucket_duration: u64,
+    /// bucket的对齐时间, encver 5之前为0
+    pub timestamp_alignment: u64,
+    pub aggregation: Aggregation,
+    /// 当前bucket的起始时间
+    pub start_current_bucket: u64,
+    /// 当前bucket的聚合状态
+    pub context: AggregationContext,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregation {
+    Min,
+    Max,
+    Sum,
+    Avg,
+    Count,
+    First,
+    Last,
+    Range,
+    StdP,
+    StdS,
+    VarP,
+    VarS,
+}
+
+impl Aggregation {
+    fn from_u64(value: u64) -> Result<Aggregation> {
+        let aggregation = match value {
+            1 => Aggregation::Min,
+            2 => Aggregation::Max,
+            3 => Aggregation::Sum,
+            4 => Aggregation::Avg,
+            5 => Aggregation::Count,
+            6 => Aggregation::First,
+            7 => Aggregation::Last,
+            8 => Aggregation::Range,
+            9 => Aggregation::StdP,
+            10 => Aggregation::StdS,
+            11 => Aggregation::VarP,
+            12 => Aggregation::VarS,
+            _ => return Err(malformed(format!("unsupported aggregation type: {}", value))),
+        };
+        Ok(aggregation)
+    }
+}
+
+/// 聚合状态, 结构由聚合类型决定
+#[derive(Debug, PartialEq)]
+pub enum AggregationContext {
+    /// SUM, COUNT, FIRST, LAST
+    Single { value: f64, resetted: bool },
+    /// MIN, MAX, RANGE
+    MaxMin { max: f64, min: f64, resetted: bool },
+    Avg { value: f64, count: f64, overflow: bool },
+    /// STD.P, STD.S, VAR.P, VAR.S
+    Std { sum: f64, sum_2: f64, count: u64 },
+}
+
+impl Module for TimeSeries {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub struct TimeSeriesParser;
+
+impl ModuleParser for TimeSeriesParser {
+    fn parse(&mut self, input: &mut dyn Read, _: &str, module_version: usize) -> Result<Box<dyn Module>> {
+        if !(MIN_ENCVER..=MAX_ENCVER).contains(&module_version) {
+            return Err(malformed(format!("unsupported {} encver: {}", MODULE_NAME, module_version)));
+        }
+        let key = input.load_string()?;
+        let retention = input.load_unsigned()?;
+        let chunk_size = input.load_unsigned()?;
+        let options = input.load_unsigned()?;
+        let last_timestamp = input.load_unsigned()?;
+        let last_value = input.load_double()?;
+        let total_samples = input.load_unsigned()?;
+        let duplicate_policy = input.load_unsigned()?;
+        let src_key = if input.load_unsigned()? != 0 { Some(input.load_string()?) } else { None };
+        let labels_count = input.load_unsigned()?;
+        let mut labels = Vec::new();
+        for _ in 0..labels_count {
+            labels.push((input.load_string()?, input.load_string()?));
+        }
+        let rules_count = input.load_unsigned()?;
+        let mut rules = Vec::new();
+        for _ in 0..rules_count {
+            rules.push(read_rule(input, module_version)?);
+        }
+        let chunks_count = input.load_unsigned()?;
+        let mut chunks = Vec::new();
+        for _ in 0..chunks_count {
+            let chunk = if options & SERIES_OPT_UNCOMPRESSED != 0 {
+                read_uncompressed_chunk(input)?
+            } else {
+                read_compressed_chunk(input)?
+            };
+            chunks.push(chunk);
+        }
+        Ok(Box::new(TimeSeries {
+            key,
+            retention,
+            chunk_size,
+            options,
+            last_timestamp,
+            last_value,
+            total_samples,
+            duplicate_policy,
+            src_key,
+            labels,
+            rules,
+            chunks,
+        }))
+    }
+}
+
+fn read_rule(input: &mut dyn Read, encver: usize) -> Result<CompactionRule> {
+    let dest_key = input.load_string()?;
+    let bucket_duration = input.load_unsigned()?;
+    let timestamp_alignment = if encver >= ALIGNMENT_ENCVER { input.load_unsigned()? } else { 0 };
+    let aggregation = Aggregation::from_u64(input.load_unsigned()?)?;
+    let start_current_bucket = input.load_unsigned()?;
+    let context = match aggregation {
+        Aggregation::Sum | Aggregation::Count | Aggregation::First | Aggregation::Last => {
+            let value = input.load_double()?;
+            let resetted = encver >= RESETTED_ENCVER && input.load_unsigned()? != 0;
+            AggregationContext::Single { value, resetted }
+        }
+        Aggregation::Min | Aggregation::Max | Aggregation::Range => {
+            let max = input.load_double()?;
+            let min = input.load_double()?;
+            // 重置标志以1字节的字符串保存
+            let resetted = if encver >= RESETTED_ENCVER {
+                match input.load_string()?.as_slice() {
+                    [flag] => *flag != 0,
+                    flag => return Err(malformed(format!("invalid resetted flag of {} bytes", flag.len()))),
+                }
+            } else {
+                false
+            };
+            AggregationContext::MaxMin { max, min, resetted }
+        }
+        Aggregation::Avg => {
+            let value = input.load_double()?;
+            let count = input.load_double()?;
+            let overflow = input.load_unsigned()? != 0;
+            AggregationContext::Avg { value, count, overflow }
+        }
+        Aggregation::StdP | Aggregation::StdS | Aggregation::VarP | Aggregation::VarS => {
+            let sum = input.load_double()?;
+            let sum_2 = input.load_double()?;
+            let count = input.load_unsigned()?;
+            AggregationContext::Std { sum, sum_2, count }
+        }
+    };
+    Ok(CompactionRule { dest_key, bucket_duration, timestamp_alignment, aggregation, start_current_bucket, context })
+}
+
+fn read_uncompressed_chunk(input: &mut dyn Read) -> Result<Chunk> {
+    // base timestamp
+    input.load_unsigned()?;
+    let num_samples = input.load_unsigned()?;
+    // chunk size
+    input.load_unsigned()?;
+    let buf = input.load_string()?;
+    if num_samples.checked_mul(SAMPLE_SIZE as u64).is_none_or(|size| size > buf.len() as u64) {
+        return Err(malformed(format!("chunk of {} bytes holds less than {} samples", buf.len(), num_samples)));
+    }
+    let mut cursor = Cursor::new(&buf);
+    let mut samples = Vec::with_capacity(num_samples as usize);
+    for _ in 0..num_samples {
+        let timestamp = cursor.read_u64::<LittleEndian>()?;
+        let value = cursor.read_f64::<LittleEndian>()?;
+        samples.push(Sample { timestamp, value });
+    }
+    Ok(Chunk::Uncompressed(samples))
+}
+
+fn read_compressed_chunk(input: &mut dyn Read) -> Result<Chunk> {
+    // size
+    input.load_unsigned()?;
+    let count = input.load_unsigned()?;
+    // 已使用的bit数
+    let idx = input.load_unsigned()?;
+    let base_value = input.load_double()?;
+    let base_timestamp = input.load_unsigned()?;
+    let prev_timestamp = input.load_unsigned()?;
+    let prev_delta = input.load_signed()?;
+    let prev_value = input.load_double()?;
+    let prev_leading = input.load_unsigned()?;
+    let prev_trailing = input.load_unsigned()?;
+    let data = input.load_string()?;
+    if idx > data.len() as u64 * 8 {
+        return Err(malformed(format!("chunk of {} bytes holds less than {} bits", data.len(), idx)));
+    }
+    let mut decoder = GorillaDecoder {
+        bits: BitReader { data: &data, pos: 0, end: idx },
+        timestamp: base_timestamp,
+        delta: 0,
+        value: base_value.to_bits(),
+        leading: 32,
+        trailing: 32,
+    };
+    // 样本个数来自输入, 不可信, 不预先分配
+    let mut samples = Vec::new();
+    if count > 0 {
+        samples.push(Sample { timestamp: base_timestamp, value: base_value });
+    }
+    for _ in 1..count {
+        samples.push(decoder.next()?);
+    }
+    // chunk中保存了追加最后一个样本后的编码状态, 用于校验解码结果
+    if decoder.bits.pos != idx
+        || (count > 0 && decoder.timestamp != prev_timestamp)
+        || (count > 1 && decoder.delta != prev_delta)
+        || (count > 0 && decoder.value != prev_value.to_bits())
+        || (count > 1 && (decoder.leading, decoder.trailing) != (prev_leading, prev_trailing))
+    {
+        return Err(malformed("compressed chunk does not match its last sample"));
+    }
+    Ok(Chunk::Compressed(samples))
+}
+
+/// 按bit读取Gorilla压缩的数据
+///
+/// 数据为小端u64的数组, 每个u64从最低位开始写入, 因此第`n`个bit即第`n / 8`个字节的第`n % 8`位
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: u64,
+    end: u64,
+}
+
+impl BitReader<'_> {
+    fn read_bit(&mut self) -> Result<bool> {
+        if self.pos >= self.end {
+            return Err(malformed("compressed chunk ends unexpectedly"));
+        }
+        let bit = self.data[(self.pos / 8) as usize] >> (self.pos % 8) & 1;
+        self.pos += 1;
+        Ok(bit == 1)
+    }
+
+    /// 读取`len`个bit, 先读到的为低位
+    fn read_bits(&mut self, len: u32) -> Result<u64> {
+        let mut value = 0;
+        for i in 0..len {
+            if self.read_bit()? {
+                value |= 1 << i;
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// Gorilla解码, 时间戳按二阶差分编码, 值按与前一个值的异或编码
+struct GorillaDecoder<'a> {
+    bits: BitReader<'a>,
+    timestamp: u64,
+    delta: i64,
+    value: u64,
+    leading: u64,
+    trailing: u64,
+}
+
+impl GorillaDecoder<'_> {
+    fn next(&mut self) -> Result<Sample> {
+        let dod = self.read_delta_of_delta()?;
+        self.delta = self.delta.wrapping_add(dod);
+        self.timestamp = self.timestamp.wrapping_add_signed(self.delta);
+        self.read_value()?;
+        Ok(Sample { timestamp: self.timestamp, value: f64::from_bits(self.value) })
+    }
+
+    /// '0'表示与前一个差分相同, 否则前缀'10', '110'...依次对应[DOD_BITS]中各级的位数, 6个'1'表示64位
+    fn read_delta_of_delta(&mut self) -> Result<i64> {
+        if !self.bits.read_bit()? {
+            return Ok(0);
+        }
+        for len in DOD_BITS {
+            if !self.bits.read_bit()? {
+                let value = self.bits.read_bits(len as u32)?;
+                // 符号扩展
+                let shift = 64 - len as u32;
+                return Ok(((value << shift) as i64) >> shift);
+            }
+        }
+        Ok(self.bits.read_bits(64)? as i64)
+    }
+
+    /// '0'表示值不变, '10'表示异或结果的有效位沿用前一次的位置,
+    /// '11'表示其后依次为5位的前导0个数, 6位的有效位数减1及有效位
+    fn read_value(&mut self) -> Result<()> {
+        if !self.bits.read_bit()? {
+            return Ok(());
+        }
+        if self.bits.read_bit()? {
+            let leading = self.bits.read_bits(5)?;
+            let len = self.bits.read_bits(6)? + 1;
+            if leading + len > 64 {
+                return Err(malformed(format!("invalid xor block: {} leading zeros, {} bits", leading, len)));
+            }
+            self.leading = leading;
+            self.trailing = 64 - leading - len;
+        }
+        let len = 64u64.saturating_sub(self.leading + self.trailing);
+        if len == 0 {
+            return Err(malformed("invalid xor block: 0 bits"));
+        }
+        let xor = self.bits.read_bits(len as u32)? << self.trailing;
+        self.value ^= xor;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::modules::test::{parse_module, ModuleValue};
+    use crate::modules::timeseries::{Aggregation, AggregationContext, Chunk, Sample, TimeSeries, DOD_BITS};
+    use crate::rdb::RawModule;
+
+    /// 按`gorilla.c`的规则编码样本, 用于构造压缩的chunk
+    struct GorillaEncoder {
+        data: Vec<u8>,
+        idx: u64,
+        base: Option<(u64, f64)>,
+        timestamp: u64,
+        delta: i64,
+        value: u64,
+        leading: u64,
+        trailing: u64,
+    }
+
+    impl GorillaEncoder {
+        fn new() -> GorillaEncoder {
+            GorillaEncoder { data: vec![], idx: 0, base: None, timestamp: 0, delta: 0, value: 0, leading: 32, trailing: 32 }
+        }
+
+        fn append_bits(&mut self, value: u64, len: u32) {
+            for i in 0..len {
+                if self.idx.is_multiple_of(64) {
+                    self.data.extend_from_slice(&[0; 8]);
+                }
+                self.data[(self.idx / 8) as usize] |= ((value >> i & 1) as u8) << (self.idx % 8);
+                self.idx += 1;
+            }
+        }
+
+        fn append(&mut self, timestamp: u64, value: f64) {
+            if self.base.is_none() {
+                self.base = Some((timestamp, value));
+                (self.timestamp, self.value) = (timestamp, value.to_bits());
+                return;
+            }
+            let delta = (timestamp - self.timestamp) as i64;
+            let dod = delta - self.delta;
+            if dod == 0 {
+                self.append_bits(0, 1);
+            } else {
+                let level = DOD_BITS.iter().position(|&len| dod >= -(1 << (len - 1)) && dod < 1 << (len - 1));
+                match level {
+                    Some(level) => {
+                        self.append_bits((1 << (level + 1)) - 1, level as u32 + 2);
+                        self.append_bits(dod as u64, DOD_BITS[level] as u32);
+                    }
+                    None => {
+                        self.append_bits(0x3f, 6);
+                        self.append_bits(dod as u64, 64);
+                    }
+                }
+            }
+            (self.timestamp, self.delta) = (timestamp, delta);
+
+            let xor = value.to_bits() ^ self.value;
+            self.value = value.to_bits();
+            if xor == 0 {
+                self.append_bits(0, 1);
+                return;
+            }
+            let leading = (xor.leading_zeros() as u64).min(31);
+            let trailing = xor.trailing_zeros() as u64;
+            if leading >= self.leading && trailing >= self.trailing {
+                self.append_bits(0b01, 2);
+            } else {
+                self.append_bits(0b11, 2);
+                self.append_bits(leading, 5);
+                self.append_bits(64 - leading - trailing - 1, 6);
+                (self.leading, self.trailing) = (leading, trailing);
+            }
+            self.append_bits(xor >> self.trailing, (64 - self.leading - self.trailing) as u32);
+        }
+
+        /// 按`Compressed_SaveToRDB`的顺序写入chunk
+        fn save(&self, value: ModuleValue, count: u64) -> ModuleValue {
+            let (base_timestamp, base_value) = self.base.unwrap();
+            value
+                .unsigned(self.data.len() as u64)
+                .unsigned(count)
+                .unsigned(self.idx)
+                .double(base_value)
+                .unsigned(base_timestamp)
+                .unsigned(self.timestamp)
+                .signed(self.delta)
+                .double(f64::from_bits(self.value))
+                .unsigned(self.leading)
+                .unsigned(self.trailing)
+                .string(&self.data)
+        }
+    }
+
+    /// 写入时间序列中chunk之前的字段, 没有compaction规则
+    fn series_header(options: u64, labels: &[(&str, &str)]) -> ModuleValue {
+        let mut value = ModuleValue::default()
+            .string("temperature")
+            .unsigned(86400000)
+            .unsigned(4096)
+            .unsigned(options)
+            .unsigned(1700000002000)
+            .double(22.0)
+            .unsigned(3)
+            .unsigned(0)
+            .unsigned(0)
+            .unsigned(labels.len() as u64);
+        for (k, v) in labels {
+            value = value.string(k).string(v);
+        }
+        value
+    }
+
+    fn assert_raw(rdb: &[u8], version: usize) {
+        parse_module(rdb, |module| {
+            let raw = module.as_any().downcast_ref::<RawModule>().unwrap();
+            assert_eq!(raw.name, "TSDB-TYPE");
+            assert_eq!(raw.version, version);
+        });
+    }
+
+    #[test]
+    fn test_parse_time_series() {
+        let mut data = Vec::new();
+        for (timestamp, value) in [(1700000000000u64, 20.5f64), (1700000001000, 21.0), (1700000002000, 22.0)] {
+            data.extend_from_slice(&timestamp.to_le_bytes());
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.resize(4096, 0);
+        let rdb = series_header(1, &[("room", "kitchen")])
+            .unsigned(0)
+            .unsigned(1)
+            .unsigned(1700000000000)
+            .unsigned(3)
+            .unsigned(4096)
+            .string(data)
+            .rdb("TSDB-TYPE", 4);
+        parse_module(&rdb, |module| {
+            let series = module.as_any().downcast_ref::<TimeSeries>().unwrap();
+            assert_eq!(series.key, b"temperature");
+            assert_eq!((series.retention, series.chunk_size, series.total_samples), (86400000, 4096, 3));
+            assert_eq!((series.last_timestamp, series.last_value), (1700000002000, 22.0));
+            assert_eq!(series.src_key, None);
+            assert_eq!(series.labels, vec![(b"room".to_vec(), b"kitchen".to_vec())]);
+            assert!(series.rules.is_empty());
+            assert_eq!(series.chunks.len(), 1);
+            match &series.chunks[0] {
+                Chunk::Uncompressed(samples) => assert_eq!(
+                    samples,
+                    &vec![
+                        Sample { timestamp: 1700000000000, value: 20.5 },
+                        Sample { timestamp: 1700000001000, value: 21.0 },
+                        Sample { timestamp: 1700000002000, value: 22.0 },
+                    ]
+                ),
+                chunk => panic!("unexpected chunk: {:?}", chunk),
+            }
+        });
+    }
+
+    #[test]
+    fn test_parse_compressed_chunk_bits() {
+        // 两个样本, 时间戳差分为10, 值不变: '10' + 5位的10 + '0', 即0b00101001
+        let rdb = series_header(0, &[])
+            .unsigned(0)
+            .unsigned(1)
+            .unsigned(64)
+            .unsigned(2)
+            .unsigned(8)
+            .double(1.5)
+            .unsigned(1000)
+            .unsigned(1010)
+            .signed(10)
+            .double(1.5)
+            .unsigned(32)
+            .unsigned(32)
+            .string([0x29, 0, 0, 0, 0, 0, 0, 0])
+            .rdb("TSDB-TYPE", 4);
+        parse_module(&rdb, |module| {
+            let series = module.as_any().downcast_ref::<TimeSeries>().unwrap();
+            assert_eq!(
+                series.samples().collect::<Vec<_>>(),
+                vec![&Sample { timestamp: 1000, value: 1.5 }, &Sample { timestamp: 1010, value: 1.5 }]
+            );
+        });
+    }
+
+    #[test]
+    fn test_parse_compressed_chunk() {
+        // 覆盖时间戳二阶差分的各级位数及值编码的三种情况
+        let mut timestamp = 1700000000000u64;
+        let mut expected = Vec::new();
+        for (i, step) in [0u64, 1000, 1000, 1003, 990, 1090, 1200, 1000, 6000, 60000, 70000, 1 << 40, 5].iter().enumerate() {
+            timestamp += step;
+            let value = match i % 4 {
+                0 => 20.0,
+                1 => 20.0,
+                2 => 20.25 + i as f64,
+                _ => -1.0 / (i as f64),
+            };
+            expected.push(Sample { timestamp, value });
+        }
+        let mut encoder = GorillaEncoder::new();
+        for sample in &expected {
+            encoder.append(sample.timestamp, sample.value);
+        }
+        let value = series_header(0, &[]).unsigned(0).unsigned(1);
+        let rdb = encoder.save(value, expected.len() as u64).rdb("TSDB-TYPE", 4);
+        parse_module(&rdb, |module| {
+            let series = module.as_any().downcast_ref::<TimeSeries>().unwrap();
+            match &series.chunks[..] {
+                [Chunk::Compressed(samples)] => assert_eq!(samples, &expected),
+                chunks => panic!("unexpected chunks: {:?}", chunks),
+            }
+        });
+    }
+
+    #[test]
+    fn test_parse_compressed_chunk_mismatch() {
+        let mut encoder = GorillaEncoder::new();
+        encoder.append(1000, 1.0);
+        encoder.append(2000, 2.0);
+        encoder.append(3000, 3.0);
+        let value = series_header(0, &[]).unsigned(0).unsigned(1);
+        // 样本个数与编码的数据不一致
+        assert_raw(&encoder.save(value, 2).rdb("TSDB-TYPE", 4), 4);
+        // 数据不足
+        let value = series_header(0, &[]).unsigned(0).unsigned(1);
+        assert_raw(&encoder.save(value, 4).rdb("TSDB-TYPE", 4), 4);
+    }
+
+    #[test]
+    fn test_parse_time_series_with_rules() {
+        // encver 5, 有对齐时间, 没有重置标志
+        let rdb = series_header(1, &[])
+            .unsigned(2)
+            .string("temperature:avg")
+            .unsigned(60000)
+            .unsigned(1000)
+            .unsigned(4)
+            .unsigned(1700000001000)
+            .double(43.0)
+            .double(2.0)
+            .unsigned(0)
+            .string("temperature:std")
+            .unsigned(60000)
+            .unsigned(0)
+            .unsigned(10)
+            .unsigned(1700000001000)
+            .double(43.0)
+            .double(925.0)
+            .unsigned(2)
+            .unsigned(0)
+            .rdb("TSDB-TYPE", 5);
+        parse_module(&rdb, |module| {
+            let series = module.as_any().downcast_ref::<TimeSeries>().unwrap();
+            assert_eq!(series.rules.len(), 2);
+            let rule = &series.rules[0];
+            assert_eq!(rule.dest_key, b"temperature:avg");
+            assert_eq!((rule.bucket_duration, rule.timestamp_alignment), (60000, 1000));
+            assert_eq!((rule.aggregation, rule.start_current_bucket), (Aggregation::Avg, 1700000001000));
+            assert_eq!(rule.context, AggregationContext::Avg { value: 43.0, count: 2.0, overflow: false });
+            let rule = &series.rules[1];
+            assert_eq!(rule.aggregation, Aggregation::StdS);
+            assert_eq!(rule.context, AggregationContext::Std { sum: 43.0, sum_2: 925.0, count: 2 });
+            assert!(series.chunks.is_empty());
+        });
+
+        // encver 6, 聚合状态带有重置标志
+        let rdb = series_header(1, &[])
+            .unsigned(2)
+            .string("temperature:sum")
+            .unsigned(60000)
+            .unsigned(0)
+            .unsigned(3)
+            .unsigned(1700000000000)
+            .double(63.5)
+            .unsigned(1)
+            .string("temperature:range")
+            .unsigned(60000)
+            .unsigned(0)
+            .unsigned(8)
+            .unsigned(1700000000000)
+            .double(22.0)
+            .double(20.5)
+            .string([0])
+            .unsigned(0)
+            .rdb("TSDB-TYPE", 6);
+        parse_module(&rdb, |module| {
+            let series = module.as_any().downcast_ref::<TimeSeries>().unwrap();
+            assert_eq!(series.rules[0].context, AggregationContext::Single { value: 63.5, resetted: true });
+            assert_eq!(series.rules[1].aggregation, Aggregation::Range);
+            assert_eq!(series.rules[1].context, AggregationContext::MaxMin { max: 22.0, min: 20.5, resetted: false });
+        });
+    }
+
+    #[test]
+    fn test_parse_sample_count_overflow() {
+        // 样本个数 * 16溢出为0, 与空的chunk长度相同
+        let rdb = series_header(1, &[])
+            .unsigned(0)
+            .unsigned(1)
+            .unsigned(0)
+            .unsigned(1 << 60)
+            .unsigned(0)
+            .string([])
+            .rdb("TSDB-TYPE", 4);
+        assert_raw(&rdb, 4);
+    }
+
+    #[test]
+    fn test_parse_time_series_unsupported() {
+        // encver 3, 样本不以chunk为单位保存
+        let rdb = series_header(1, &[])
+            .unsigned(0)
+            .unsigned(1)
+            .unsigned(1700000002000)
+            .double(22.0)
+            .rdb("TSDB-TYPE", 3);
+        assert_raw(&rdb, 3);
+        // TWA的聚合状态
+        let rdb = series_header(1, &[])
+            .unsigned(1)
+            .string("temperature:twa")
+            .unsigned(60000)
+            .unsigned(0)
+            .unsigned(13)
+            .unsigned(1700000000000)
+            .double(0.0)
+            .unsigned(0)
+            .rdb("TSDB-TYPE", 5);
+        assert_raw(&rdb, 5);
+    }
+}
@@ -4,50 +4,186 @@
 use core::result;
 use std::any::Any;
 use std::cmp;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Debug, Error, Formatter, Display};
-use std::io::{Cursor, Read, Result};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::io::{self, Cursor, ErrorKind, Read, Result};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
-use log::info;
+use log::{info, warn};
 
 use crate::cmd::connection::SELECT;
 use crate::cmd::Command;
+use crate::error::RedisSyncError;
+use crate::io::{Crc64Reader, ProgressReader, RecordReader};
 use crate::iter::{
     IntSetIter, Iter, QuickListIter, SortedSetIter, StrValIter, ZipListIter, ZipMapIter,
 };
-use crate::{lzf, to_string, Event, EventHandler};
+use crate::{lzf, to_string, Event, EventHandler, ModuleParser};
 
-use std::f64::{INFINITY, NAN, NEG_INFINITY};
 use std::iter::FromIterator;
 
 use std::str::FromStr;
 use std::sync::Arc;
 
+/// 无盘复制时RDB之后紧跟的结束标记的长度
+pub(crate) const RDB_EOF_MARK_LEN: usize = 40;
+/// 长度来自输入时, 一次最多预先分配的字节数
+pub(crate) const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// 校验无盘复制时RDB之后的结束标记, 与`$EOF:<mark>`中的标记不一致说明RDB的边界已错位
+pub(crate) fn check_eof_mark(expected: &[u8], actual: &[u8]) -> result::Result<(), RedisSyncError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(RedisSyncError::Protocol(format!(
+            "eof mark mismatch, expected: {}, actual: {}",
+            String::from_utf8_lossy(expected),
+            String::from_utf8_lossy(actual)
+        )))
+    }
+}
+
+/// 值的格式错误, 经由`io::Error`传递, 在[RDBParser::parse]中转换为[RedisSyncError::MalformedRDB]
+///
+/// [RDBParser::parse]: trait.RDBParser.html#method.parse
+/// [RedisSyncError::MalformedRDB]: ../error/enum.RedisSyncError.html#variant.MalformedRDB
+#[derive(Debug)]
+pub(crate) struct Malformed {
+    value_type: Option<u8>,
+    key: Option<Vec<u8>>,
+    reason: String,
+}
+
+impl Display for Malformed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> result::Result<(), Error> {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl std::error::Error for Malformed {}
+
+pub(crate) fn malformed<S: Into<String>>(reason: S) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        Malformed {
+            value_type: None,
+            key: None,
+            reason: reason.into(),
+        },
+    )
+}
+
+/// 为值的格式错误补充所属记录的类型与key
+fn with_value(err: io::Error, value_type: u8, key: Option<&[u8]>) -> io::Error {
+    match err.downcast::<Malformed>() {
+        Ok(mut malformed) => {
+            malformed.value_type.get_or_insert(value_type);
+            if malformed.key.is_none() {
+                malformed.key = key.map(|key| key.to_vec());
+            }
+            io::Error::new(ErrorKind::InvalidData, malformed)
+        }
+        Err(err) => err,
+    }
+}
+
+/// 将解析`value_type`记录时出现的错误转换为[RedisSyncError], `offset`为记录在RDB中的起始位置
+pub(crate) fn rdb_error(err: io::Error, value_type: Option<u8>, offset: u64) -> RedisSyncError {
+    match err.downcast::<Malformed>() {
+        Ok(malformed) => RedisSyncError::MalformedRDB {
+            value_type: malformed.value_type.or(value_type),
+            key: malformed.key.map(|key| String::from_utf8_lossy(&key).into_owned()),
+            offset,
+            reason: malformed.reason,
+        },
+        Err(err) => RedisSyncError::Disconnect(err),
+    }
+}
+
+/// 校验RDB末尾的checksum, `expected`为0时表示生成RDB时关闭了校验(`rdbchecksum no`)
+pub(crate) fn verify_checksum(expected: u64, actual: u64) -> result::Result<(), RedisSyncError> {
+    if expected != 0 && expected != actual {
+        return Err(RedisSyncError::Checksum { expected, actual });
+    }
+    Ok(())
+}
+
+/// 解析RDB头部的版本号
+pub(crate) fn parse_rdb_version(header: &[u8]) -> result::Result<isize, RedisSyncError> {
+    let version = String::from_utf8_lossy(header);
+    version.parse::<isize>().map_err(|_| RedisSyncError::MalformedRDB {
+        value_type: None,
+        key: None,
+        offset: 5,
+        reason: format!("invalid rdb version: {}", version),
+    })
+}
+
+/// 是否为已知的值类型
+pub(crate) fn is_value_type(value_type: u8) -> bool {
+    value_type <= RDB_TYPE_HASH_LISTPACK_EX && value_type != 8
+}
+
+/// 读取`len`个字节, 数据不足时返回`UnexpectedEof`
+///
+/// `len`来自输入, 不可信, 因此按实际读到的数据逐步分配内存
+pub(crate) fn read_bytes<R: Read + ?Sized>(input: &mut R, len: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(len.min(READ_CHUNK_SIZE));
+    (&mut *input).take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(io::Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("expect {} bytes, but only {} left", len, buf.len()),
+        ));
+    }
+    Ok(buf)
+}
+
+/// 迭代器返回`NotFound`表示元素已读完, 其余错误原样返回
+fn next_or_end<T>(next: Result<T>) -> Result<Option<T>> {
+    match next {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// 读取一个listpack元素并解析为整数
+fn read_list_pack_int<T: FromStr, R: Read>(input: R) -> Result<T> {
+    let entry = to_string(read_list_pack_entry(input)?);
+    T::from_str(&entry).map_err(|_| malformed(format!("invalid integer in listpack: {}", entry)))
+}
+
 pub trait RDBDecode: Read {
     fn read_length(&mut self) -> Result<(isize, bool)> {
         let byte = self.read_u8()?;
         let _type = (byte & 0xC0) >> 6;
 
-        let mut result = -1;
-        let mut is_encoded = false;
-
         if _type == RDB_ENCVAL {
-            result = (byte & 0x3F) as isize;
-            is_encoded = true;
+            Ok(((byte & 0x3F) as isize, true))
         } else if _type == RDB_6BITLEN {
-            result = (byte & 0x3F) as isize;
+            Ok(((byte & 0x3F) as isize, false))
         } else if _type == RDB_14BITLEN {
             let next_byte = self.read_u8()?;
-            result = (((byte as u16 & 0x3F) << 8) | next_byte as u16) as isize;
+            Ok(((((byte as u16 & 0x3F) << 8) | next_byte as u16) as isize, false))
         } else if byte == RDB_32BITLEN {
-            result = self.read_integer(4, true)?;
+            Ok((self.read_u32::<BigEndian>()? as isize, false))
         } else if byte == RDB_64BITLEN {
-            result = self.read_integer(8, true)?;
-        };
-        Ok((result, is_encoded))
+            // module的`RedisModule_SaveUnsigned`也以此保存, 超出isize的值按位保留, 由调用方解释
+            Ok((self.read_u64::<BigEndian>()? as isize, false))
+        } else {
+            Err(malformed(format!("invalid length encoding: {:#04x}", byte)))
+        }
+    }
+
+    /// 读取一个长度或元素个数, 编码的值及超出isize的长度视为格式错误
+    fn read_len(&mut self) -> Result<usize> {
+        match self.read_length()? {
+            (length, false) if length >= 0 => Ok(length as usize),
+            (length, _) => Err(malformed(format!("invalid length: {}", length))),
+        }
     }
 
     fn read_integer(&mut self, size: isize, is_big_endian: bool) -> Result<isize> {
@@ -70,7 +206,7 @@ pub trait RDBDecode: Read {
         } else if size == 8 {
             return Ok(cursor.read_i64::<LittleEndian>()? as isize);
         }
-        panic!("Invalid integer size: {}", size)
+        Err(malformed(format!("Invalid integer size: {}", size)))
     }
 
     fn read_string(&mut self) -> Result<Vec<u8>> {
@@ -90,142 +226,218 @@ pub trait RDBDecode: Read {
                     return Ok(int.to_string().into_bytes());
                 }
                 RDB_ENC_LZF => {
-                    let (compressed_len, _) = self.read_length()?;
-                    let (origin_len, _) = self.read_length()?;
-                    let mut compressed = vec![0; compressed_len as usize];
-                    self.read_exact(&mut compressed)?;
-                    let mut origin = vec![0; origin_len as usize];
-                    lzf::decompress(&mut compressed, compressed_len , &mut origin, origin_len );
-                    return Ok(origin);
+                    let compressed_len = self.read_len()?;
+                    let origin_len = self.read_len()?;
+                    let compressed = read_bytes(self, compressed_len)?;
+                    return lzf::decompress(&compressed, origin_len);
                 }
-                _ => panic!("Invalid string length: {}", length),
+                _ => return Err(malformed(format!("Invalid string length: {}", length))),
             };
         };
-        let mut buff = vec![0; length as usize];
-        self.read_exact(&mut buff)?;
-        Ok(buff)
+        if length < 0 {
+            return Err(malformed(format!("Invalid string length: {}", length)));
+        }
+        read_bytes(self, length as usize)
     }
 
     /// 从流中读取一个double
     fn read_double(&mut self) -> Result<f64> {
         let len = self.read_u8()?;
         match len {
-            255 => Ok(NEG_INFINITY),
-            254 => Ok(INFINITY),
-            253 => Ok(NAN),
+            255 => Ok(f64::NEG_INFINITY),
+            254 => Ok(f64::INFINITY),
+            253 => Ok(f64::NAN),
             _ => {
                 let mut buff = vec![0; len as usize];
                 self.read_exact(&mut buff)?;
                 let score_str = to_string(buff);
-                let score = score_str.parse::<f64>().unwrap();
-                Ok(score)
+                score_str
+                    .parse::<f64>()
+                    .map_err(|_| malformed(format!("invalid double: {}", score_str)))
             }
         }
     }
 }
 
 pub trait RDBParser: Read {
+    /// 解析RDB, 格式错误时返回[RedisSyncError::MalformedRDB], I/O错误时返回[RedisSyncError::Disconnect]
+    ///
+    /// [RedisSyncError::MalformedRDB]: ../error/enum.RedisSyncError.html#variant.MalformedRDB
+    /// [RedisSyncError::Disconnect]: ../error/enum.RedisSyncError.html#variant.Disconnect
     fn parse(
         &mut self,
         event_handler: &mut dyn EventHandler,
         running: Arc<AtomicBool>,
-    ) -> Result<()> {
+    ) -> result::Result<(), RedisSyncError> {
+        self.parse_with_modules(event_handler, &mut ModuleRegistry::new(), running)
+    }
+
+    /// 同[parse], module类型的值交由`modules`中注册的解析器解析
+    ///
+    /// [parse]: #method.parse
+    fn parse_with_modules(
+        &mut self,
+        event_handler: &mut dyn EventHandler,
+        modules: &mut ModuleRegistry,
+        running: Arc<AtomicBool>,
+    ) -> result::Result<(), RedisSyncError> {
         event_handler.handle(Event::RDB(Object::BOR));
+        let read = Arc::new(AtomicU64::new(0));
+        let mut input = Crc64Reader::new(ProgressReader::new(self, Arc::clone(&read)));
         let mut bytes = vec![0; 5];
         // 开头5个字节: REDIS
-        self.read_exact(&mut bytes)?;
+        input.read_exact(&mut bytes)?;
         // 4个字节: rdb版本
-        self.read_exact(&mut bytes[..=3])?;
-        let rdb_version = String::from_utf8_lossy(&bytes[..=3]);
-        let rdb_version = rdb_version.parse::<isize>().unwrap();
+        input.read_exact(&mut bytes[..=3])?;
+        let rdb_version = parse_rdb_version(&bytes[..=3])?;
         let mut db = 0;
 
         while running.load(Ordering::Relaxed) {
-            let mut meta = Meta {
-                db,
-                expire: None,
-                evict: None,
-            };
+            let offset = read.load(Ordering::Relaxed);
+            let data_type = input.read_u8()?;
+            match input.parse_entry(data_type, rdb_version, &mut db, event_handler, modules) {
+                Ok(true) => {}
+                Ok(false) => {
+                    if rdb_version >= 5 {
+                        let actual = input.crc();
+                        let expected = input.read_u64::<LittleEndian>()?;
+                        verify_checksum(expected, actual)?;
+                    }
+                    break;
+                }
+                Err(err) => return Err(rdb_error(err, Some(data_type), offset)),
+            }
+        }
+        event_handler.handle(Event::RDB(Object::EOR));
+        Ok(())
+    }
 
-            let data_type = self.read_u8()?;
-            match data_type {
-                RDB_OPCODE_AUX => {
-                    let field_name = self.read_string()?;
-                    let field_val = self.read_string()?;
-                    let field_name = to_string(field_name);
+    /// 解析`data_type`之后的一条记录, 读到`RDB_OPCODE_EOF`时返回`false`, 其后的checksum由调用方读取并校验
+    ///
+    /// 方法参数:
+    ///
+    /// * `data_type`: 已读取的opcode或数据类型
+    /// * `rdb_version`: RDB的版本, 遇到未知的opcode时用于报错
+    /// * `db`: 当前所在的db, 遇到`RDB_OPCODE_SELECTDB`时更新
+    /// * `modules`: module类型的值的解析器
+    fn parse_entry(
+        &mut self,
+        data_type: u8,
+        rdb_version: isize,
+        db: &mut isize,
+        event_handler: &mut dyn EventHandler,
+        modules: &mut ModuleRegistry,
+    ) -> Result<bool> {
+        let mut meta = Meta {
+            db: *db,
+            expire: None,
+            evict: None,
+        };
 
-                    let field_val = to_string(field_val);
-                    info!("{}:{}", field_name, field_val);
-                }
-                RDB_OPCODE_SELECTDB => {
-                    let (_db, _) = self.read_length()?;
-                    meta.db = _db;
-                    db = _db;
-                    let cmd = SELECT { db: _db as i32 };
-                    event_handler.handle(Event::AOF(Command::SELECT(&cmd)));
-                }
-                RDB_OPCODE_RESIZEDB => {
-                    let (total, _) = self.read_length()?;
-                    info!("db[{}] total keys: {}", db, total);
-                    let (expired, _) = self.read_length()?;
-                    info!("db[{}] expired keys: {}", db, expired);
+        match data_type {
+            RDB_OPCODE_AUX => {
+                let field = self.read_string()?;
+                let value = self.read_string()?;
+                info!("{}:{}", String::from_utf8_lossy(&field), String::from_utf8_lossy(&value));
+                event_handler.handle(Event::RDB(Object::Aux(Aux { field: &field, value: &value })));
+            }
+            RDB_OPCODE_SELECTDB => {
+                let (_db, _) = self.read_length()?;
+                *db = _db;
+                let cmd = SELECT { db: _db as i32 };
+                event_handler.handle(Event::AOF(Command::SELECT(&cmd)));
+            }
+            RDB_OPCODE_RESIZEDB => {
+                let (total, _) = self.read_length()?;
+                info!("db[{}] total keys: {}", db, total);
+                let (expires, _) = self.read_length()?;
+                info!("db[{}] expired keys: {}", db, expires);
+                event_handler.handle(Event::RDB(Object::ResizeDB(ResizeDB {
+                    db: *db,
+                    total: total as usize,
+                    expires: expires as usize,
+                })));
+            }
+            RDB_OPCODE_EXPIRETIME | RDB_OPCODE_EXPIRETIME_MS => {
+                if data_type == RDB_OPCODE_EXPIRETIME_MS {
+                    let expired_time = self.read_integer(8, false)?;
+                    meta.expire = Option::Some((ExpireType::Millisecond, expired_time as i64));
+                } else {
+                    let expired_time = self.read_integer(4, false)?;
+                    meta.expire = Option::Some((ExpireType::Second, expired_time as i64));
                 }
-                RDB_OPCODE_EXPIRETIME | RDB_OPCODE_EXPIRETIME_MS => {
-                    if data_type == RDB_OPCODE_EXPIRETIME_MS {
-                        let expired_time = self.read_integer(8, false)?;
-                        meta.expire = Option::Some((ExpireType::Millisecond, expired_time as i64));
-                    } else {
-                        let expired_time = self.read_integer(4, false)?;
-                        meta.expire = Option::Some((ExpireType::Second, expired_time as i64));
+                let value_type = self.read_u8()?;
+                match value_type {
+                    RDB_OPCODE_FREQ => {
+                        let val = self.read_u8()?;
+                        let value_type = self.read_u8()?;
+                        meta.evict = Option::Some((EvictType::LFU, val as i64));
+                        self.read_object(value_type, event_handler, modules, &meta)?;
                     }
-                    let value_type = self.read_u8()?;
-                    match value_type {
-                        RDB_OPCODE_FREQ => {
-                            let val = self.read_u8()?;
-                            let value_type = self.read_u8()?;
-                            meta.evict = Option::Some((EvictType::LFU, val as i64));
-                            self.read_object(value_type, event_handler, &meta)?;
-                        }
-                        RDB_OPCODE_IDLE => {
-                            let (val, _) = self.read_length()?;
-                            let value_type = self.read_u8()?;
-                            meta.evict = Option::Some((EvictType::LRU, val as i64));
-                            self.read_object(value_type, event_handler, &meta)?;
-                        }
-                        _ => {
-                            self.read_object(value_type, event_handler, &meta)?;
-                        }
+                    RDB_OPCODE_IDLE => {
+                        let (val, _) = self.read_length()?;
+                        let value_type = self.read_u8()?;
+                        meta.evict = Option::Some((EvictType::LRU, val as i64));
+                        self.read_object(value_type, event_handler, modules, &meta)?;
                     }
-                }
-                RDB_OPCODE_FREQ => {
-                    let val = self.read_u8()?;
-                    let value_type = self.read_u8()?;
-                    meta.evict = Option::Some((EvictType::LFU, val as i64));
-                    self.read_object(value_type, event_handler, &meta)?;
-                }
-                RDB_OPCODE_IDLE => {
-                    let (val, _) = self.read_length()?;
-                    meta.evict = Option::Some((EvictType::LRU, val as i64));
-                    let value_type = self.read_u8()?;
-                    self.read_object(value_type, event_handler, &meta)?;
-                }
-                RDB_OPCODE_MODULE_AUX => {
-                    self.read_length()?;
-                    self.rdb_load_check_module_value()?;
-                }
-                RDB_OPCODE_EOF => {
-                    if rdb_version >= 5 {
-                        self.read_integer(8, true)?;
+                    _ => {
+                        self.read_object(value_type, event_handler, modules, &meta)?;
                     }
-                    break;
                 }
-                _ => {
-                    self.read_object(data_type, event_handler, &meta)?;
+            }
+            RDB_OPCODE_FREQ => {
+                let val = self.read_u8()?;
+                let value_type = self.read_u8()?;
+                meta.evict = Option::Some((EvictType::LFU, val as i64));
+                self.read_object(value_type, event_handler, modules, &meta)?;
+            }
+            RDB_OPCODE_IDLE => {
+                let (val, _) = self.read_length()?;
+                meta.evict = Option::Some((EvictType::LRU, val as i64));
+                let value_type = self.read_u8()?;
+                self.read_object(value_type, event_handler, modules, &meta)?;
+            }
+            RDB_OPCODE_MODULE_AUX => {
+                self.read_length()?;
+                self.rdb_load_check_module_value()?;
+            }
+            RDB_OPCODE_FUNCTION2 => {
+                let code = self.read_string()?;
+                event_handler.handle(Event::RDB(Object::Function(Function { code: &code })));
+            }
+            RDB_OPCODE_FUNCTION_PRE_GA => {
+                // 依次为库名, 引擎名, 可选的描述, 代码
+                self.read_string()?;
+                self.read_string()?;
+                let (has_desc, _) = self.read_length()?;
+                if has_desc != 0 {
+                    self.read_string()?;
                 }
-            };
-        }
-        event_handler.handle(Event::RDB(Object::EOR));
-        Ok(())
+                let code = self.read_string()?;
+                event_handler.handle(Event::RDB(Object::Function(Function { code: &code })));
+            }
+            RDB_OPCODE_SLOT_INFO => {
+                let (slot, _) = self.read_length()?;
+                let (size, _) = self.read_length()?;
+                let (expires, _) = self.read_length()?;
+                event_handler.handle(Event::RDB(Object::SlotInfo(SlotInfo {
+                    slot: slot as usize,
+                    size: size as usize,
+                    expires: expires as usize,
+                })));
+            }
+            RDB_OPCODE_EOF => return Ok(false),
+            _ if is_value_type(data_type) => {
+                self.read_object(data_type, event_handler, modules, &meta)?;
+            }
+            _ => {
+                return Err(malformed(format!(
+                    "unsupported RDB version {}, unknown opcode: {}",
+                    rdb_version, data_type
+                )))
+            }
+        };
+        Ok(true)
     }
 }
 
@@ -234,20 +446,36 @@ pub trait DefaultRDBParser: Read {
         &mut self,
         value_type: u8,
         event_handler: &mut dyn EventHandler,
+        modules: &mut ModuleRegistry,
+        meta: &Meta,
+    ) -> Result<()> {
+        if !is_value_type(value_type) {
+            return Err(with_value(malformed(format!("unknown value type: {}", value_type)), value_type, None));
+        }
+        let key = self.read_string().map_err(|err| with_value(err, value_type, None))?;
+        self.read_value(value_type, &key, event_handler, modules, meta)
+            .map_err(|err| with_value(err, value_type, Some(&key)))
+    }
+
+    /// 读取`key`之后的值
+    fn read_value(
+        &mut self,
+        value_type: u8,
+        key: &[u8],
+        event_handler: &mut dyn EventHandler,
+        modules: &mut ModuleRegistry,
         meta: &Meta,
     ) -> Result<()> {
         match value_type {
             RDB_TYPE_STRING => {
-                let key = self.read_string()?;
                 let value = self.read_string()?;
                 event_handler.handle(Event::RDB(Object::String(KeyValue {
-                    key: &key,
+                    key,
                     value: &value,
                     meta,
                 })));
             }
             RDB_TYPE_LIST | RDB_TYPE_SET => {
-                let key = self.read_string()?;
                 let (count, _) = self.read_length()?;
                 let mut iter = StrValIter { count };
 
@@ -255,7 +483,7 @@ pub trait DefaultRDBParser: Read {
                 while has_more {
                     let mut val = Vec::new();
                     for _ in 0..BATCH_SIZE {
-                        if let Ok(next_val) = iter.next(&mut *self) {
+                        if let Some(next_val) = next_or_end(iter.next(&mut *self))? {
                             val.push(next_val);
                         } else {
                             has_more = false;
@@ -265,13 +493,13 @@ pub trait DefaultRDBParser: Read {
                     if !val.is_empty() {
                         if value_type == RDB_TYPE_LIST {
                             event_handler.handle(Event::RDB(Object::List(List {
-                                key: &key,
+                                key,
                                 values: &val,
                                 meta,
                             })));
                         } else {
                             event_handler.handle(Event::RDB(Object::Set(Set {
-                                key: &key,
+                                key,
                                 members: &val,
                                 meta,
                             })));
@@ -280,7 +508,6 @@ pub trait DefaultRDBParser: Read {
                 }
             }
             RDB_TYPE_ZSET => {
-                let key = self.read_string()?;
                 let (count, _) = self.read_length()?;
                 let mut iter = SortedSetIter { count, v: 1 };
 
@@ -288,7 +515,7 @@ pub trait DefaultRDBParser: Read {
                 while has_more {
                     let mut val = Vec::new();
                     for _ in 0..BATCH_SIZE {
-                        if let Ok(next_val) = iter.next(&mut *self) {
+                        if let Some(next_val) = next_or_end(iter.next(&mut *self))? {
                             val.push(next_val);
                         } else {
                             has_more = false;
@@ -297,7 +524,7 @@ pub trait DefaultRDBParser: Read {
                     }
                     if !val.is_empty() {
                         event_handler.handle(Event::RDB(Object::SortedSet(SortedSet {
-                            key: &key,
+                            key,
                             items: &val,
                             meta,
                         })));
@@ -305,7 +532,6 @@ pub trait DefaultRDBParser: Read {
                 }
             }
             RDB_TYPE_ZSET_2 => {
-                let key = self.read_string()?;
                 let (count, _) = self.read_length()?;
                 let mut iter = SortedSetIter { count, v: 2 };
 
@@ -313,7 +539,7 @@ pub trait DefaultRDBParser: Read {
                 while has_more {
                     let mut val = Vec::new();
                     for _ in 0..BATCH_SIZE {
-                        if let Ok(next_val) = iter.next(&mut *self) {
+                        if let Some(next_val) = next_or_end(iter.next(&mut *self))? {
                             val.push(next_val);
                         } else {
                             has_more = false;
@@ -322,7 +548,7 @@ pub trait DefaultRDBParser: Read {
                     }
                     if !val.is_empty() {
                         event_handler.handle(Event::RDB(Object::SortedSet(SortedSet {
-                            key: &key,
+                            key,
                             items: &val,
                             meta,
                         })));
@@ -330,7 +556,6 @@ pub trait DefaultRDBParser: Read {
                 }
             }
             RDB_TYPE_HASH => {
-                let key = self.read_string()?;
                 let (count, _) = self.read_length()?;
                 let mut iter = StrValIter { count: count * 2 };
 
@@ -340,10 +565,11 @@ pub trait DefaultRDBParser: Read {
                     for _ in 0..BATCH_SIZE {
                         let name;
                         let value;
-                        if let Ok(next_val) = iter.next(&mut *self) {
+                        if let Some(next_val) = next_or_end(iter.next(&mut *self))? {
                             name = next_val;
-                            value = iter.next(&mut *self).expect("missing hash field value");
-                            val.push(Field { name, value });
+                            value = next_or_end(iter.next(&mut *self))?
+                                .ok_or_else(|| malformed("missing hash field value"))?;
+                            val.push(Field { name, value, expire: None });
                         } else {
                             has_more = false;
                             break;
@@ -351,7 +577,7 @@ pub trait DefaultRDBParser: Read {
                     }
                     if !val.is_empty() {
                         event_handler.handle(Event::RDB(Object::Hash(Hash {
-                            key: &key,
+                            key,
                             fields: &val,
                             meta,
                         })));
@@ -359,7 +585,6 @@ pub trait DefaultRDBParser: Read {
                 }
             }
             RDB_TYPE_HASH_ZIPMAP => {
-                let key = self.read_string()?;
                 let bytes = self.read_string()?;
                 let cursor = &mut Cursor::new(&bytes);
                 cursor.set_position(1);
@@ -372,7 +597,7 @@ pub trait DefaultRDBParser: Read {
                 while has_more {
                     let mut fields = Vec::new();
                     for _ in 0..BATCH_SIZE {
-                        if let Ok(field) = iter.next() {
+                        if let Some(field) = next_or_end(iter.next())? {
                             fields.push(field);
                         } else {
                             has_more = false;
@@ -381,7 +606,7 @@ pub trait DefaultRDBParser: Read {
                     }
                     if !fields.is_empty() {
                         event_handler.handle(Event::RDB(Object::Hash(Hash {
-                            key: &key,
+                            key,
                             fields: &fields,
                             meta,
                         })));
@@ -389,7 +614,6 @@ pub trait DefaultRDBParser: Read {
                 }
             }
             RDB_TYPE_LIST_ZIPLIST => {
-                let key = self.read_string()?;
                 let bytes = self.read_string()?;
                 let cursor = &mut Cursor::new(bytes);
                 // 跳过ZL_BYTES和ZL_TAIL
@@ -401,7 +625,7 @@ pub trait DefaultRDBParser: Read {
                 while has_more {
                     let mut val = Vec::new();
                     for _ in 0..BATCH_SIZE {
-                        if let Ok(next_val) = iter.next(&mut *self) {
+                        if let Some(next_val) = next_or_end(iter.next(&mut *self))? {
                             val.push(next_val);
                         } else {
                             has_more = false;
@@ -410,7 +634,7 @@ pub trait DefaultRDBParser: Read {
                     }
                     if !val.is_empty() {
                         event_handler.handle(Event::RDB(Object::List(List {
-                            key: &key,
+                            key,
                             values: &val,
                             meta,
                         })));
@@ -418,7 +642,6 @@ pub trait DefaultRDBParser: Read {
                 }
             }
             RDB_TYPE_HASH_ZIPLIST => {
-                let key = self.read_string()?;
                 let bytes = self.read_string()?;
                 let cursor = &mut Cursor::new(bytes);
                 // 跳过ZL_BYTES和ZL_TAIL
@@ -432,10 +655,11 @@ pub trait DefaultRDBParser: Read {
                     for _ in 0..BATCH_SIZE {
                         let name;
                         let value;
-                        if let Ok(next_val) = iter.next(&mut *self) {
+                        if let Some(next_val) = next_or_end(iter.next(&mut *self))? {
                             name = next_val;
-                            value = iter.next(&mut *self).expect("missing hash field value");
-                            val.push(Field { name, value });
+                            value = next_or_end(iter.next(&mut *self))?
+                                .ok_or_else(|| malformed("missing hash field value"))?;
+                            val.push(Field { name, value, expire: None });
                         } else {
                             has_more = false;
                             break;
@@ -443,7 +667,7 @@ pub trait DefaultRDBParser: Read {
                     }
                     if !val.is_empty() {
                         event_handler.handle(Event::RDB(Object::Hash(Hash {
-                            key: &key,
+                            key,
                             fields: &val,
                             meta,
                         })));
@@ -451,7 +675,6 @@ pub trait DefaultRDBParser: Read {
                 }
             }
             RDB_TYPE_ZSET_ZIPLIST => {
-                let key = self.read_string()?;
                 let bytes = self.read_string()?;
                 let cursor = &mut Cursor::new(bytes);
                 // 跳过ZL_BYTES和ZL_TAIL
@@ -465,13 +688,15 @@ pub trait DefaultRDBParser: Read {
                     for _ in 0..BATCH_SIZE {
                         let member;
                         let score: f64;
-                        if let Ok(next_val) = iter.next(&mut *self) {
+                        if let Some(next_val) = next_or_end(iter.next(&mut *self))? {
                             member = next_val;
                             let score_str = to_string(
-                                iter.next(&mut *self)
-                                    .expect("missing sorted set element's score"),
+                                next_or_end(iter.next(&mut *self))?
+                                    .ok_or_else(|| malformed("missing sorted set element's score"))?,
                             );
-                            score = score_str.parse::<f64>().unwrap();
+                            score = score_str
+                                .parse::<f64>()
+                                .map_err(|_| malformed(format!("invalid score: {}", score_str)))?;
                             val.push(Item { member, score });
                         } else {
                             has_more = false;
@@ -480,7 +705,7 @@ pub trait DefaultRDBParser: Read {
                     }
                     if !val.is_empty() {
                         event_handler.handle(Event::RDB(Object::SortedSet(SortedSet {
-                            key: &key,
+                            key,
                             items: &val,
                             meta,
                         })));
@@ -488,7 +713,6 @@ pub trait DefaultRDBParser: Read {
                 }
             }
             RDB_TYPE_SET_INTSET => {
-                let key = self.read_string()?;
                 let bytes = self.read_string()?;
                 let mut cursor = Cursor::new(&bytes);
                 let encoding = cursor.read_i32::<LittleEndian>()?;
@@ -503,7 +727,7 @@ pub trait DefaultRDBParser: Read {
                 while has_more {
                     let mut val = Vec::new();
                     for _ in 0..BATCH_SIZE {
-                        if let Ok(next_val) = iter.next(&mut *self) {
+                        if let Some(next_val) = next_or_end(iter.next(&mut *self))? {
                             val.push(next_val);
                         } else {
                             has_more = false;
@@ -512,7 +736,7 @@ pub trait DefaultRDBParser: Read {
                     }
                     if !val.is_empty() {
                         event_handler.handle(Event::RDB(Object::Set(Set {
-                            key: &key,
+                            key,
                             members: &val,
                             meta,
                         })));
@@ -520,7 +744,6 @@ pub trait DefaultRDBParser: Read {
                 }
             }
             RDB_TYPE_LIST_QUICKLIST => {
-                let key = self.read_string()?;
                 let (count, _) = self.read_length()?;
                 let mut iter = QuickListIter {
                     len: -1,
@@ -532,7 +755,7 @@ pub trait DefaultRDBParser: Read {
                 while has_more {
                     let mut val = Vec::new();
                     for _ in 0..BATCH_SIZE {
-                        if let Ok(next_val) = iter.next(&mut *self) {
+                        if let Some(next_val) = next_or_end(iter.next(&mut *self))? {
                             val.push(next_val);
                         } else {
                             has_more = false;
@@ -541,7 +764,7 @@ pub trait DefaultRDBParser: Read {
                     }
                     if !val.is_empty() {
                         event_handler.handle(Event::RDB(Object::List(List {
-                            key: &key,
+                            key,
                             values: &val,
                             meta,
                         })));
@@ -549,7 +772,6 @@ pub trait DefaultRDBParser: Read {
                 }
             }
             RDB_TYPE_MODULE | RDB_TYPE_MODULE_2 => {
-                let _key = self.read_string()?;
                 let (module_id, _) = self.read_length()?;
                 let module_id = module_id as usize;
                 let mut array: [char; 9] = [' '; 9];
@@ -557,60 +779,136 @@ pub trait DefaultRDBParser: Read {
                     let i1 = 10 + (array.len() - 1 - i) * 6;
                     let i2 = module_id >> i1;
                     let i3 = i2 & 63;
-                    let chr = MODULE_SET.get(i3).unwrap();
-                    array[i] = *chr;
+                    array[i] = MODULE_SET[i3];
                 }
-                let _module_name: String = String::from_iter(array.iter());
-                let _module_version: usize = module_id & 1023;
-                // if self.module_parser.is_none() && value_type == RDB_TYPE_MODULE {
-                //     panic!("MODULE {}, version {} 无法解析", module_name, module_version);
-                // }
-                // if let Some(parser) = &mut self.module_parser {
-                //     let module: Box<dyn Module>;
-                //     if value_type == RDB_TYPE_MODULE_2 {
-                //         module = parser.borrow_mut().parse(input, &module_name, 2);
-                //         let (len, _) = input.read_length()?;
-                //         if len != 0 {
-                //             panic!(
-                //                 "module '{}' that is not terminated by EOF marker, but {}",
-                //                 &module_name, len
-                //             );
-                //         }
-                //     } else {
-                //         module = parser.borrow_mut().parse(input, &module_name, module_version);
-                //     }
-                //     event_handler.handle(Event::RDB(Object::Module(key, module, meta)));
-                // } else {
-                //     // 没有parser，并且是Module 2类型的值，那就可以直接跳过了
-                //     self.rdb_load_check_module_value(input)?;
-                // }
+                let module_name: String = String::from_iter(array.iter());
+                let module_version: usize = module_id & 1023;
+                let module: Box<dyn Module> = match modules.get_mut(&module_name) {
+                    // Module 2类型的值先按opcode完整读出, 解析失败时不影响后续数据, 退回原始数据
+                    Some(parser) if value_type == RDB_TYPE_MODULE_2 => {
+                        let mut input = RecordReader::new(&mut *self);
+                        input.rdb_load_check_module_value()?;
+                        let data = input.into_record();
+                        let mut value = Cursor::new(&data[..]);
+                        let parsed = parser
+                            .parse(&mut value, &module_name, module_version)
+                            .and_then(|module| match value.read_length()? {
+                                (RDB_MODULE_OPCODE_EOF, _) if value.position() == data.len() as u64 => Ok(module),
+                                _ => Err(malformed("module value is not fully consumed")),
+                            });
+                        match parsed {
+                            Ok(module) => module,
+                            Err(err) => {
+                                warn!("failed to parse module '{}' version {}: {}, keep raw data", module_name, module_version, err);
+                                Box::new(RawModule {
+                                    name: module_name,
+                                    version: module_version,
+                                    data,
+                                })
+                            }
+                        }
+                    }
+                    Some(parser) => {
+                        let mut input = &mut *self;
+                        parser.parse(&mut input, &module_name, module_version)?
+                    }
+                    // 没有parser, 并且是Module 2类型的值, 可以按opcode跳过, 同时保留原始数据
+                    None if value_type == RDB_TYPE_MODULE_2 => {
+                        let mut input = RecordReader::new(&mut *self);
+                        input.rdb_load_check_module_value()?;
+                        Box::new(RawModule {
+                            name: module_name,
+                            version: module_version,
+                            data: input.into_record(),
+                        })
+                    }
+                    None => {
+                        return Err(malformed(format!(
+                            "no parser registered for module {}, version {}",
+                            module_name, module_version
+                        )))
+                    }
+                };
+                event_handler.handle(Event::RDB(Object::Module(key.to_vec(), module, meta)));
             }
             RDB_TYPE_STREAM_LISTPACKS => {
-                let key = self.read_string()?;
                 let stream = self.read_stream_list_packs(meta,RDB_TYPE_STREAM_LISTPACKS)?;
-                event_handler.handle(Event::RDB(Object::Stream(key, stream)));
+                event_handler.handle(Event::RDB(Object::Stream(key.to_vec(), stream)));
             }
-            RDB_TYPE_STREAM_LISTPACKS_2 => {
-               // println!("In>>>RDB_TYPE_STREAM_LISTPACKS_2");
-                let key = self.read_string()?;
-                let stream = self.read_stream_list_packs(meta,RDB_TYPE_STREAM_LISTPACKS_2)?;
-                event_handler.handle(Event::RDB(Object::Stream(key, stream)));
+            RDB_TYPE_STREAM_LISTPACKS_2 | RDB_TYPE_STREAM_LISTPACKS_3 => {
+                let stream = self.read_stream_list_packs(meta, value_type)?;
+                event_handler.handle(Event::RDB(Object::Stream(key.to_vec(), stream)));
             }
             RDB_TYPE_ZSET_LISTPACK => {
-                let key = self.read_string()?;
                 let items = self.read_zset_list_pack()?;
-                event_handler.handle(Event::RDB(Object::SortedSet(SortedSet { key: &key, items: &items ,  meta })));
+                event_handler.handle(Event::RDB(Object::SortedSet(SortedSet { key, items: &items ,  meta })));
             }
             RDB_TYPE_HASH_LISTPACK=>{
                 //println!("In>>>RDB_TYPE_HASH_LISTPACK");
-                let key = self.read_string()?;
                 let fields = self.read_hash_list_pack()?;
-                event_handler.handle(Event::RDB(Object::Hash(Hash { key: &key, fields:&fields ,  meta })));
+                event_handler.handle(Event::RDB(Object::Hash(Hash { key, fields:&fields ,  meta })));
             }
-            RDB_TYPE_SET_LISTPACK=>{
-                panic!("no impl")
+            RDB_TYPE_HASH_METADATA | RDB_TYPE_HASH_METADATA_PRE_GA => {
+                // GA版本中字段的过期时间存储为相对于最小过期时间的偏移, 0表示未设置
+                let mut min_expire = None;
+                if value_type == RDB_TYPE_HASH_METADATA {
+                    min_expire = Some(self.read_integer(8, false)? as i64);
+                }
+                let (count, _) = self.read_length()?;
+                let mut remaining = count.max(0) as usize;
+
+                while remaining > 0 {
+                    let batch = remaining.min(BATCH_SIZE);
+                    let mut val = Vec::with_capacity(batch);
+                    for _ in 0..batch {
+                        let (ttl, _) = self.read_length()?;
+                        let expire = match (ttl as i64, min_expire) {
+                            (0, _) => None,
+                            (ttl, Some(min_expire)) => Some(ttl + min_expire - 1),
+                            (ttl, None) => Some(ttl),
+                        };
+                        let name = self.read_string()?;
+                        let value = self.read_string()?;
+                        val.push(Field { name, value, expire });
+                    }
+                    remaining -= batch;
+                    event_handler.handle(Event::RDB(Object::Hash(Hash {
+                        key,
+                        fields: &val,
+                        meta,
+                    })));
+                }
+            }
+            RDB_TYPE_HASH_LISTPACK_EX | RDB_TYPE_HASH_LISTPACK_EX_PRE_GA => {
+                if value_type == RDB_TYPE_HASH_LISTPACK_EX {
+                    // 最小过期时间, 各字段自带过期时间, 无需使用
+                    self.read_integer(8, false)?;
+                }
+                let fields = self.read_hash_list_pack_ex()?;
+                event_handler.handle(Event::RDB(Object::Hash(Hash { key, fields: &fields, meta })));
+            }
+            RDB_TYPE_SET_LISTPACK => {
+                let bytes = self.read_string()?;
+                let mut list_pack = Cursor::new(&bytes);
+                // 跳过总字节数
+                list_pack.set_position(4);
+                let mut remaining = list_pack.read_u16::<LittleEndian>()? as usize;
+
+                while remaining > 0 {
+                    let batch = remaining.min(BATCH_SIZE);
+                    let mut val = Vec::with_capacity(batch);
+                    for _ in 0..batch {
+                        val.push(read_list_pack_entry(&mut list_pack)?);
+                    }
+                    remaining -= batch;
+                    event_handler.handle(Event::RDB(Object::Set(Set {
+                        key,
+                        members: &val,
+                        meta,
+                    })));
+                }
             }
-            _ => panic!("unknown data type: {}", value_type),
+            _ => return Err(malformed(format!("unknown value type: {}", value_type))),
         }
         Ok(())
     }
@@ -629,6 +927,8 @@ pub trait DefaultRDBParser: Read {
                 self.read_exact(&mut [0; 4])?;
             } else if op_code == RDB_MODULE_OPCODE_DOUBLE {
                 self.read_exact(&mut [0; 8])?;
+            } else {
+                return Err(malformed(format!("unknown module opcode: {}", op_code)));
             }
         }
         Ok(())
@@ -640,11 +940,12 @@ pub trait DefaultRDBParser: Read {
         
         let mut list_pack = Cursor::new(&raw_list_packs);
         list_pack.set_position(4);
-        let length = list_pack.read_i16::<LittleEndian>().expect("read_i16 err");
+        let length = list_pack.read_i16::<LittleEndian>()?;
         let mut re = vec![];
          for _ in 0..length/2{
              let member = read_list_pack_entry(&mut list_pack)?;
-             let score =  f64::from_str(&to_string(read_list_pack_entry(&mut list_pack)?)).expect("parse err");
+             let score = to_string(read_list_pack_entry(&mut list_pack)?);
+             let score = f64::from_str(&score).map_err(|_| malformed(format!("invalid score: {}", score)))?;
             re.push(Item{member,score });
          };
         Ok(re)
@@ -654,17 +955,34 @@ pub trait DefaultRDBParser: Read {
         let raw_list_packs = self.read_string()?;
         let mut list_pack = Cursor::new(&raw_list_packs);
         list_pack.set_position(4);
-        let length = list_pack.read_i16::<LittleEndian>().expect("read_i16 err");
+        let length = list_pack.read_i16::<LittleEndian>()?;
         let mut re = vec![];
 
          for _ in 0..length/2{
              let name = read_list_pack_entry(&mut list_pack)?;
              let value =  read_list_pack_entry(&mut list_pack)?;
-             re.push(Field { name , value })
+             re.push(Field { name , value, expire: None })
          };
         Ok(re)
     }
 
+    /// 读取带过期时间的hash listpack, 每个字段依次为: 字段名, 字段值, 过期时间(0表示未设置)
+    fn read_hash_list_pack_ex(&mut self) -> Result<Vec<Field>> {
+        let raw_list_packs = self.read_string()?;
+        let mut list_pack = Cursor::new(&raw_list_packs);
+        list_pack.set_position(4);
+        let length = list_pack.read_u16::<LittleEndian>()?;
+        let mut re = Vec::with_capacity(length as usize / 3);
+        for _ in 0..length / 3 {
+            let name = read_list_pack_entry(&mut list_pack)?;
+            let value = read_list_pack_entry(&mut list_pack)?;
+            let ttl = read_list_pack_int::<i64, _>(&mut list_pack)?;
+            let expire = if ttl == 0 { None } else { Some(ttl) };
+            re.push(Field { name, value, expire });
+        }
+        Ok(re)
+    }
+
     fn read_stream_list_packs<'a>(&mut self, _meta: &'a Meta,version: u8 ) -> Result<Stream<'a>> {
         let mut entries: BTreeMap<ID, Entry> = BTreeMap::new();
         let (length, _) = self.read_length()?;
@@ -678,32 +996,37 @@ pub trait DefaultRDBParser: Read {
             let raw_list_packs = self.read_string()?;
             let mut list_pack = Cursor::new(&raw_list_packs);
             list_pack.set_position(6);
-            let count = i64::from_str(&to_string(read_list_pack_entry(&mut list_pack)?)).unwrap();
-            let deleted = i64::from_str(&to_string(read_list_pack_entry(&mut list_pack)?)).unwrap();
-            let num_fields =
-                i32::from_str(&to_string(read_list_pack_entry(&mut list_pack)?)).unwrap();
-            let mut tmp_fields = Vec::with_capacity(num_fields as usize);
+            let count = read_list_pack_int::<u64, _>(&mut list_pack)?;
+            let deleted = read_list_pack_int::<u64, _>(&mut list_pack)?;
+            let num_fields = read_list_pack_int::<u32, _>(&mut list_pack)?;
+            // 以上个数均来自输入, 只在实际读到元素时分配内存
+            let mut tmp_fields = Vec::new();
             for _ in 0..num_fields {
                 tmp_fields.push(read_list_pack_entry(&mut list_pack)?);
             }
             read_list_pack_entry(&mut list_pack)?;
 
-            let total = count + deleted;
+            let total = count
+                .checked_add(deleted)
+                .ok_or_else(|| malformed(format!("invalid stream entry count: {} + {}", count, deleted)))?;
             for _ in 0..total {
                 let mut fields = BTreeMap::new();
                 let flag =
-                    i32::from_str(&to_string(read_list_pack_entry(&mut list_pack)?)).unwrap();
-                let ms = i64::from_str(&to_string(read_list_pack_entry(&mut list_pack)?)).unwrap();
-                let seq = i64::from_str(&to_string(read_list_pack_entry(&mut list_pack)?)).unwrap();
+                    read_list_pack_int::<i32, _>(&mut list_pack)?;
+                let ms = read_list_pack_int::<i64, _>(&mut list_pack)?;
+                let seq = read_list_pack_int::<i64, _>(&mut list_pack)?;
                 let id = ID {
-                    ms: ms + base_id.ms,
-                    seq: seq + base_id.seq,
+                    ms: ms.wrapping_add(base_id.ms),
+                    seq: seq.wrapping_add(base_id.seq),
                 };
                 let deleted = (flag & 1) != 0;
                 if (flag & 2) != 0 {
                     for i in 0..num_fields {
                         let value = read_list_pack_entry(&mut list_pack)?;
-                        let field = tmp_fields.get(i as usize).unwrap().to_vec();
+                        let field = tmp_fields
+                            .get(i as usize)
+                            .ok_or_else(|| malformed("stream entry has more values than master fields"))?
+                            .to_vec();
                         fields.insert(field, value);
                     }
                     entries.insert(
@@ -716,7 +1039,7 @@ pub trait DefaultRDBParser: Read {
                     );
                 } else {
                     let num_fields =
-                        i32::from_str(&to_string(read_list_pack_entry(&mut list_pack)?)).unwrap();
+                        read_list_pack_int::<i32, _>(&mut list_pack)?;
                     for _ in 0..num_fields {
                         let field = read_list_pack_entry(&mut list_pack)?;
                         let value = read_list_pack_entry(&mut list_pack)?;
@@ -735,7 +1058,7 @@ pub trait DefaultRDBParser: Read {
             }
             let end = list_pack.read_u8()?;
             if end != 255 {
-                panic!("listpack expect 255 but {}", end);
+                return Err(malformed(format!("listpack expect 255 but {}", end)));
             }
         }
         //stream len
@@ -744,7 +1067,7 @@ pub trait DefaultRDBParser: Read {
         let mut first_id= None;
         let mut max_deleted_id =None;
         let mut added_count= None;
-        if version == RDB_TYPE_STREAM_LISTPACKS_2{
+        if version >= RDB_TYPE_STREAM_LISTPACKS_2{
              first_id = Some(ID{ms:self.read_length()?.0 as i64,seq: self.read_length()?.0 as i64 });
             max_deleted_id = Some(ID{ms:self.read_length()?.0 as i64,seq: self.read_length()?.0 as i64 });
 added_count = Some(self.read_length()?.0 as u64);
@@ -763,34 +1086,52 @@ added_count = Some(self.read_length()?.0 as u64);
                 seq: seq as i64,
             };
             let mut entries_read=  None;
-            if version == RDB_TYPE_STREAM_LISTPACKS_2 {
+            if version >= RDB_TYPE_STREAM_LISTPACKS_2 {
                 entries_read = Some(self.read_length()?.0 as u64);
             }
-            groups.push(Group {
-                name,
-                last_id: group_last_id,
-                entries_read
-            });
 
-            let (global_pel, _) = self.read_length()?;
+            let global_pel = self.read_len()?;
+            let mut pending = Vec::new();
             for _ in 0..global_pel {
-                self.read_long(8, false)?;
-                self.read_long(8, false)?;
-                self.read_integer(8, false)?;
-                self.read_length()?;
+                let id = self.read_stream_id()?;
+                let delivery_time = self.read_integer(8, false)? as i64;
+                let delivery_count = self.read_length()?.0 as u64;
+                pending.push(PendingEntry {
+                    id,
+                    delivery_time,
+                    delivery_count,
+                });
             }
 
-            let (consumer_count, _) = self.read_length()?;
+            let consumer_count = self.read_len()?;
+            let mut consumers = Vec::new();
             for _ in 0..consumer_count {
-                self.read_string()?;
-                self.read_integer(8, false)?;
+                let name = self.read_string()?;
+                let seen_time = self.read_integer(8, false)? as i64;
+                let mut active_time = None;
+                if version >= RDB_TYPE_STREAM_LISTPACKS_3 {
+                    active_time = Some(self.read_integer(8, false)? as i64);
+                }
 
-                let (pel, _) = self.read_length()?;
+                let pel = self.read_len()?;
+                let mut pending = Vec::new();
                 for _ in 0..pel {
-                    self.read_long(8, false)?;
-                    self.read_long(8, false)?;
+                    pending.push(self.read_stream_id()?);
                 }
+                consumers.push(Consumer {
+                    name,
+                    seen_time,
+                    active_time,
+                    pending,
+                });
             }
+            groups.push(Group {
+                name,
+                last_id: group_last_id,
+                entries_read,
+                pending,
+                consumers,
+            });
         }
         Ok(Stream {
             entries,
@@ -804,6 +1145,13 @@ added_count = Some(self.read_length()?.0 as u64);
         })
     }
 
+    /// 读取PEL中以16字节大端存储的消息ID
+    fn read_stream_id(&mut self) -> Result<ID> {
+        let ms = self.read_long(8, false)?;
+        let seq = self.read_long(8, false)?;
+        Ok(ID { ms, seq })
+    }
+
     fn read_long(&mut self, length: i32, little_endian: bool) -> Result<i64> {
         let mut r: i64 = 0;
         for i in 0..length {
@@ -834,7 +1182,7 @@ fn read_long(input: &mut dyn Read, length: i32, little_endian: bool) -> Result<i
 fn read_list_pack_entry<T: Read>(mut input:  T) -> Result<Vec<u8>> {
     let special = input.read_u8()? as i32;
     let skip: i32;
-    let mut bytes;
+    let bytes;
     if (special & 0x80) == 0 {
         skip = 1;
         let value = special & 0x7F;
@@ -843,8 +1191,7 @@ fn read_list_pack_entry<T: Read>(mut input:  T) -> Result<Vec<u8>> {
     } else if (special & 0xC0) == 0x80 {
         let len = special & 0x3F;
         skip = 1 + len;
-        bytes = vec![0; len as usize];
-        input.read_exact(&mut bytes)?;
+        bytes = read_bytes(&mut input, len as usize)?;
     } else if (special & 0xE0) == 0xC0 {
         skip = 2;
         let next = input.read_u8()?;
@@ -875,15 +1222,13 @@ fn read_list_pack_entry<T: Read>(mut input:  T) -> Result<Vec<u8>> {
         let next = input.read_u8()?;
         let len = ((special & 0x0F) << 8) | next as i32;
         skip = 2 + len;
-        bytes = vec![0; len as usize];
-        input.read_exact(&mut bytes)?;
+        bytes = read_bytes(&mut input, len as usize)?;
     } else if (special & 0xFF) == 0xF0 {
         let len = input.read_u32::<BigEndian>()?;
-        skip = 5 + len as i32;
-        bytes = vec![0; len as usize];
-        input.read_exact(&mut bytes)?;
+        skip = 5i32.saturating_add_unsigned(len);
+        bytes = read_bytes(&mut input, len as usize)?;
     } else {
-        panic!("{}", special)
+        return Err(malformed(format!("invalid listpack entry encoding: {}", special)));
     }
     if skip <= 127 {
         let mut buf = vec![0; 1];
@@ -923,22 +1268,16 @@ pub(crate) fn read_zip_list_entry(cursor: &mut Cursor<Vec<u8>>) -> Result<Vec<u8
     match flag >> 6 {
         0 => {
             let length = flag & 0x3F;
-            let mut buff = vec![0; length as usize];
-            cursor.read_exact(&mut buff)?;
-            return Ok(buff);
+            return read_bytes(cursor, length as usize);
         }
         1 => {
             let next_byte = cursor.read_u8()?;
             let length = (((flag as u16) & 0x3F) << 8) | (next_byte as u16);
-            let mut buff = vec![0; length as usize];
-            cursor.read_exact(&mut buff)?;
-            return Ok(buff);
+            return read_bytes(cursor, length as usize);
         }
         2 => {
             let length = cursor.read_u32::<BigEndian>()?;
-            let mut buff = vec![0; length as usize];
-            cursor.read_exact(&mut buff)?;
-            return Ok(buff);
+            return read_bytes(cursor, length as usize);
         }
         _ => {}
     }
@@ -971,6 +1310,53 @@ pub(crate) fn read_zip_list_entry(cursor: &mut Cursor<Vec<u8>>) -> Result<Vec<u8
 }
 
 impl<R: Read + ?Sized> RDBDecode for R {}
+
+/// 按`RDB_TYPE_MODULE_2`的格式读取module值, 与Redis module API中的`RedisModule_Load*`一一对应
+///
+/// 每个值之前都有一个opcode标明其类型, opcode不符时返回错误
+pub trait ModuleDecode: Read {
+    /// 对应`RedisModule_LoadUnsigned`
+    fn load_unsigned(&mut self) -> Result<u64> {
+        self.expect_module_opcode(RDB_MODULE_OPCODE_UINT)?;
+        let (value, _) = self.read_length()?;
+        Ok(value as u64)
+    }
+
+    /// 对应`RedisModule_LoadSigned`
+    fn load_signed(&mut self) -> Result<i64> {
+        self.expect_module_opcode(RDB_MODULE_OPCODE_SINT)?;
+        let (value, _) = self.read_length()?;
+        Ok(value as i64)
+    }
+
+    /// 对应`RedisModule_LoadFloat`
+    fn load_float(&mut self) -> Result<f32> {
+        self.expect_module_opcode(RDB_MODULE_OPCODE_FLOAT)?;
+        self.read_f32::<LittleEndian>()
+    }
+
+    /// 对应`RedisModule_LoadDouble`
+    fn load_double(&mut self) -> Result<f64> {
+        self.expect_module_opcode(RDB_MODULE_OPCODE_DOUBLE)?;
+        self.read_f64::<LittleEndian>()
+    }
+
+    /// 对应`RedisModule_LoadString`及`RedisModule_LoadStringBuffer`
+    fn load_string(&mut self) -> Result<Vec<u8>> {
+        self.expect_module_opcode(RDB_MODULE_OPCODE_STRING)?;
+        self.read_string()
+    }
+
+    fn expect_module_opcode(&mut self, expected: isize) -> Result<()> {
+        let (op_code, _) = self.read_length()?;
+        if op_code != expected {
+            return Err(malformed(format!("expect module opcode {}, but {}", expected, op_code)));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + ?Sized> ModuleDecode for R {}
 impl<R: Read + ?Sized> RDBParser for R {}
 impl<R: Read + ?Sized> DefaultRDBParser for R {}
 
@@ -991,19 +1377,121 @@ pub enum Object<'a> {
     Module(Vec<u8>, Box<dyn Module>, &'a Meta),
     /// 代表Redis中的Stream类型数据
     Stream(Vec<u8>, Stream<'a>),
+    /// 代表Redis中的Function库
+    Function(Function<'a>),
+    /// 代表RDB中的辅助字段, 如redis-ver, repl-id, repl-offset
+    Aux(Aux<'a>),
+    /// 代表RDB中当前db的key数量
+    ResizeDB(ResizeDB),
+    /// 代表集群模式下RDB中一个slot的key数量
+    SlotInfo(SlotInfo),
     /// 代表rdb数据解析开始
     BOR,
     /// 代表rdb数据解析完毕
     EOR,
 }
 
+/// Function库, 可通过`FUNCTION LOAD REPLACE`加载到目标端
+#[derive(Debug)]
+pub struct Function<'a> {
+    /// 库的源码, 以`#!<engine> name=<library>`开头
+    pub code: &'a [u8],
+}
+
+/// RDB的辅助字段, 常见的有:
+///
+/// * `redis-ver`: 生成RDB的Redis版本
+/// * `redis-bits`: 32位或64位
+/// * `ctime`: RDB的生成时间, 秒
+/// * `used-mem`: 生成RDB时使用的内存
+/// * `repl-id`, `repl-offset`: RDB对应的复制id及偏移量, 可用于之后的PSYNC
+/// * `aof-base`: 是否为AOF的base文件
+#[derive(Debug)]
+pub struct Aux<'a> {
+    pub field: &'a [u8],
+    pub value: &'a [u8],
+}
+
+impl Aux<'_> {
+    /// `repl-offset`字段的值, 其他字段或无法解析时返回`None`
+    pub fn repl_offset(&self) -> Option<i64> {
+        if self.field != b"repl-offset" {
+            return None;
+        }
+        std::str::from_utf8(self.value).ok()?.parse().ok()
+    }
+}
+
+/// 当前db的key数量, 出现在该db的数据之前, 可用于预分配空间或展示进度
+#[derive(Debug, Clone, Copy)]
+pub struct ResizeDB {
+    pub db: isize,
+    /// key的总数
+    pub total: usize,
+    /// 设置了过期时间的key数量
+    pub expires: usize,
+}
+
+/// 集群模式下一个slot的key数量, 出现在该slot的数据之前
+#[derive(Debug, Clone, Copy)]
+pub struct SlotInfo {
+    pub slot: usize,
+    /// key的总数
+    pub size: usize,
+    /// 设置了过期时间的key数量
+    pub expires: usize,
+}
+
 pub trait Module {
     fn as_any(&self) -> &dyn Any;
 }
 
 impl Debug for dyn Module {
-    fn fmt(&self, _: &mut Formatter) -> result::Result<(), Error> {
-        unimplemented!()
+    fn fmt(&self, f: &mut Formatter) -> result::Result<(), Error> {
+        match self.as_any().downcast_ref::<RawModule>() {
+            Some(raw) => raw.fmt(f),
+            None => f.write_str("Module"),
+        }
+    }
+}
+
+/// 没有注册[ModuleParser]的`RDB_TYPE_MODULE_2`值, 保留其原始数据
+///
+/// [ModuleParser]: ../trait.ModuleParser.html
+#[derive(Debug)]
+pub struct RawModule {
+    pub name: String,
+    pub version: usize,
+    /// module id之后的全部原始数据, 包括末尾的EOF标记
+    pub data: Vec<u8>,
+}
+
+impl Module for RawModule {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// 按module名称注册的[ModuleParser]
+///
+/// [ModuleParser]: ../trait.ModuleParser.html
+#[derive(Default)]
+pub struct ModuleRegistry {
+    parsers: HashMap<String, Box<dyn ModuleParser + Send>>,
+}
+
+impl ModuleRegistry {
+    pub fn new() -> ModuleRegistry {
+        ModuleRegistry::default()
+    }
+
+    /// 注册`module_name`的解析器, 同名的解析器会被替换
+    pub fn register(&mut self, module_name: &str, parser: Box<dyn ModuleParser + Send>) {
+        self.parsers.insert(module_name.to_string(), parser);
+    }
+
+    pub(crate) fn get_mut(&mut self, module_name: &str) -> Option<&mut Box<dyn ModuleParser + Send>> {
+        self.parsers.get_mut(module_name)
     }
 }
 
@@ -1081,7 +1569,7 @@ pub struct SortedSet<'a> {
 }
 
 /// SortedSet中的一条元素
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Item {
     /// 元素值
     pub member: Vec<u8>,
@@ -1101,12 +1589,14 @@ pub struct Hash<'a> {
 }
 
 /// Hash类型数据中的一个字段
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Field {
     /// 字段名
     pub name: Vec<u8>,
     /// 字段值
     pub value: Vec<u8>,
+    /// 字段的过期时间, unix毫秒, 未设置时为`None`
+    pub expire: Option<i64>,
 }
 
 #[derive(Debug)]
@@ -1204,7 +1694,32 @@ pub struct Entry {
 pub struct Group {
     pub name: Vec<u8>,
     pub last_id: ID,
-    pub entries_read: Option<u64>
+    pub entries_read: Option<u64>,
+    /// 已投递但尚未确认的消息
+    pub pending: Vec<PendingEntry>,
+    /// 组内的消费者
+    pub consumers: Vec<Consumer>,
+}
+
+/// 消费组中已投递但尚未确认的消息
+#[derive(Debug)]
+pub struct PendingEntry {
+    pub id: ID,
+    /// 最近一次投递的时间, unix毫秒
+    pub delivery_time: i64,
+    /// 已投递的次数
+    pub delivery_count: u64,
+}
+
+#[derive(Debug)]
+pub struct Consumer {
+    pub name: Vec<u8>,
+    /// 最近一次尝试读取的时间, unix毫秒
+    pub seen_time: i64,
+    /// 最近一次成功读取的时间, unix毫秒, Redis 7.2之前的RDB中没有此字段
+    pub active_time: Option<i64>,
+    /// 此消费者持有的待确认消息
+    pub pending: Vec<ID>,
 }
 
 /// Map object types to RDB object types.
@@ -1236,8 +1751,18 @@ pub(crate) const RDB_TYPE_LIST_QUICKLIST_2: u8 = 18;
 pub(crate) const RDB_TYPE_STREAM_LISTPACKS_2: u8 = 19;
 pub(crate) const RDB_TYPE_SET_LISTPACK: u8 = 20;
 pub(crate) const RDB_TYPE_STREAM_LISTPACKS_3: u8 = 21;
+pub(crate) const RDB_TYPE_HASH_METADATA_PRE_GA: u8 = 22;
+pub(crate) const RDB_TYPE_HASH_LISTPACK_EX_PRE_GA: u8 = 23;
+pub(crate) const RDB_TYPE_HASH_METADATA: u8 = 24;
+pub(crate) const RDB_TYPE_HASH_LISTPACK_EX: u8 = 25;
 /// Special RDB opcodes
 ///
+// Slot info, 集群模式下每个slot的key数量, Redis 7.4新增.
+pub(crate) const RDB_OPCODE_SLOT_INFO: u8 = 244;
+// Function library data, Redis 7.0 GA及之后的格式.
+pub(crate) const RDB_OPCODE_FUNCTION2: u8 = 245;
+// Function library data, Redis 7.0 rc版本的格式.
+pub(crate) const RDB_OPCODE_FUNCTION_PRE_GA: u8 = 246;
 // Module auxiliary data.
 pub(crate) const RDB_OPCODE_MODULE_AUX: u8 = 247;
 // LRU idle time.
@@ -156,7 +156,7 @@ mod test {
                             _ => eprintln!("wrong type"),
                         }
                     }
-                    assert!(b"SELECT".eq(data.get(0).unwrap().as_slice()));
+                    assert!(b"SELECT".eq(data.first().unwrap().as_slice()));
                     assert!(b"0".eq(data.get(1).unwrap().as_slice()));
                 }
                 _ => eprintln!("wrong type"),
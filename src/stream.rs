@@ -0,0 +1,139 @@
+/*!
+与master之间的连接
+
+根据[Config]中的`is_tls_enabled`决定使用明文TCP还是TLS, 两者都实现了`Read`与`Write`,
+`Connect`与`RDBParser`无需关心底层的连接类型。
+
+[Config]: ../config/struct.Config.html
+*/
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+
+use anyhow::{anyhow, Result};
+use native_tls::{Identity, TlsConnector, TlsStream};
+
+use crate::config::Config;
+
+pub(crate) enum Stream {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Stream {
+    /// 连接master, 开启TLS时在TCP连接建立后完成TLS握手
+    pub(crate) fn connect(config: &Config) -> Result<Stream> {
+        let tcp = TcpStream::connect((config.host.as_str(), config.port))?;
+        tcp.set_read_timeout(config.read_timeout)?;
+        tcp.set_write_timeout(config.write_timeout)?;
+        if !config.is_tls_enabled {
+            return Ok(Stream::Tcp(tcp));
+        }
+        let connector = tls_connector(config)?;
+        let tls = connector
+            .connect(&config.host, tcp)
+            .map_err(|err| anyhow!("tls handshake err: {}", err))?;
+        Ok(Stream::Tls(Box::new(tls)))
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp().local_addr()
+    }
+
+    pub(crate) fn tcp(&self) -> &TcpStream {
+        match self {
+            Stream::Tcp(tcp) => tcp,
+            Stream::Tls(tls) => tls.get_ref(),
+        }
+    }
+}
+
+/// 根据配置创建TLS连接器
+///
+/// * `identity`: PKCS#12格式的客户端证书文件, 用于双向认证, 密码为`identity_passwd`
+/// * `is_tls_insecure`: 不校验master的证书与主机名
+pub(crate) fn tls_connector(config: &Config) -> Result<TlsConnector> {
+    let mut builder = TlsConnector::builder();
+    if let Some(path) = &config.identity {
+        let der = fs::read(path).map_err(|err| anyhow!("read identity {} err: {}", path, err))?;
+        let passwd = config.identity_passwd.as_deref().unwrap_or("");
+        let identity = Identity::from_pkcs12(&der, passwd)?;
+        builder.identity(identity);
+    }
+    if config.is_tls_insecure {
+        builder.danger_accept_invalid_certs(true);
+        builder.danger_accept_invalid_hostnames(true);
+    }
+    Ok(builder.build()?)
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(tcp) => tcp.read(buf),
+            Stream::Tls(tls) => tls.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(tcp) => tcp.write(buf),
+            Stream::Tls(tls) => tls.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(tcp) => tcp.flush(),
+            Stream::Tls(tls) => tls.flush(),
+        }
+    }
+}
+
+/// 带读缓冲的连接, 写操作直接作用于底层连接
+///
+/// TLS连接无法像`TcpStream::try_clone`那样拆分读写, 读写都经由同一个对象完成
+pub(crate) struct BufStream<S: Read + Write> {
+    inner: BufReader<S>,
+}
+
+impl<S: Read + Write> BufStream<S> {
+    pub(crate) fn new(stream: S) -> BufStream<S> {
+        BufStream {
+            inner: BufReader::new(stream),
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &S {
+        self.inner.get_ref()
+    }
+}
+
+impl<S: Read + Write> Read for BufStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Read + Write> BufRead for BufStream<S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+impl<S: Read + Write> Write for BufStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.get_mut().flush()
+    }
+}
@@ -0,0 +1,969 @@
+/*!
+RDB的写入
+
+[RDBWriter]接收与[RDBParser]相同的[Object]事件, 将其重新序列化为合法的RDB文件,
+可用于过滤、转换或合并快照后, 直接交给另一个Redis加载。
+
+[RDBWriter]: struct.RDBWriter.html
+[RDBParser]: ../rdb/trait.RDBParser.html
+[Object]: ../rdb/enum.Object.html
+*/
+
+use std::io::{self, ErrorKind, Result, Write};
+
+use byteorder::{BigEndian, LittleEndian, WriteBytesExt};
+use log::warn;
+
+use crate::dump::OwnedObject;
+use crate::io::Crc64Writer;
+use crate::lzf;
+use crate::rdb::*;
+use crate::{Event, EventHandler};
+
+/// 支持写入的最低RDB版本, 对应Redis 5.0
+pub const MIN_RDB_VERSION: u8 = 9;
+/// 支持写入的最高RDB版本, 对应Redis 7.4
+pub const MAX_RDB_VERSION: u8 = 12;
+
+/// 紧凑编码的阈值, 与Redis的默认配置一致, 超出时Redis加载后也会转换为普通编码
+const LIST_PACK_MAX_ENTRIES: usize = 128;
+const LIST_PACK_MAX_VALUE: usize = 64;
+const INT_SET_MAX_ENTRIES: usize = 512;
+/// 每个stream节点中的最大消息数, 与`stream-node-max-entries`的默认值一致
+const STREAM_NODE_MAX_ENTRIES: usize = 100;
+/// 小于此长度的字符串不压缩
+const LZF_MIN_LENGTH: usize = 20;
+
+const STREAM_ITEM_FLAG_DELETED: i64 = 1;
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+pub struct WriteOptions {
+    /// 目标RDB版本, 决定可用的数据类型, 需要不高于目标Redis支持的版本
+    pub version: u8,
+    /// 较小的set, sorted set, hash使用intset/listpack编码, list始终使用普通编码
+    pub compact: bool,
+    /// 使用lzf压缩较长的字符串, 对应`rdbcompression yes`
+    pub compress: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            version: 11,
+            compact: false,
+            compress: false,
+        }
+    }
+}
+
+/// 按RDB的格式写入长度、字符串等基础数据, 与[RDBDecode]对应
+///
+/// [RDBDecode]: ../rdb/trait.RDBDecode.html
+pub trait RDBEncode: Write {
+    fn write_length(&mut self, length: u64) -> Result<()> {
+        if length < 1 << 6 {
+            self.write_u8(length as u8)
+        } else if length < 1 << 14 {
+            self.write_u16::<BigEndian>(length as u16 | 0x4000)
+        } else if length <= u32::MAX as u64 {
+            self.write_u8(RDB_32BITLEN)?;
+            self.write_u32::<BigEndian>(length as u32)
+        } else {
+            self.write_u8(RDB_64BITLEN)?;
+            self.write_u64::<BigEndian>(length)
+        }
+    }
+
+    /// 写入字符串, 可以表示为32位整数的字符串使用整数编码
+    fn write_string(&mut self, value: &[u8]) -> Result<()> {
+        match to_int(value) {
+            Some(int) if int >= i8::MIN as i64 && int <= i8::MAX as i64 => {
+                self.write_u8(0xC0 | RDB_ENC_INT8 as u8)?;
+                self.write_i8(int as i8)
+            }
+            Some(int) if int >= i16::MIN as i64 && int <= i16::MAX as i64 => {
+                self.write_u8(0xC0 | RDB_ENC_INT16 as u8)?;
+                self.write_i16::<LittleEndian>(int as i16)
+            }
+            Some(int) if int >= i32::MIN as i64 && int <= i32::MAX as i64 => {
+                self.write_u8(0xC0 | RDB_ENC_INT32 as u8)?;
+                self.write_i32::<LittleEndian>(int as i32)
+            }
+            _ => {
+                self.write_length(value.len() as u64)?;
+                self.write_all(value)
+            }
+        }
+    }
+
+    /// 写入字符串, 较长且压缩后至少节省4个字节时使用lzf压缩, 与Redis的rdbSaveLzfStringObject一致
+    fn write_lzf_string(&mut self, value: &[u8]) -> Result<()> {
+        if value.len() > LZF_MIN_LENGTH {
+            if let Some(compressed) = lzf::compress(value, value.len() - 4) {
+                self.write_u8(0xC0 | RDB_ENC_LZF as u8)?;
+                self.write_length(compressed.len() as u64)?;
+                self.write_length(value.len() as u64)?;
+                return self.write_all(&compressed);
+            }
+        }
+        self.write_string(value)
+    }
+
+    /// 以8字节小端写入毫秒时间戳
+    fn write_millis(&mut self, millis: i64) -> Result<()> {
+        self.write_i64::<LittleEndian>(millis)
+    }
+
+    /// 以16字节大端写入stream消息ID
+    fn write_stream_id(&mut self, id: &ID) -> Result<()> {
+        self.write_i64::<BigEndian>(id.ms)?;
+        self.write_i64::<BigEndian>(id.seq)
+    }
+}
+
+impl<W: Write + ?Sized> RDBEncode for W {}
+
+/// 可以无损地表示为i64的字符串, 比如"12", 而"012", "+1"则不能
+fn to_int(value: &[u8]) -> Option<i64> {
+    if value.is_empty() || value.len() > 20 {
+        return None;
+    }
+    let int: i64 = std::str::from_utf8(value).ok()?.parse().ok()?;
+    if int.to_string().as_bytes() == value {
+        Some(int)
+    } else {
+        None
+    }
+}
+
+fn unsupported(reason: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, reason)
+}
+
+/// 构建listpack, 格式见Redis中的listpack.c
+#[derive(Default)]
+pub(crate) struct ListPack {
+    entries: Vec<u8>,
+    count: usize,
+}
+
+impl ListPack {
+    pub(crate) fn push(&mut self, value: &[u8]) {
+        match to_int(value) {
+            Some(int) => self.push_int(int),
+            None => {
+                let start = self.entries.len();
+                let len = value.len();
+                if len < 1 << 6 {
+                    self.entries.push(0x80 | len as u8);
+                } else if len < 1 << 12 {
+                    self.entries.push(0xE0 | (len >> 8) as u8);
+                    self.entries.push(len as u8);
+                } else {
+                    self.entries.push(0xF0);
+                    self.entries.extend_from_slice(&(len as u32).to_le_bytes());
+                }
+                self.entries.extend_from_slice(value);
+                self.push_back_len(start);
+            }
+        }
+    }
+
+    pub(crate) fn push_int(&mut self, int: i64) {
+        let start = self.entries.len();
+        if (0..=127).contains(&int) {
+            self.entries.push(int as u8);
+        } else if (-4096..=4095).contains(&int) {
+            let int = int as u16 & 0x1FFF;
+            self.entries.push(0xC0 | (int >> 8) as u8);
+            self.entries.push(int as u8);
+        } else if int >= i16::MIN as i64 && int <= i16::MAX as i64 {
+            self.entries.push(0xF1);
+            self.entries.extend_from_slice(&(int as i16).to_le_bytes());
+        } else if (-(1 << 23)..1 << 23).contains(&int) {
+            self.entries.push(0xF2);
+            self.entries.extend_from_slice(&(int as i32).to_le_bytes()[..3]);
+        } else if int >= i32::MIN as i64 && int <= i32::MAX as i64 {
+            self.entries.push(0xF3);
+            self.entries.extend_from_slice(&(int as i32).to_le_bytes());
+        } else {
+            self.entries.push(0xF4);
+            self.entries.extend_from_slice(&int.to_le_bytes());
+        }
+        self.push_back_len(start);
+    }
+
+    /// 每个元素之后记录该元素的长度, 用于反向遍历, 第一个字节的最高位为0, 其余字节为1
+    fn push_back_len(&mut self, start: usize) {
+        let len = self.entries.len() - start;
+        let bytes = match len {
+            0..=127 => 1,
+            128..=16382 => 2,
+            16383..=2097150 => 3,
+            2097151..=268435454 => 4,
+            _ => 5,
+        };
+        for i in (0..bytes).rev() {
+            let byte = ((len >> (7 * i)) & 127) as u8;
+            self.entries.push(if i == bytes - 1 { byte } else { byte | 128 });
+        }
+        self.count += 1;
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        let total = 6 + self.entries.len() + 1;
+        let mut bytes = Vec::with_capacity(total);
+        bytes.extend_from_slice(&(total as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.count.min(u16::MAX as usize) as u16).to_le_bytes());
+        bytes.extend_from_slice(&self.entries);
+        bytes.push(0xFF);
+        bytes
+    }
+}
+
+/// 按Redis中`d2string`的方式格式化score, 整数不带小数部分
+fn score_to_string(score: f64) -> String {
+    if score.is_infinite() {
+        if score > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else if score.fract() == 0.0 && score.abs() < (1u64 << 53) as f64 {
+        (score as i64).to_string()
+    } else {
+        score.to_string()
+    }
+}
+
+/// 构建intset, `members`需全部为整数
+fn int_set(members: &[i64]) -> Vec<u8> {
+    let mut members = members.to_vec();
+    members.sort_unstable();
+    members.dedup();
+    let fits = |min: i64, max: i64| members.iter().all(|m| *m >= min && *m <= max);
+    let encoding: u32 = if fits(i16::MIN as i64, i16::MAX as i64) {
+        2
+    } else if fits(i32::MIN as i64, i32::MAX as i64) {
+        4
+    } else {
+        8
+    };
+    let mut bytes = Vec::with_capacity(8 + members.len() * encoding as usize);
+    bytes.extend_from_slice(&encoding.to_le_bytes());
+    bytes.extend_from_slice(&(members.len() as u32).to_le_bytes());
+    for member in members {
+        bytes.extend_from_slice(&member.to_le_bytes()[..encoding as usize]);
+    }
+    bytes
+}
+
+/// 由module名称及版本计算module id
+fn module_id(name: &str, version: usize) -> Result<u64> {
+    if name.chars().count() != 9 {
+        return Err(unsupported(format!("invalid module name: {}", name)));
+    }
+    let mut id = version as u64 & 1023;
+    for (i, chr) in name.chars().enumerate() {
+        let index = MODULE_SET
+            .iter()
+            .position(|c| *c == chr)
+            .ok_or_else(|| unsupported(format!("invalid module name: {}", name)))?;
+        id |= (index as u64) << (10 + (8 - i) * 6);
+    }
+    Ok(id)
+}
+
+/// 数据的key及元信息, 写在值之前
+struct Header {
+    key: Vec<u8>,
+    db: isize,
+    /// 过期时间, unix毫秒
+    expire: Option<i64>,
+    evict: Option<(bool, i64)>,
+}
+
+impl Header {
+    fn new(key: &[u8], meta: &Meta) -> Header {
+        Header {
+            key: key.to_vec(),
+            db: meta.db,
+            expire: meta.expire.as_ref().map(|(expire_type, expire)| match expire_type {
+                ExpireType::Second => expire * 1000,
+                ExpireType::Millisecond => *expire,
+            }),
+            evict: meta
+                .evict
+                .as_ref()
+                .map(|(evict_type, value)| (matches!(evict_type, EvictType::LFU), *value)),
+        }
+    }
+}
+
+/// 分批到达的集合类数据, 在遇到下一个key时才写入
+enum Value {
+    List(Vec<Vec<u8>>),
+    Set(Vec<Vec<u8>>),
+    SortedSet(Vec<(Vec<u8>, f64)>),
+    Hash(Vec<(Vec<u8>, Vec<u8>, Option<i64>)>),
+}
+
+impl Value {
+    /// 与`other`属于同一种类型时合并, 否则原样返回`other`
+    fn merge(&mut self, other: Value) -> Option<Value> {
+        match (self, other) {
+            (Value::List(values), Value::List(other)) => values.extend(other),
+            (Value::Set(values), Value::Set(other)) => values.extend(other),
+            (Value::SortedSet(values), Value::SortedSet(other)) => values.extend(other),
+            (Value::Hash(values), Value::Hash(other)) => values.extend(other),
+            (_, other) => return Some(other),
+        }
+        None
+    }
+}
+
+/// 将[Object]事件写为RDB文件
+///
+/// 可直接作为[EventHandler]交给[RDBParser], 写入时出现的第一个错误会在[finish]中返回。
+/// 同一个key分批产生的多个事件会合并为一条数据。
+/// `RESIZEDB`, `SLOT_INFO`中的数量在过滤或合并后不再准确, 因此不会写入。
+///
+/// [Object]: ../rdb/enum.Object.html
+/// [EventHandler]: ../trait.EventHandler.html
+/// [RDBParser]: ../rdb/trait.RDBParser.html
+/// [finish]: #method.finish
+pub struct RDBWriter<W: Write> {
+    output: Crc64Writer<W>,
+    options: WriteOptions,
+    db: Option<isize>,
+    pending: Option<(Header, Value)>,
+    error: Option<io::Error>,
+    /// 按DUMP的格式写入, 只写值的类型及值
+    dump: bool,
+}
+
+impl<W: Write> RDBWriter<W> {
+    /// 创建RDBWriter并写入RDB头部, `options.version`不在支持的范围内时返回错误
+    pub fn new(output: W, options: WriteOptions) -> Result<RDBWriter<W>> {
+        if !(MIN_RDB_VERSION..=MAX_RDB_VERSION).contains(&options.version) {
+            return Err(unsupported(format!("unsupported rdb version: {}", options.version)));
+        }
+        let mut output = Crc64Writer::new(output);
+        write!(output, "REDIS{:04}", options.version)?;
+        Ok(RDBWriter {
+            output,
+            options,
+            db: None,
+            pending: None,
+            error: None,
+            dump: false,
+        })
+    }
+
+    /// 写入一个对象, 集合类数据会暂存到遇见下一个key时才写入
+    pub fn write(&mut self, object: &Object) -> Result<()> {
+        match object {
+            Object::List(list) => self.append(list.key, list.meta, Value::List(list.values.to_vec())),
+            Object::Set(set) => self.append(set.key, set.meta, Value::Set(set.members.to_vec())),
+            Object::SortedSet(zset) => {
+                let items = zset.items.iter().map(|item| (item.member.clone(), item.score)).collect();
+                self.append(zset.key, zset.meta, Value::SortedSet(items))
+            }
+            Object::Hash(hash) => {
+                let fields = hash
+                    .fields
+                    .iter()
+                    .map(|field| (field.name.clone(), field.value.clone(), field.expire))
+                    .collect();
+                self.append(hash.key, hash.meta, Value::Hash(fields))
+            }
+            _ => {
+                self.flush_pending()?;
+                self.write_other(object)
+            }
+        }
+    }
+
+    /// 写入暂存的数据, RDB结束标记及checksum, 返回底层的Writer
+    pub fn finish(mut self) -> Result<W> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.flush_pending()?;
+        self.output.write_u8(RDB_OPCODE_EOF)?;
+        let crc = self.output.crc();
+        self.output.write_u64::<LittleEndian>(crc)?;
+        self.output.flush()?;
+        Ok(self.output.into_inner())
+    }
+
+    fn append(&mut self, key: &[u8], meta: &Meta, value: Value) -> Result<()> {
+        if let Some((header, pending)) = &mut self.pending {
+            if header.key == key && header.db == meta.db {
+                match pending.merge(value) {
+                    None => return Ok(()),
+                    Some(value) => {
+                        self.flush_pending()?;
+                        self.pending = Some((Header::new(key, meta), value));
+                        return Ok(());
+                    }
+                }
+            }
+            self.flush_pending()?;
+        }
+        self.pending = Some((Header::new(key, meta), value));
+        Ok(())
+    }
+
+    fn flush_pending(&mut self) -> Result<()> {
+        match self.pending.take() {
+            Some((header, Value::List(values))) => self.write_list(&header, &values),
+            Some((header, Value::Set(members))) => self.write_set(&header, &members),
+            Some((header, Value::SortedSet(items))) => self.write_sorted_set(&header, &items),
+            Some((header, Value::Hash(fields))) => self.write_hash(&header, &fields),
+            None => Ok(()),
+        }
+    }
+
+    fn write_other(&mut self, object: &Object) -> Result<()> {
+        match object {
+            Object::String(kv) => {
+                self.write_header(&Header::new(kv.key, kv.meta), RDB_TYPE_STRING)?;
+                self.write_str(kv.value)
+            }
+            Object::Stream(key, stream) => self.write_stream(&Header::new(key, stream.meta), stream),
+            Object::Module(key, module, meta) => self.write_module(&Header::new(key, meta), module.as_ref()),
+            Object::Function(function) => {
+                if self.options.version < 10 {
+                    return Err(unsupported(format!("functions require rdb version 10, but {}", self.options.version)));
+                }
+                self.output.write_u8(RDB_OPCODE_FUNCTION2)?;
+                self.write_str(function.code)
+            }
+            Object::Aux(aux) => {
+                self.output.write_u8(RDB_OPCODE_AUX)?;
+                self.write_str(aux.field)?;
+                self.write_str(aux.value)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn write_module(&mut self, header: &Header, module: &dyn Module) -> Result<()> {
+        let raw = module.as_any().downcast_ref::<RawModule>().ok_or_else(|| {
+            unsupported(format!("module value of {} can not be serialized", String::from_utf8_lossy(&header.key)))
+        })?;
+        let id = module_id(&raw.name, raw.version)?;
+        self.write_header(header, RDB_TYPE_MODULE_2)?;
+        self.output.write_length(id)?;
+        self.output.write_all(&raw.data)
+    }
+
+    fn write_str(&mut self, value: &[u8]) -> Result<()> {
+        if self.options.compress {
+            self.output.write_lzf_string(value)
+        } else {
+            self.output.write_string(value)
+        }
+    }
+
+    fn write_header(&mut self, header: &Header, value_type: u8) -> Result<()> {
+        if self.dump {
+            return self.output.write_u8(value_type);
+        }
+        if self.db != Some(header.db) {
+            self.output.write_u8(RDB_OPCODE_SELECTDB)?;
+            self.output.write_length(header.db as u64)?;
+            self.db = Some(header.db);
+        }
+        if let Some(expire) = header.expire {
+            self.output.write_u8(RDB_OPCODE_EXPIRETIME_MS)?;
+            self.output.write_millis(expire)?;
+        }
+        match header.evict {
+            Some((true, freq)) => {
+                self.output.write_u8(RDB_OPCODE_FREQ)?;
+                self.output.write_u8(freq as u8)?;
+            }
+            Some((false, idle)) => {
+                self.output.write_u8(RDB_OPCODE_IDLE)?;
+                self.output.write_length(idle as u64)?;
+            }
+            None => {}
+        }
+        self.output.write_u8(value_type)?;
+        self.write_str(&header.key)
+    }
+
+    fn is_small<'b>(&self, len: usize, values: impl Iterator<Item = &'b [u8]>) -> bool {
+        self.options.compact && len <= LIST_PACK_MAX_ENTRIES && values.into_iter().all(|v| v.len() <= LIST_PACK_MAX_VALUE)
+    }
+
+    fn write_list(&mut self, header: &Header, values: &[Vec<u8>]) -> Result<()> {
+        self.write_header(header, RDB_TYPE_LIST)?;
+        self.output.write_length(values.len() as u64)?;
+        for value in values {
+            self.write_str(value)?;
+        }
+        Ok(())
+    }
+
+    fn write_set(&mut self, header: &Header, members: &[Vec<u8>]) -> Result<()> {
+        if self.options.compact && members.len() <= INT_SET_MAX_ENTRIES {
+            let ints: Option<Vec<i64>> = members.iter().map(|member| to_int(member)).collect();
+            if let Some(ints) = ints {
+                self.write_header(header, RDB_TYPE_SET_INTSET)?;
+                return self.write_str(&int_set(&ints));
+            }
+        }
+        if self.options.version >= 11 && self.is_small(members.len(), members.iter().map(|m| m.as_slice())) {
+            let mut list_pack = ListPack::default();
+            members.iter().for_each(|member| list_pack.push(member));
+            self.write_header(header, RDB_TYPE_SET_LISTPACK)?;
+            return self.write_str(&list_pack.into_bytes());
+        }
+        self.write_header(header, RDB_TYPE_SET)?;
+        self.output.write_length(members.len() as u64)?;
+        for member in members {
+            self.write_str(member)?;
+        }
+        Ok(())
+    }
+
+    fn write_sorted_set(&mut self, header: &Header, items: &[(Vec<u8>, f64)]) -> Result<()> {
+        if self.options.version >= 10 && self.is_small(items.len(), items.iter().map(|(m, _)| m.as_slice())) {
+            let mut list_pack = ListPack::default();
+            for (member, score) in items {
+                list_pack.push(member);
+                list_pack.push(score_to_string(*score).as_bytes());
+            }
+            self.write_header(header, RDB_TYPE_ZSET_LISTPACK)?;
+            return self.write_str(&list_pack.into_bytes());
+        }
+        self.write_header(header, RDB_TYPE_ZSET_2)?;
+        self.output.write_length(items.len() as u64)?;
+        for (member, score) in items {
+            self.write_str(member)?;
+            self.output.write_f64::<LittleEndian>(*score)?;
+        }
+        Ok(())
+    }
+
+    fn write_hash(&mut self, header: &Header, fields: &[(Vec<u8>, Vec<u8>, Option<i64>)]) -> Result<()> {
+        let min_expire = fields.iter().filter_map(|(_, _, expire)| *expire).min();
+        let min_expire = match min_expire {
+            Some(_) if self.options.version < 12 => {
+                warn!(
+                    "field expiration of hash {} requires rdb version 12, dropped",
+                    String::from_utf8_lossy(&header.key)
+                );
+                None
+            }
+            min_expire => min_expire,
+        };
+        let small = self.is_small(fields.len(), fields.iter().flat_map(|(n, v, _)| [n.as_slice(), v.as_slice()]));
+        match min_expire {
+            Some(min_expire) if small => {
+                let mut list_pack = ListPack::default();
+                for (name, value, expire) in fields {
+                    list_pack.push(name);
+                    list_pack.push(value);
+                    list_pack.push_int(expire.unwrap_or(0));
+                }
+                self.write_header(header, RDB_TYPE_HASH_LISTPACK_EX)?;
+                self.output.write_millis(min_expire)?;
+                self.write_str(&list_pack.into_bytes())
+            }
+            Some(min_expire) => {
+                self.write_header(header, RDB_TYPE_HASH_METADATA)?;
+                self.output.write_millis(min_expire)?;
+                self.output.write_length(fields.len() as u64)?;
+                for (name, value, expire) in fields {
+                    // 过期时间存储为相对于最小过期时间的偏移, 0表示未设置
+                    let ttl = expire.map(|expire| (expire - min_expire + 1) as u64).unwrap_or(0);
+                    self.output.write_length(ttl)?;
+                    self.write_str(name)?;
+                    self.write_str(value)?;
+                }
+                Ok(())
+            }
+            None if small && self.options.version >= 10 => {
+                let mut list_pack = ListPack::default();
+                for (name, value, _) in fields {
+                    list_pack.push(name);
+                    list_pack.push(value);
+                }
+                self.write_header(header, RDB_TYPE_HASH_LISTPACK)?;
+                self.write_str(&list_pack.into_bytes())
+            }
+            None => {
+                self.write_header(header, RDB_TYPE_HASH)?;
+                self.output.write_length(fields.len() as u64)?;
+                for (name, value, _) in fields {
+                    self.write_str(name)?;
+                    self.write_str(value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn write_stream(&mut self, header: &Header, stream: &Stream) -> Result<()> {
+        let version = self.options.version;
+        let value_type = match version {
+            9 => RDB_TYPE_STREAM_LISTPACKS,
+            10 => RDB_TYPE_STREAM_LISTPACKS_2,
+            _ => RDB_TYPE_STREAM_LISTPACKS_3,
+        };
+        self.write_header(header, value_type)?;
+        let entries: Vec<&Entry> = stream.entries.values().collect();
+        let nodes = entries.chunks(STREAM_NODE_MAX_ENTRIES);
+        self.output.write_length(nodes.len() as u64)?;
+        for node in nodes {
+            let mut master_id = Vec::with_capacity(16);
+            master_id.write_stream_id(&node[0].id)?;
+            self.write_str(&master_id)?;
+            self.write_str(&stream_node(node))?;
+        }
+
+        let length = entries.iter().filter(|entry| !entry.deleted).count();
+        let zero = ID { ms: 0, seq: 0 };
+        let last_id = stream.last_id.or(entries.last().map(|entry| entry.id)).unwrap_or(zero);
+        self.output.write_length(length as u64)?;
+        self.output.write_length(last_id.ms as u64)?;
+        self.output.write_length(last_id.seq as u64)?;
+        if value_type >= RDB_TYPE_STREAM_LISTPACKS_2 {
+            let first_id = stream
+                .first_id
+                .or(entries.iter().find(|entry| !entry.deleted).map(|entry| entry.id))
+                .unwrap_or(zero);
+            let max_deleted_id = stream.max_deleted_id.unwrap_or(zero);
+            self.output.write_length(first_id.ms as u64)?;
+            self.output.write_length(first_id.seq as u64)?;
+            self.output.write_length(max_deleted_id.ms as u64)?;
+            self.output.write_length(max_deleted_id.seq as u64)?;
+            self.output.write_length(stream.added_entries_count.unwrap_or(entries.len() as u64))?;
+        }
+
+        self.output.write_length(stream.groups.len() as u64)?;
+        for group in &stream.groups {
+            self.write_str(&group.name)?;
+            self.output.write_length(group.last_id.ms as u64)?;
+            self.output.write_length(group.last_id.seq as u64)?;
+            if value_type >= RDB_TYPE_STREAM_LISTPACKS_2 {
+                // 未知时与Redis一样记为-1
+                self.output.write_length(group.entries_read.unwrap_or(u64::MAX))?;
+            }
+            self.output.write_length(group.pending.len() as u64)?;
+            for pending in &group.pending {
+                self.output.write_stream_id(&pending.id)?;
+                self.output.write_millis(pending.delivery_time)?;
+                self.output.write_length(pending.delivery_count)?;
+            }
+            self.output.write_length(group.consumers.len() as u64)?;
+            for consumer in &group.consumers {
+                self.write_str(&consumer.name)?;
+                self.output.write_millis(consumer.seen_time)?;
+                if value_type >= RDB_TYPE_STREAM_LISTPACKS_3 {
+                    self.output.write_millis(consumer.active_time.unwrap_or(consumer.seen_time))?;
+                }
+                self.output.write_length(consumer.pending.len() as u64)?;
+                for id in &consumer.pending {
+                    self.output.write_stream_id(id)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RDBWriter<Vec<u8>> {
+    /// 将`object`编码为DUMP payload: 值的类型及值, 2个字节的RDB版本, 8个字节的crc64
+    pub(crate) fn dump(object: &OwnedObject, options: WriteOptions) -> Result<Vec<u8>> {
+        if !(MIN_RDB_VERSION..=MAX_RDB_VERSION).contains(&options.version) {
+            return Err(unsupported(format!("unsupported rdb version: {}", options.version)));
+        }
+        let version = options.version as u16;
+        let mut writer = RDBWriter {
+            output: Crc64Writer::new(Vec::new()),
+            options,
+            db: None,
+            pending: None,
+            error: None,
+            dump: true,
+        };
+        let header = Header::new(object.key(), &Meta { db: 0, expire: None, evict: None });
+        match object {
+            OwnedObject::String(_, value) => {
+                writer.write_header(&header, RDB_TYPE_STRING)?;
+                writer.write_str(value)?;
+            }
+            OwnedObject::List(_, values) => writer.write_list(&header, values)?,
+            OwnedObject::Set(_, members) => writer.write_set(&header, members)?,
+            OwnedObject::SortedSet(_, items) => {
+                let items: Vec<_> = items.iter().map(|item| (item.member.clone(), item.score)).collect();
+                writer.write_sorted_set(&header, &items)?;
+            }
+            OwnedObject::Hash(_, fields) => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|field| (field.name.clone(), field.value.clone(), field.expire))
+                    .collect();
+                writer.write_hash(&header, &fields)?;
+            }
+            OwnedObject::Module(_, module) => writer.write_module(&header, module.as_ref())?,
+            OwnedObject::Stream(_, stream) => writer.write_stream(&header, stream)?,
+        }
+        let mut output = writer.output;
+        output.write_u16::<LittleEndian>(version)?;
+        let crc = output.crc();
+        output.write_u64::<LittleEndian>(crc)?;
+        Ok(output.into_inner())
+    }
+}
+
+/// 构建stream的一个节点, 以第一条消息的字段作为master字段
+fn stream_node(entries: &[&Entry]) -> Vec<u8> {
+    let master = entries[0];
+    let master_fields: Vec<&Vec<u8>> = master.fields.keys().collect();
+    let deleted = entries.iter().filter(|entry| entry.deleted).count();
+
+    let mut list_pack = ListPack::default();
+    list_pack.push_int((entries.len() - deleted) as i64);
+    list_pack.push_int(deleted as i64);
+    list_pack.push_int(master_fields.len() as i64);
+    master_fields.iter().for_each(|field| list_pack.push(field));
+    list_pack.push_int(0);
+
+    for entry in entries {
+        let same_fields = entry.fields.keys().eq(master_fields.iter().copied());
+        let mut flags = 0;
+        if entry.deleted {
+            flags |= STREAM_ITEM_FLAG_DELETED;
+        }
+        if same_fields {
+            flags |= STREAM_ITEM_FLAG_SAMEFIELDS;
+        }
+        list_pack.push_int(flags);
+        list_pack.push_int(entry.id.ms - master.id.ms);
+        list_pack.push_int(entry.id.seq - master.id.seq);
+        let num_fields = entry.fields.len() as i64;
+        if same_fields {
+            entry.fields.values().for_each(|value| list_pack.push(value));
+            list_pack.push_int(num_fields + 3);
+        } else {
+            list_pack.push_int(num_fields);
+            for (field, value) in &entry.fields {
+                list_pack.push(field);
+                list_pack.push(value);
+            }
+            list_pack.push_int(num_fields * 2 + 4);
+        }
+    }
+    list_pack.into_bytes()
+}
+
+impl<W: Write> EventHandler for RDBWriter<W> {
+    fn handle(&mut self, event: Event) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Event::RDB(object) = event {
+            if let Err(err) = self.write(&object) {
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::any::Any;
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use crate::rdb::*;
+    use crate::writer::{RDBWriter, WriteOptions};
+    use crate::{Event, EventHandler};
+
+    #[derive(Default)]
+    struct Objects {
+        objects: Vec<String>,
+    }
+
+    impl EventHandler for Objects {
+        fn handle(&mut self, event: Event) {
+            match event {
+                Event::RDB(Object::BOR) | Event::RDB(Object::EOR) => {}
+                Event::RDB(object) => self.objects.push(format!("{:?}", object)),
+                Event::AOF(_) | Event::Sync(_) => {}
+            }
+        }
+    }
+
+    fn write(objects: &[Object], options: WriteOptions) -> Vec<u8> {
+        let mut writer = RDBWriter::new(Vec::new(), options).unwrap();
+        for object in objects {
+            writer.write(object).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn parse(rdb: &[u8]) -> Vec<String> {
+        let mut handler = Objects::default();
+        RDBParser::parse(&mut Cursor::new(rdb), &mut handler, Arc::new(AtomicBool::new(true))).unwrap();
+        handler.objects
+    }
+
+    fn meta(db: isize) -> Meta {
+        Meta { db, expire: None, evict: None }
+    }
+
+    fn stream(version: u8, meta: &Meta) -> Stream<'_> {
+        let id = |ms, seq| ID { ms, seq };
+        let entry = |id: ID, deleted, fields: &[(&str, &str)]| Entry {
+            id,
+            deleted,
+            fields: fields.iter().map(|(f, v)| (f.as_bytes().to_vec(), v.as_bytes().to_vec())).collect(),
+        };
+        let mut entries = BTreeMap::new();
+        for entry in [
+            entry(id(1000, 0), false, &[("a", "1"), ("b", "x")]),
+            entry(id(1000, 1), true, &[("a", "2"), ("b", "y")]),
+            entry(id(1001, 0), false, &[("c", "hello")]),
+        ] {
+            entries.insert(entry.id, entry);
+        }
+        let v2 = version >= 10;
+        Stream {
+            entries,
+            groups: vec![Group {
+                name: b"group".to_vec(),
+                last_id: id(1000, 0),
+                entries_read: if v2 { Some(1) } else { None },
+                pending: vec![PendingEntry { id: id(1000, 0), delivery_time: 1700000000000, delivery_count: 2 }],
+                consumers: vec![Consumer {
+                    name: b"consumer".to_vec(),
+                    seen_time: 1700000000001,
+                    active_time: if version >= 11 { Some(1700000000002) } else { None },
+                    pending: vec![id(1000, 0)],
+                }],
+            }],
+            last_id: Some(id(1001, 0)),
+            first_id: if v2 { Some(id(1000, 0)) } else { None },
+            max_deleted_id: if v2 { Some(id(1000, 1)) } else { None },
+            added_entries_count: if v2 { Some(3) } else { None },
+            meta,
+        }
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let options = [
+            WriteOptions { version: 9, compact: false, compress: false },
+            WriteOptions { version: 10, compact: true, compress: true },
+            WriteOptions { version: 11, compact: true, compress: false },
+            WriteOptions { version: 12, compact: false, compress: true },
+        ];
+        let db0 = meta(0);
+        let db2 = meta(2);
+        let expire = Meta {
+            db: 0,
+            expire: Some((ExpireType::Millisecond, 1700000000000)),
+            evict: Some((EvictType::LRU, 30)),
+        };
+        let freq = Meta { db: 2, expire: None, evict: Some((EvictType::LFU, 5)) };
+        let long = "redis-sync ".repeat(20);
+        let values = vec![b"a".to_vec(), b"-12".to_vec(), long.as_bytes().to_vec()];
+        let ints = vec![b"-70000".to_vec(), b"1".to_vec(), b"300".to_vec()];
+        let items = vec![
+            Item { member: b"m1".to_vec(), score: 1.5 },
+            Item { member: b"m2".to_vec(), score: -2.0 },
+        ];
+        let fields = vec![
+            Field { name: b"f".to_vec(), value: b"v".to_vec(), expire: None },
+            Field { name: b"n".to_vec(), value: b"4096".to_vec(), expire: None },
+        ];
+        for options in options {
+            let version = options.version;
+            let mut objects = vec![
+                Object::Aux(Aux { field: b"redis-ver", value: b"7.2.4" }),
+                Object::String(KeyValue { key: b"num", value: b"12345", meta: &db0 }),
+                Object::String(KeyValue { key: b"long", value: long.as_bytes(), meta: &expire }),
+                Object::List(List { key: b"list", values: &values, meta: &db0 }),
+                Object::Set(Set { key: b"ints", members: &ints, meta: &db0 }),
+                Object::Set(Set { key: b"set", members: &values[..2], meta: &db2 }),
+                Object::SortedSet(SortedSet { key: b"zset", items: &items, meta: &freq }),
+                Object::Hash(Hash { key: b"hash", fields: &fields, meta: &db2 }),
+                Object::Stream(b"stream".to_vec(), stream(version, &db2)),
+                Object::Module(b"module".to_vec(), Box::new(RawModule { name: "counter-1".to_string(), version: 3, data: vec![2, 42, 0] }), &db2),
+            ];
+            if version >= 10 {
+                objects.push(Object::Function(Function { code: b"#!lua name=lib\nreturn 1" }));
+            }
+            let expected: Vec<String> = objects.iter().map(|object| format!("{:?}", object)).collect();
+            let rdb = write(&objects, options);
+            assert_eq!(&rdb[..9], format!("REDIS{:04}", version).as_bytes());
+            assert_eq!(parse(&rdb), expected, "rdb version {}", version);
+        }
+    }
+
+    #[test]
+    fn test_write_batches() {
+        // 同一个key分批产生的事件合并为一条数据
+        let db0 = meta(0);
+        let values: Vec<Vec<u8>> = (0..100).map(|i| format!("v{}", i).into_bytes()).collect();
+        let objects = [
+            Object::List(List { key: b"list", values: &values[..50], meta: &db0 }),
+            Object::List(List { key: b"list", values: &values[50..], meta: &db0 }),
+            Object::List(List { key: b"other", values: &values[..1], meta: &db0 }),
+        ];
+        let rdb = write(&objects, WriteOptions::default());
+        let objects = parse(&rdb);
+        assert_eq!(objects.len(), 3);
+        assert!(objects[0].contains("key: [108, 105, 115, 116]") && objects[0].contains("[118, 54, 51]"));
+        assert!(objects[1].contains("[118, 54, 52]") && objects[1].contains("[118, 57, 57]"));
+        assert!(objects[2].contains("key: [111, 116, 104, 101, 114]"));
+    }
+
+    #[test]
+    fn test_write_hash_field_expire() {
+        let db0 = meta(0);
+        let fields = vec![
+            Field { name: b"a".to_vec(), value: b"1".to_vec(), expire: Some(1700000005000) },
+            Field { name: b"b".to_vec(), value: b"2".to_vec(), expire: None },
+            Field { name: b"c".to_vec(), value: b"3".to_vec(), expire: Some(1700000000000) },
+        ];
+        let object = Object::Hash(Hash { key: b"hash", fields: &fields, meta: &db0 });
+        for compact in [false, true] {
+            let rdb = write(std::slice::from_ref(&object), WriteOptions { version: 12, compact, compress: false });
+            assert_eq!(rdb[11], if compact { RDB_TYPE_HASH_LISTPACK_EX } else { RDB_TYPE_HASH_METADATA });
+            assert_eq!(parse(&rdb), vec![format!("{:?}", object)]);
+        }
+        // 低版本中不支持字段的过期时间
+        let rdb = write(std::slice::from_ref(&object), WriteOptions { version: 11, compact: false, compress: false });
+        let objects = parse(&rdb);
+        assert!(objects[0].contains("expire: None"));
+        assert!(!objects[0].contains("expire: Some"));
+    }
+
+    struct Typed;
+
+    impl Module for Typed {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn test_write_unsupported() {
+        assert!(RDBWriter::new(Vec::new(), WriteOptions { version: 8, compact: false, compress: false }).is_err());
+
+        let db0 = meta(0);
+        let mut writer = RDBWriter::new(Vec::new(), WriteOptions::default()).unwrap();
+        writer.handle(Event::RDB(Object::Module(b"typed".to_vec(), Box::new(Typed), &db0)));
+        writer.handle(Event::RDB(Object::String(KeyValue { key: b"k", value: b"v", meta: &db0 })));
+        assert!(writer.finish().is_err());
+
+        let mut writer = RDBWriter::new(Vec::new(), WriteOptions { version: 9, compact: false, compress: false }).unwrap();
+        assert!(writer.write(&Object::Function(Function { code: b"#!lua name=lib" })).is_err());
+    }
+}